@@ -0,0 +1,439 @@
+//! Repository configuration for FAI Protocol
+//!
+//! Settings live in `.fai/config.toml`. Every section is optional, so a
+//! missing file or missing keys fall back to the defaults below.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// File name of the repository configuration inside `.fai/`
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Repository configuration
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RepoConfig {
+    /// Garbage collection settings
+    #[serde(default)]
+    pub gc: GcConfig,
+    /// Branch settings
+    #[serde(default)]
+    pub branches: BranchConfig,
+    /// Named remotes, keyed by remote name
+    #[serde(default)]
+    pub remotes: BTreeMap<String, RemoteConfig>,
+    /// File transfer settings
+    #[serde(default)]
+    pub transfer: TransferConfig,
+    /// Network settings for `fai serve`
+    #[serde(default)]
+    pub network: NetworkConfig,
+    /// Rules for pushes received from peers
+    #[serde(default)]
+    pub receive: ReceiveConfig,
+}
+
+impl RepoConfig {
+    /// Load the configuration of the repository at `fai_path` (the `.fai` directory)
+    ///
+    /// # Returns
+    /// The parsed configuration, or the defaults if no config file exists
+    pub fn load(fai_path: &Path) -> Result<Self> {
+        let config_file = fai_path.join(CONFIG_FILE);
+        if !config_file.exists() {
+            return Ok(Self::default());
+        }
+
+        let config_str = std::fs::read_to_string(&config_file)?;
+        toml::from_str(&config_str)
+            .map_err(|e| anyhow::anyhow!("Invalid {}: {}", config_file.display(), e))
+    }
+
+    /// Write the configuration to `fai_path/config.toml`
+    pub fn save(&self, fai_path: &Path) -> Result<()> {
+        let config_str = toml::to_string_pretty(self)?;
+        std::fs::write(fai_path.join(CONFIG_FILE), config_str)?;
+        Ok(())
+    }
+}
+
+/// Garbage collection settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GcConfig {
+    /// How long reflog entries keep their commits (and objects) alive
+    #[serde(default = "default_reflog_expire_days")]
+    pub reflog_expire_days: u32,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self {
+            reflog_expire_days: default_reflog_expire_days(),
+        }
+    }
+}
+
+fn default_reflog_expire_days() -> u32 {
+    90
+}
+
+/// Branch settings
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BranchConfig {
+    /// Branches that cannot be deleted or renamed, even with `--force`.
+    /// A trailing `*` matches any suffix (e.g. "release/*").
+    #[serde(default)]
+    pub protected: Vec<String>,
+    /// Upstream of each local branch, keyed by branch name
+    #[serde(default)]
+    pub upstream: BTreeMap<String, Upstream>,
+}
+
+impl BranchConfig {
+    /// Whether a branch name matches one of the protection rules
+    pub fn is_protected(&self, name: &str) -> bool {
+        self.protected.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == pattern,
+        })
+    }
+}
+
+/// The remote branch a local branch is compared against and pulls from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Upstream {
+    /// Remote name
+    pub remote: String,
+    /// Branch name on the remote
+    pub branch: String,
+}
+
+impl Upstream {
+    /// Parse "<remote>/<branch>"
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.trim_start_matches("refs/remotes/").split_once('/') {
+            Some((remote, branch)) if !remote.is_empty() && !branch.is_empty() => Ok(Self {
+                remote: remote.to_string(),
+                branch: branch.to_string(),
+            }),
+            _ => Err(anyhow::anyhow!(
+                "Invalid upstream '{}', expected <remote>/<branch>",
+                name
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Upstream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.remote, self.branch)
+    }
+}
+
+/// A named peer to push to and pull from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteConfig {
+    /// Peer ID of the remote
+    pub peer_id: String,
+    /// Multiaddrs to dial the remote at, tried in order
+    #[serde(default)]
+    pub addresses: Vec<String>,
+}
+
+/// File transfer settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransferConfig {
+    /// Chunk requests kept outstanding to each peer during a download
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: usize,
+    /// Order in which chunks are requested from peers
+    #[serde(default)]
+    pub chunk_selection: ChunkSelection,
+    /// Upload limit for all peers together, in bytes per second or with a
+    /// K, M or G suffix (e.g. "10M"); unlimited if unset
+    #[serde(default, deserialize_with = "deserialize_rate", skip_serializing_if = "Option::is_none")]
+    pub upload_limit: Option<u64>,
+    /// Upload limit for each peer
+    #[serde(default, deserialize_with = "deserialize_rate", skip_serializing_if = "Option::is_none")]
+    pub peer_upload_limit: Option<u64>,
+    /// Download limit for all peers together
+    #[serde(default, deserialize_with = "deserialize_rate", skip_serializing_if = "Option::is_none")]
+    pub download_limit: Option<u64>,
+    /// Download limit for each peer
+    #[serde(default, deserialize_with = "deserialize_rate", skip_serializing_if = "Option::is_none")]
+    pub peer_download_limit: Option<u64>,
+    /// Inbound chunk requests served at once; the rest wait in a queue that
+    /// takes turns between peers
+    #[serde(default = "default_max_inbound_requests")]
+    pub max_inbound_requests: usize,
+}
+
+impl Default for TransferConfig {
+    fn default() -> Self {
+        Self {
+            max_in_flight: default_max_in_flight(),
+            chunk_selection: ChunkSelection::default(),
+            upload_limit: None,
+            peer_upload_limit: None,
+            download_limit: None,
+            peer_download_limit: None,
+            max_inbound_requests: default_max_inbound_requests(),
+        }
+    }
+}
+
+fn default_max_in_flight() -> usize {
+    4
+}
+
+fn default_max_inbound_requests() -> usize {
+    8
+}
+
+/// Parse a transfer rate in bytes per second, e.g. "65536", "512K", "10M" or "1G"
+///
+/// Suffixes are powers of 1024 and may be followed by "B" or "iB".
+pub fn parse_rate(rate: &str) -> Result<u64> {
+    let rate = rate.trim();
+    let digits = rate.find(|c: char| !c.is_ascii_digit()).unwrap_or(rate.len());
+    let (number, unit) = rate.split_at(digits);
+    let multiplier: u64 = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        _ => return Err(anyhow::anyhow!("Invalid rate '{}': unknown unit '{}'", rate, unit)),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid rate '{}': expected a number like 512K or 10M", rate))?;
+    match number.checked_mul(multiplier) {
+        Some(0) => Err(anyhow::anyhow!("Invalid rate '{}': must be greater than zero", rate)),
+        Some(bytes) => Ok(bytes),
+        None => Err(anyhow::anyhow!("Invalid rate '{}': too large", rate)),
+    }
+}
+
+/// Read a rate given either as bytes per second or as a string for [`parse_rate`]
+fn deserialize_rate<'de, D>(deserializer: D) -> std::result::Result<Option<u64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Rate {
+        Bytes(u64),
+        Text(String),
+    }
+
+    let rate = match Rate::deserialize(deserializer)? {
+        Rate::Bytes(bytes) => parse_rate(&bytes.to_string()),
+        Rate::Text(text) => parse_rate(&text),
+    };
+    rate.map(Some).map_err(serde::de::Error::custom)
+}
+
+/// How a download picks the next chunk to request from a peer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChunkSelection {
+    /// Chunks the fewest peers can serve first, so they are fetched while
+    /// those peers are still around
+    #[default]
+    RarestFirst,
+    /// Chunks in file order
+    RoundRobin,
+}
+
+/// Network settings for `fai serve`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    /// Multiaddrs to listen on, e.g. "/ip4/0.0.0.0/tcp/4001",
+    /// "/ip4/0.0.0.0/udp/4001/quic-v1" or "/ip6/::/tcp/4001"
+    #[serde(default = "default_listen")]
+    pub listen: Vec<String>,
+    /// Addresses other peers can reach this node at, when they differ from
+    /// the listen addresses (e.g. behind port forwarding); announced to
+    /// peers through identify
+    #[serde(default)]
+    pub external: Vec<String>,
+    /// Relays to also listen through, for nodes behind NAT, e.g.
+    /// "/ip4/203.0.113.7/tcp/4001/p2p/<relay peer id>" (see `fai relay`)
+    #[serde(default)]
+    pub relays: Vec<String>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            listen: default_listen(),
+            external: Vec::new(),
+            relays: Vec::new(),
+        }
+    }
+}
+
+/// Rules for pushes received from peers
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiveConfig {
+    /// Accept forced pushes that don't fast-forward a branch; off by
+    /// default, since any peer can push
+    #[serde(default)]
+    pub allow_forced_push: bool,
+}
+
+/// Ephemeral TCP and QUIC ports on all IPv4 interfaces
+pub fn default_listen() -> Vec<String> {
+    vec![
+        "/ip4/0.0.0.0/tcp/0".to_string(),
+        "/ip4/0.0.0.0/udp/0/quic-v1".to_string(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_missing_config_uses_defaults() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = RepoConfig::load(temp_dir.path()).unwrap();
+        assert_eq!(config, RepoConfig::default());
+        assert_eq!(config.gc.reflog_expire_days, 90);
+    }
+
+    #[test]
+    fn test_config_roundtrip() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join(CONFIG_FILE), "[gc]\nreflog_expire_days = 7\n").unwrap();
+
+        let mut config = RepoConfig::load(temp_dir.path()).unwrap();
+        assert_eq!(config.gc.reflog_expire_days, 7);
+
+        config.gc.reflog_expire_days = 30;
+        config.save(temp_dir.path()).unwrap();
+        assert_eq!(RepoConfig::load(temp_dir.path()).unwrap().gc.reflog_expire_days, 30);
+    }
+
+    #[test]
+    fn test_protected_branch_patterns() {
+        let config = BranchConfig {
+            protected: vec!["main".to_string(), "release/*".to_string()],
+            ..Default::default()
+        };
+        assert!(config.is_protected("main"));
+        assert!(config.is_protected("release/1.2"));
+        assert!(!config.is_protected("maintenance"));
+        assert!(!config.is_protected("feature/release"));
+    }
+
+    #[test]
+    fn test_upstream_config() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(
+            temp_dir.path().join(CONFIG_FILE),
+            "[branches.upstream.main]\nremote = \"origin\"\nbranch = \"release/1.0\"\n",
+        )
+        .unwrap();
+
+        let config = RepoConfig::load(temp_dir.path()).unwrap();
+        let upstream = &config.branches.upstream["main"];
+        assert_eq!(upstream.to_string(), "origin/release/1.0");
+        assert_eq!(Upstream::parse("origin/release/1.0").unwrap(), *upstream);
+        assert!(Upstream::parse("origin").is_err());
+        assert!(Upstream::parse("/main").is_err());
+    }
+
+    #[test]
+    fn test_remote_config() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(
+            temp_dir.path().join(CONFIG_FILE),
+            "[remotes.origin]\npeer_id = \"12D3KooW\"\naddresses = [\"/ip4/10.0.0.2/tcp/4001\"]\n\n[remotes.backup]\npeer_id = \"12D3KooX\"\n",
+        )
+        .unwrap();
+
+        let config = RepoConfig::load(temp_dir.path()).unwrap();
+        assert_eq!(config.remotes["origin"].addresses, vec!["/ip4/10.0.0.2/tcp/4001".to_string()]);
+        assert!(config.remotes["backup"].addresses.is_empty());
+
+        config.save(temp_dir.path()).unwrap();
+        assert_eq!(RepoConfig::load(temp_dir.path()).unwrap(), config);
+    }
+
+    #[test]
+    fn test_transfer_config() {
+        let temp_dir = TempDir::new().unwrap();
+        assert_eq!(RepoConfig::load(temp_dir.path()).unwrap().transfer.max_in_flight, 4);
+
+        std::fs::write(
+            temp_dir.path().join(CONFIG_FILE),
+            "[transfer]\nchunk_selection = \"round-robin\"\n",
+        )
+        .unwrap();
+        let config = RepoConfig::load(temp_dir.path()).unwrap();
+        assert_eq!(config.transfer.chunk_selection, ChunkSelection::RoundRobin);
+        assert_eq!(config.transfer.max_in_flight, 4);
+
+        std::fs::write(temp_dir.path().join(CONFIG_FILE), "[transfer]\nchunk_selection = \"random\"\n").unwrap();
+        assert!(RepoConfig::load(temp_dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_transfer_limits() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = RepoConfig::load(temp_dir.path()).unwrap();
+        assert_eq!(config.transfer.upload_limit, None);
+        assert_eq!(config.transfer.max_inbound_requests, 8);
+
+        std::fs::write(
+            temp_dir.path().join(CONFIG_FILE),
+            "[transfer]\nupload_limit = \"10M\"\npeer_upload_limit = 65536\ndownload_limit = \"512KiB\"\nmax_inbound_requests = 2\n",
+        )
+        .unwrap();
+        let config = RepoConfig::load(temp_dir.path()).unwrap();
+        assert_eq!(config.transfer.upload_limit, Some(10 * 1024 * 1024));
+        assert_eq!(config.transfer.peer_upload_limit, Some(65536));
+        assert_eq!(config.transfer.download_limit, Some(512 * 1024));
+        assert_eq!(config.transfer.peer_download_limit, None);
+        assert_eq!(config.transfer.max_inbound_requests, 2);
+
+        config.save(temp_dir.path()).unwrap();
+        assert_eq!(RepoConfig::load(temp_dir.path()).unwrap(), config);
+
+        for invalid in ["upload_limit = 0", "upload_limit = \"10X\"", "upload_limit = \"fast\""] {
+            std::fs::write(temp_dir.path().join(CONFIG_FILE), format!("[transfer]\n{}\n", invalid)).unwrap();
+            assert!(RepoConfig::load(temp_dir.path()).is_err(), "{} should be rejected", invalid);
+        }
+    }
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("1000").unwrap(), 1000);
+        assert_eq!(parse_rate("512k").unwrap(), 512 * 1024);
+        assert_eq!(parse_rate("2MB").unwrap(), 2 * 1024 * 1024);
+        assert_eq!(parse_rate("1GiB").unwrap(), 1024 * 1024 * 1024);
+        assert!(parse_rate("").is_err());
+        assert!(parse_rate("M").is_err());
+        assert!(parse_rate("1.5M").is_err());
+        assert!(parse_rate("0K").is_err());
+    }
+
+    #[test]
+    fn test_network_config() {
+        let temp_dir = TempDir::new().unwrap();
+        assert_eq!(RepoConfig::load(temp_dir.path()).unwrap().network.listen, default_listen());
+
+        std::fs::write(
+            temp_dir.path().join(CONFIG_FILE),
+            "[network]\nlisten = [\"/ip4/0.0.0.0/udp/4001/quic-v1\", \"/ip6/::/tcp/4001\"]\nexternal = [\"/dns4/models.example.com/tcp/4001\"]\n",
+        )
+        .unwrap();
+        let config = RepoConfig::load(temp_dir.path()).unwrap();
+        assert_eq!(config.network.listen, vec!["/ip4/0.0.0.0/udp/4001/quic-v1", "/ip6/::/tcp/4001"]);
+        assert_eq!(config.network.external, vec!["/dns4/models.example.com/tcp/4001"]);
+        assert!(config.network.relays.is_empty());
+    }
+}
@@ -0,0 +1,282 @@
+//! Long-running daemon with a local control API
+//!
+//! `fai daemon` keeps a single [`NetworkManager`] running: it serves chunks
+//! like `fai serve` and takes requests from other `fai` commands on a Unix
+//! socket at `.fai/daemon.sock`, so those commands skip starting their own
+//! swarm and waiting through discovery. Requests and responses are JSON,
+//! one per line.
+//!
+//! [`NetworkManager`]: crate::network::NetworkManager
+//!
+//! Requests are handled one at a time while the swarm keeps running; a
+//! request that finds the queue full is answered with an error. Status and
+//! stop requests are answered right away, even during a transfer.
+//!
+//! Only one daemon runs per repository: it holds an exclusive lock on
+//! `.fai/daemon.lock`, and the socket is only accessible to its owner.
+//!
+//! The daemon needs Unix domain sockets. On other platforms [`run`] fails and
+//! [`Client::connect`] finds no daemon, so commands run the network themselves.
+
+use crate::network::{PeerInfo, PushResponse, RefUpdate, TransferProgress};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+#[cfg(unix)]
+mod server;
+
+#[cfg(unix)]
+pub use server::run;
+
+/// Name of the control socket inside `.fai`
+pub const SOCKET_FILE: &str = "daemon.sock";
+
+/// Name of the lock file held by the running daemon inside `.fai`
+pub const LOCK_FILE: &str = "daemon.lock";
+
+/// Path of the control socket of the repository at `fai_path`
+pub fn socket_path(fai_path: &Path) -> PathBuf {
+    fai_path.join(SOCKET_FILE)
+}
+
+/// A request to the daemon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Request {
+    /// Report what the daemon is doing
+    Status,
+    /// List the peers the daemon knows
+    Peers,
+    /// Download a committed file into the object store
+    Fetch {
+        /// Remote name, peer ID or address
+        remote: String,
+        /// Hash of the file
+        hash: String,
+    },
+    /// Push branch updates to a remote
+    Push {
+        /// Remote name, peer ID or address
+        remote: String,
+        /// Branches to update on the remote
+        updates: Vec<RefUpdate>,
+    },
+    /// Shut the daemon down
+    Stop,
+}
+
+/// The daemon's answer to a request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Response {
+    /// Answer to [`Request::Status`]
+    Status(Status),
+    /// Answer to [`Request::Peers`]
+    Peers(Vec<Peer>),
+    /// Answer to [`Request::Fetch`]: number of objects downloaded
+    Fetched(usize),
+    /// Answer to [`Request::Push`]
+    Pushed(PushResponse),
+    /// Answer to [`Request::Stop`]
+    Stopping,
+    /// The request failed
+    Error(String),
+}
+
+/// What the daemon is doing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Status {
+    /// Peer ID of the daemon
+    pub peer_id: String,
+    /// When the daemon started
+    pub started_at: DateTime<Utc>,
+    /// Addresses the daemon listens on
+    pub listeners: Vec<String>,
+    /// Number of peers the daemon is connected to
+    pub connected_peers: usize,
+    /// Request being handled, e.g. "fetch 1a2b3c4d from origin"
+    pub current: Option<String>,
+    /// Download progress of the current request
+    pub progress: TransferProgress,
+    /// Requests waiting behind the current one
+    pub queued: usize,
+}
+
+/// A peer known to the daemon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Peer {
+    /// Peer ID
+    pub peer_id: String,
+    /// Addresses the peer was seen at
+    pub addresses: Vec<String>,
+    /// When the peer was last seen
+    pub last_seen: DateTime<Utc>,
+}
+
+impl From<&PeerInfo> for Peer {
+    fn from(peer: &PeerInfo) -> Self {
+        Self {
+            peer_id: peer.peer_id.to_string(),
+            addresses: peer.addresses.iter().map(ToString::to_string).collect(),
+            last_seen: peer.last_seen.into(),
+        }
+    }
+}
+
+impl Peer {
+    /// Convert back into the network layer's peer information
+    ///
+    /// # Returns
+    /// None if the peer ID is invalid; invalid addresses are skipped
+    pub fn into_peer_info(self) -> Option<PeerInfo> {
+        Some(PeerInfo {
+            peer_id: self.peer_id.parse().ok()?,
+            addresses: self
+                .addresses
+                .iter()
+                .filter_map(|addr| addr.parse().ok())
+                .collect(),
+            last_seen: self.last_seen.into(),
+        })
+    }
+}
+
+/// Connection to a running daemon
+pub struct Client {
+    #[cfg(unix)]
+    stream: BufReader<UnixStream>,
+    /// Never constructed: there is no daemon to connect to
+    #[cfg(not(unix))]
+    unsupported: std::convert::Infallible,
+}
+
+impl Client {
+    /// Connect to the daemon of the repository at `fai_path`
+    ///
+    /// # Returns
+    /// None if no daemon is running (a socket left behind by a daemon that
+    /// died counts as none)
+    #[cfg(unix)]
+    pub async fn connect(fai_path: &Path) -> Result<Option<Self>> {
+        let path = socket_path(fai_path);
+        if !path.exists() {
+            return Ok(None);
+        }
+        match UnixStream::connect(&path).await {
+            Ok(stream) => Ok(Some(Self {
+                stream: BufReader::new(stream),
+            })),
+            Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => Ok(None),
+            Err(e) => Err(anyhow::anyhow!(
+                "Failed to connect to the daemon at {}: {}",
+                path.display(),
+                e
+            )),
+        }
+    }
+
+    /// Connect to the daemon of the repository at `fai_path`
+    ///
+    /// # Returns
+    /// Always None: the daemon is not supported on this platform
+    #[cfg(not(unix))]
+    pub async fn connect(_fai_path: &Path) -> Result<Option<Self>> {
+        Ok(None)
+    }
+
+    /// Send a request and wait for the answer
+    ///
+    /// # Returns
+    /// The response; [`Response::Error`] is turned into an error
+    #[cfg(unix)]
+    pub async fn request(&mut self, request: &Request) -> Result<Response> {
+        let mut line = serde_json::to_string(request)?;
+        line.push('\n');
+        self.stream.get_mut().write_all(line.as_bytes()).await?;
+
+        let mut line = String::new();
+        if self.stream.read_line(&mut line).await? == 0 {
+            return Err(anyhow::anyhow!("The daemon closed the connection"));
+        }
+        match serde_json::from_str(&line)? {
+            Response::Error(e) => Err(anyhow::anyhow!(e)),
+            response => Ok(response),
+        }
+    }
+
+    /// Send a request and wait for the answer
+    #[cfg(not(unix))]
+    pub async fn request(&mut self, _request: &Request) -> Result<Response> {
+        match self.unsupported {}
+    }
+
+    /// Ask the daemon what it is doing
+    pub async fn status(&mut self) -> Result<Status> {
+        match self.request(&Request::Status).await? {
+            Response::Status(status) => Ok(status),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// List the peers the daemon knows
+    pub async fn peers(&mut self) -> Result<Vec<PeerInfo>> {
+        match self.request(&Request::Peers).await? {
+            Response::Peers(peers) => {
+                Ok(peers.into_iter().filter_map(Peer::into_peer_info).collect())
+            }
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Have the daemon download a committed file into the object store
+    ///
+    /// # Returns
+    /// Number of objects downloaded
+    pub async fn fetch(&mut self, remote: &str, hash: &str) -> Result<usize> {
+        let request = Request::Fetch {
+            remote: remote.to_string(),
+            hash: hash.to_string(),
+        };
+        match self.request(&request).await? {
+            Response::Fetched(downloaded) => Ok(downloaded),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Have the daemon push branch updates to a remote
+    pub async fn push(&mut self, remote: &str, updates: Vec<RefUpdate>) -> Result<PushResponse> {
+        let request = Request::Push {
+            remote: remote.to_string(),
+            updates,
+        };
+        match self.request(&request).await? {
+            Response::Pushed(response) => Ok(response),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Shut the daemon down
+    pub async fn stop(&mut self) -> Result<()> {
+        match self.request(&Request::Stop).await? {
+            Response::Stopping => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+}
+
+fn unexpected(response: Response) -> anyhow::Error {
+    anyhow::anyhow!("Unexpected response from the daemon: {:?}", response)
+}
+
+/// Run the daemon until it is stopped or interrupted
+///
+/// # Returns
+/// Always an error: the control socket needs Unix domain sockets
+#[cfg(not(unix))]
+pub async fn run(_network: crate::network::NetworkManager, _fai_path: &Path) -> Result<()> {
+    Err(anyhow::anyhow!("fai daemon is only supported on Unix"))
+}
@@ -0,0 +1,341 @@
+//! The daemon's request loop and control socket
+
+use super::{socket_path, Peer, Request, Response, Status, LOCK_FILE};
+use crate::config::RepoConfig;
+use crate::network::{remote::Remote, NetworkManager, TransferProgress};
+use anyhow::Result;
+use chrono::Utc;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot, Notify};
+
+/// Requests that may wait behind the one being handled
+const MAX_QUEUED_REQUESTS: usize = 16;
+
+/// A request waiting for the daemon, with the channel to answer it on
+type Job = (Request, oneshot::Sender<Response>);
+
+/// State shared between the request loop and the connections
+struct Shared {
+    /// Status reported to clients
+    status: Mutex<Status>,
+    /// Download counters of the network manager
+    progress: Arc<Mutex<TransferProgress>>,
+    /// Counters when the current request started
+    progress_base: Mutex<TransferProgress>,
+    /// Signalled when a client asks the daemon to stop
+    stop: Notify,
+}
+
+impl Shared {
+    /// Current status, with the progress of the current request
+    fn snapshot(&self) -> Status {
+        let mut status = self
+            .status
+            .lock()
+            .map(|status| status.clone())
+            .unwrap_or_else(|e| e.into_inner().clone());
+        if status.current.is_some() {
+            let now = read(&self.progress);
+            let base = read(&self.progress_base);
+            status.progress = TransferProgress {
+                objects_done: now.objects_done.saturating_sub(base.objects_done),
+                objects_total: now.objects_total.saturating_sub(base.objects_total),
+            };
+        }
+        status
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Status)) {
+        if let Ok(mut status) = self.status.lock() {
+            f(&mut status);
+        }
+    }
+}
+
+fn read(progress: &Mutex<TransferProgress>) -> TransferProgress {
+    progress
+        .lock()
+        .map(|progress| *progress)
+        .unwrap_or_default()
+}
+
+/// Removes the control socket when the daemon stops
+struct SocketGuard(PathBuf);
+
+impl Drop for SocketGuard {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Run the daemon until it is stopped or interrupted
+///
+/// # Arguments
+/// * `network` - Started network manager to keep running
+/// * `fai_path` - The repository's `.fai` directory
+pub async fn run(mut network: NetworkManager, fai_path: &Path) -> Result<()> {
+    // Held until the daemon exits; the OS releases it if the daemon dies
+    let lock = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(fai_path.join(LOCK_FILE))?;
+    if let Err(e) = lock.try_lock() {
+        return Err(match e {
+            std::fs::TryLockError::WouldBlock => {
+                anyhow::anyhow!("A daemon is already running for this repository")
+            }
+            std::fs::TryLockError::Error(e) => e.into(),
+        });
+    }
+
+    // With the lock held, a socket left behind belongs to a daemon that died
+    let path = socket_path(fai_path);
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    let _guard = SocketGuard(path.clone());
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    println!("Control socket: {}", path.display());
+
+    let shared = Arc::new(Shared {
+        status: Mutex::new(Status {
+            peer_id: network.local_peer_id().to_string(),
+            started_at: Utc::now(),
+            listeners: Vec::new(),
+            connected_peers: 0,
+            current: None,
+            progress: TransferProgress::default(),
+            queued: 0,
+        }),
+        progress: network.transfer_progress(),
+        progress_base: Mutex::default(),
+        stop: Notify::new(),
+    });
+
+    let (jobs, mut queue) = mpsc::channel::<Job>(MAX_QUEUED_REQUESTS);
+    let accept_shared = shared.clone();
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(serve_connection(
+                        stream,
+                        jobs.clone(),
+                        accept_shared.clone(),
+                    ));
+                }
+                Err(e) => eprintln!("Failed to accept control connection: {}", e),
+            }
+        }
+    });
+
+    loop {
+        shared.update(|status| {
+            status.listeners = network
+                .listeners()
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            status.connected_peers = network.connected_peers().len();
+        });
+
+        tokio::select! {
+            Some((request, reply)) = queue.recv() => {
+                if let Ok(mut base) = shared.progress_base.lock() {
+                    *base = read(&shared.progress);
+                }
+                shared.update(|status| {
+                    status.queued = status.queued.saturating_sub(1);
+                    status.current = Some(describe(&request));
+                });
+                let response = handle(&mut network, fai_path, &shared, request)
+                    .await
+                    .unwrap_or_else(|e| Response::Error(e.to_string()));
+                shared.update(|status| status.current = None);
+                let _ = reply.send(response);
+            }
+            // poll_events only waits inside swarm.next(), so dropping it
+            // when a request comes in loses no events
+            result = network.poll_events() => {
+                if let Err(e) = result {
+                    eprintln!("Error during event polling: {}", e);
+                }
+            }
+            _ = shared.stop.notified() => {
+                println!("Stopping on request");
+                return Ok(());
+            }
+            _ = tokio::signal::ctrl_c() => {
+                println!("Stopping");
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Read requests from a client until it disconnects
+async fn serve_connection(
+    stream: UnixStream,
+    jobs: mpsc::Sender<Job>,
+    shared: Arc<Shared>,
+) {
+    let mut stream = BufReader::new(stream);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match stream.read_line(&mut line).await {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+
+        let request = serde_json::from_str::<Request>(&line);
+        let response = match &request {
+            Ok(Request::Status) => Response::Status(shared.snapshot()),
+            // Answered before stopping, so the reply isn't lost when the daemon exits
+            Ok(Request::Stop) => Response::Stopping,
+            Ok(request) => {
+                let (reply, answer) = oneshot::channel();
+                shared.update(|status| status.queued += 1);
+                match jobs.try_send((request.clone(), reply)) {
+                    Ok(()) => answer
+                        .await
+                        .unwrap_or_else(|_| Response::Error("The daemon stopped".to_string())),
+                    Err(mpsc::error::TrySendError::Full(_)) => {
+                        shared.update(|status| status.queued = status.queued.saturating_sub(1));
+                        Response::Error("The daemon is busy, try again later".to_string())
+                    }
+                    Err(mpsc::error::TrySendError::Closed(_)) => return,
+                }
+            }
+            Err(e) => Response::Error(format!("Invalid request: {}", e)),
+        };
+
+        let Ok(mut reply) = serde_json::to_string(&response) else {
+            return;
+        };
+        reply.push('\n');
+        if stream.get_mut().write_all(reply.as_bytes()).await.is_err() {
+            return;
+        }
+        if let Ok(Request::Stop) = request {
+            shared.stop.notify_one();
+            return;
+        }
+    }
+}
+
+/// Carry out a queued request
+async fn handle(
+    network: &mut NetworkManager,
+    fai_path: &Path,
+    shared: &Shared,
+    request: Request,
+) -> Result<Response> {
+    match request {
+        Request::Status => Ok(Response::Status(shared.snapshot())),
+        Request::Peers => Ok(Response::Peers(
+            network.list_peers().iter().map(Peer::from).collect(),
+        )),
+        Request::Fetch { remote, hash } => {
+            let remote = Remote::resolve(&RepoConfig::load(fai_path)?, &remote)?;
+            network.add_remote(&remote);
+            let peers = network.download_peers(remote.peer_id);
+            Ok(Response::Fetched(network.fetch_file(&peers, &hash).await?))
+        }
+        Request::Push { remote, updates } => {
+            let remote = Remote::resolve(&RepoConfig::load(fai_path)?, &remote)?;
+            network.add_remote(&remote);
+            Ok(Response::Pushed(
+                network.push(remote.peer_id, &remote.name, updates).await?,
+            ))
+        }
+        Request::Stop => Ok(Response::Stopping),
+    }
+}
+
+/// Short description of a request for status reports
+fn describe(request: &Request) -> String {
+    match request {
+        Request::Status => "status".to_string(),
+        Request::Peers => "list peers".to_string(),
+        Request::Fetch { remote, hash } => {
+            format!("fetch {} from {}", &hash[..8.min(hash.len())], remote)
+        }
+        Request::Push { remote, updates } => {
+            let branches: Vec<&str> = updates
+                .iter()
+                .map(|update| update.branch.as_str())
+                .collect();
+            format!("push {} to {}", branches.join(", "), remote)
+        }
+        Request::Stop => "stop".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::Client;
+    use crate::storage::StorageManager;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_control_api() {
+        let temp_dir = TempDir::new().unwrap();
+        let fai_path = temp_dir.path().join(".fai");
+        std::fs::create_dir_all(&fai_path).unwrap();
+        let storage = Arc::new(StorageManager::new(fai_path.clone()).unwrap());
+        let database = crate::database::DatabaseManager::new(&fai_path.join("db.sqlite")).unwrap();
+        let mut network = NetworkManager::new(
+            storage,
+            database,
+            libp2p::identity::Keypair::generate_ed25519(),
+        )
+        .unwrap();
+        network.start().await.unwrap();
+        let peer_id = network.local_peer_id().to_string();
+        assert!(Client::connect(&fai_path).await.unwrap().is_none());
+
+        let daemon_path = fai_path.clone();
+        let daemon = tokio::spawn(async move { run(network, &daemon_path).await });
+        let mut client = loop {
+            if let Some(client) = Client::connect(&fai_path).await.unwrap() {
+                break client;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        };
+
+        let mode = std::fs::metadata(socket_path(&fai_path)).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600, "only the owner may use the socket");
+
+        let status = client.status().await.unwrap();
+        assert_eq!(status.peer_id, peer_id);
+        assert_eq!(status.current, None);
+        assert!(client.peers().await.is_ok());
+
+        // Failed requests come back as errors and leave the daemon running
+        assert!(client.fetch("nowhere", "abc").await.is_err());
+        assert!(run_again(&fai_path).await.is_err());
+        assert_eq!(client.status().await.unwrap().queued, 0);
+
+        client.stop().await.unwrap();
+        daemon.await.unwrap().unwrap();
+        assert!(!socket_path(&fai_path).exists());
+    }
+
+    /// Try to start a second daemon for the same repository
+    async fn run_again(fai_path: &Path) -> Result<()> {
+        let storage = Arc::new(StorageManager::new(fai_path.to_path_buf())?);
+        let database = crate::database::DatabaseManager::new(&fai_path.join("db.sqlite"))?;
+        let network = NetworkManager::new(
+            storage,
+            database,
+            libp2p::identity::Keypair::generate_ed25519(),
+        )?;
+        run(network, fai_path).await
+    }
+}
@@ -5,7 +5,51 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use rusqlite::{params, Connection};
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Component, Path};
+
+/// Placeholder head for branches that have no commits yet
+pub const EMPTY_COMMIT_HASH: &str = "0000000000000000000000000000000000000000";
+
+/// Failed dials after which an address that never worked is forgotten
+pub const MAX_ADDRESS_FAILURES: u32 = 5;
+
+/// Bad objects a peer may send before it is banned
+pub const MAX_BAD_OBJECTS: u32 = 3;
+
+/// How long a peer that sent bad objects is banned for, in seconds
+pub const PEER_BAN_SECS: i64 = 60 * 60;
+
+/// Check that a branch or tag name can be used in a ref
+///
+/// # Arguments
+/// * `kind` - "branch" or "tag", for the error message
+/// * `name` - Name to check
+pub(crate) fn check_ref_name(kind: &str, name: &str) -> Result<()> {
+    if name.is_empty()
+        || name == "HEAD"
+        || name.starts_with('-')
+        || name.contains("..")
+        || name.chars().any(|c| c.is_whitespace() || c == '~')
+    {
+        return Err(anyhow::anyhow!("Invalid {} name: '{}'", kind, name));
+    }
+    Ok(())
+}
+
+/// Check that a snapshot path stays inside the working tree
+///
+/// Snapshots can come from peers, so a path must be relative and may not
+/// contain `..` before it is joined onto the working directory.
+pub fn check_file_path(path: &str) -> Result<()> {
+    let relative = Path::new(path)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    if path.is_empty() || !relative {
+        return Err(anyhow::anyhow!("Unsafe file path: '{}'", path));
+    }
+    Ok(())
+}
 
 /// Represents a commit in the FAI repository
 #[derive(Debug, Clone)]
@@ -22,6 +66,151 @@ pub struct Commit {
     pub is_merge: bool,
 }
 
+/// A tag marking a commit, e.g. a released model version
+///
+/// Tags are stored as `refs/tags/<name>` refs. Annotated tags additionally
+/// carry a message and creation time; lightweight tags have neither.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Tag {
+    /// Tag name (without the `refs/tags/` prefix)
+    pub name: String,
+    /// Commit hash the tag points to
+    pub target: String,
+    /// Annotation message (None for lightweight tags)
+    pub message: Option<String>,
+    /// When the annotated tag was created (None for lightweight tags)
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl Tag {
+    /// Whether this is an annotated tag
+    pub fn is_annotated(&self) -> bool {
+        self.message.is_some()
+    }
+}
+
+/// A remote-tracking ref: where a branch of a remote was when we last fetched it
+///
+/// Stored as `refs/remotes/<remote>/<branch>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteRef {
+    /// Remote the branch was fetched from
+    pub remote: String,
+    /// Branch name on the remote
+    pub branch: String,
+    /// Commit hash the remote branch pointed to
+    pub target: String,
+}
+
+impl RemoteRef {
+    /// Short display name ("<remote>/<branch>")
+    pub fn name(&self) -> String {
+        format!("{}/{}", self.remote, self.branch)
+    }
+}
+
+/// An address book entry: one known address of a peer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerAddress {
+    /// Peer ID of the peer
+    pub peer_id: String,
+    /// Multiaddr the peer was seen at (without a `/p2p` suffix)
+    pub address: String,
+    /// When the address was last announced or dialed successfully
+    pub last_seen: DateTime<Utc>,
+    /// Successful dials to this address
+    pub successes: u32,
+    /// Failed dials to this address
+    pub failures: u32,
+}
+
+/// How a peer has behaved when serving data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerReputation {
+    /// Peer ID of the peer
+    pub peer_id: String,
+    /// Objects received from the peer that did not match their hash
+    pub bad_objects: u32,
+    /// End of the peer's current or last ban, if it was ever banned
+    pub banned_until: Option<DateTime<Utc>>,
+}
+
+impl PeerReputation {
+    /// Whether the peer is banned right now
+    pub fn is_banned(&self) -> bool {
+        self.banned_until.is_some_and(|until| until > Utc::now())
+    }
+}
+
+/// Journal of a pull or clone that has not finished downloading
+///
+/// Written before anything is fetched and cleared once every file is in
+/// place, so an interrupted transfer can be resumed or discarded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transfer {
+    /// Remote the commits come from
+    pub remote: String,
+    /// Peer ID of the remote
+    pub peer_id: String,
+    /// Commit being fetched (None until the peer has sent commits)
+    pub target: Option<String>,
+    /// When the transfer was first started
+    pub started_at: DateTime<Utc>,
+    /// Commits received from the peer
+    pub commits: Vec<String>,
+    /// Files those commits need
+    pub files: Vec<String>,
+    /// Objects downloaded and verified so far
+    pub verified_objects: Vec<String>,
+    /// The remote's tracking refs before the transfer, as (branch, commit)
+    pub previous_refs: Vec<(String, String)>,
+}
+
+/// A recorded update of a ref (branch head or HEAD)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+    /// Ref that moved (e.g., "refs/heads/main" or "HEAD")
+    pub ref_name: String,
+    /// Previous commit hash (None if the ref was created)
+    pub old_hash: Option<String>,
+    /// New commit hash (None if the ref was deleted)
+    pub new_hash: Option<String>,
+    /// What caused the update (e.g., "commit: Add model")
+    pub operation: String,
+    /// User that performed the update
+    pub user: String,
+    /// When the update happened
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A saved set of staged and working tree changes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StashEntry {
+    /// Stash identifier
+    pub id: i64,
+    /// Branch the changes were stashed from
+    pub branch: String,
+    /// Branch head at the time of stashing (None if the branch had no commits)
+    pub base_commit: Option<String>,
+    /// Description of the stash
+    pub message: String,
+    /// When the stash was created
+    pub created_at: DateTime<Utc>,
+}
+
+/// A file saved in a stash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StashFile {
+    /// File path relative to the repository root
+    pub path: String,
+    /// Hash of the stored file content
+    pub hash: String,
+    /// File size in bytes
+    pub size: u64,
+    /// Whether the file was staged (true) or only modified in the working tree (false)
+    pub staged: bool,
+}
+
 /// Database manager for FAI Protocol
 pub struct DatabaseManager {
     /// SQLite database connection
@@ -38,6 +227,8 @@ impl DatabaseManager {
     /// A new DatabaseManager instance
     pub fn new(db_path: &Path) -> Result<Self> {
         let conn = Connection::open(db_path)?;
+        // Another process (e.g. `fai daemon`) may be writing at the same time
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
         let db = Self { conn };
         db.init_schema()?;
         Ok(db)
@@ -48,6 +239,17 @@ impl DatabaseManager {
         &self.conn
     }
 
+    /// Run `f` inside a transaction
+    ///
+    /// Everything `f` writes is committed together, or rolled back if it
+    /// returns an error.
+    pub fn transaction<T>(&self, f: impl FnOnce(&Self) -> Result<T>) -> Result<T> {
+        let tx = self.conn.unchecked_transaction()?;
+        let value = f(self)?;
+        tx.commit()?;
+        Ok(value)
+    }
+
     /// Initialize the database schema
     ///
     /// Creates the necessary tables if they don't exist
@@ -120,17 +322,121 @@ impl DatabaseManager {
             [],
         )?;
 
-        // Initialize default branch if not exists
+        // Create tag annotations table (the tag refs themselves live in branch_refs)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS tag_annotations (
+                ref_name TEXT PRIMARY KEY,
+                message TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        // Create reflog table recording every ref update
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS reflog (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                ref_name TEXT NOT NULL,
+                old_hash TEXT,
+                new_hash TEXT,
+                operation TEXT NOT NULL,
+                user TEXT NOT NULL,
+                timestamp INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        // Create stash tables for saved staging/working tree changes
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS stashes (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                branch TEXT NOT NULL,
+                base_commit TEXT,
+                message TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS stash_files (
+                stash_id INTEGER NOT NULL,
+                file_path TEXT NOT NULL,
+                file_hash TEXT NOT NULL,
+                file_size INTEGER NOT NULL,
+                staged BOOLEAN NOT NULL,
+                PRIMARY KEY (stash_id, file_path, staged),
+                FOREIGN KEY (stash_id) REFERENCES stashes(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        // Create address book of peers seen through discovery, identify and dials
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS peer_addresses (
+                peer_id TEXT NOT NULL,
+                address TEXT NOT NULL,
+                last_seen INTEGER NOT NULL,
+                successes INTEGER NOT NULL DEFAULT 0,
+                failures INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (peer_id, address)
+            )",
+            [],
+        )?;
+
+        // Create record of peers that sent data failing verification
         self.conn.execute(
-            "INSERT OR IGNORE INTO branches (name, head_commit) VALUES ('main', '0000000000000000000000000000000000000000')",
+            "CREATE TABLE IF NOT EXISTS peer_reputation (
+                peer_id TEXT PRIMARY KEY,
+                bad_objects INTEGER NOT NULL DEFAULT 0,
+                banned_until INTEGER
+            )",
             [],
         )?;
 
+        // Create journal of the pull or clone in progress (at most one)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS transfer_journal (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                remote TEXT NOT NULL,
+                peer_id TEXT NOT NULL,
+                target TEXT,
+                started_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+        for table in ["transfer_commits", "transfer_files", "transfer_objects"] {
+            self.conn.execute(
+                &format!("CREATE TABLE IF NOT EXISTS {} (hash TEXT PRIMARY KEY)", table),
+                [],
+            )?;
+        }
         self.conn.execute(
-            "INSERT OR IGNORE INTO branch_refs (ref_name, target) VALUES ('HEAD', 'refs/heads/main')",
+            "CREATE TABLE IF NOT EXISTS transfer_refs (
+                branch TEXT PRIMARY KEY,
+                target TEXT NOT NULL
+            )",
             [],
         )?;
 
+        // Initialize the default branch when the database is first created.
+        // Once HEAD exists, `main` may have been renamed or deleted.
+        let has_head: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM branch_refs WHERE ref_name = 'HEAD')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_head {
+            self.conn.execute(
+                "INSERT OR IGNORE INTO branches (name, head_commit) VALUES ('main', ?1)",
+                [EMPTY_COMMIT_HASH],
+            )?;
+            self.conn.execute(
+                "INSERT INTO branch_refs (ref_name, target) VALUES ('HEAD', 'refs/heads/main')",
+                [],
+            )?;
+        }
+
         Ok(())
     }
 
@@ -299,7 +605,7 @@ impl DatabaseManager {
                 "SELECT parent_hash FROM commit_parents WHERE commit_hash = ?1 ORDER BY parent_hash"
             )?;
             let parent_rows = parent_stmt.query_map([hash], |row| {
-                Ok(row.get::<_, String>(0)?)
+                row.get::<_, String>(0)
             })?;
 
             let mut parents = Vec::new();
@@ -341,6 +647,36 @@ impl DatabaseManager {
         Ok(files)
     }
 
+    /// Build the complete file set for a new commit
+    ///
+    /// Starts from the files of `base_commit` and overlays the staging area on
+    /// top, so every commit records the full repository state rather than only
+    /// the files that were staged for it.
+    ///
+    /// # Arguments
+    /// * `base_commit` - Commit to inherit files from (None for a root commit)
+    ///
+    /// # Returns
+    /// Vector of tuples containing (file_path, file_hash, file_size), ordered by path
+    pub fn snapshot_with_staged(&self, base_commit: Option<&str>) -> Result<Vec<(String, String, u64)>> {
+        let mut snapshot: BTreeMap<String, (String, u64)> = BTreeMap::new();
+
+        if let Some(base) = base_commit {
+            for (path, hash, size) in self.get_commit_files(base)? {
+                snapshot.insert(path, (hash, size));
+            }
+        }
+
+        for (path, hash, size) in self.get_staged_files()? {
+            snapshot.insert(path, (hash, size));
+        }
+
+        Ok(snapshot
+            .into_iter()
+            .map(|(path, (hash, size))| (path, hash, size))
+            .collect())
+    }
+
     /// Get the latest commit (HEAD)
     ///
     /// # Returns
@@ -383,7 +719,7 @@ impl DatabaseManager {
                 "SELECT parent_hash FROM commit_parents WHERE commit_hash = ?1 ORDER BY parent_hash"
             )?;
             let parent_rows = parent_stmt.query_map([&commit_hash], |parent_row| {
-                Ok(parent_row.get::<_, String>(0)?)
+                parent_row.get::<_, String>(0)
             })?;
 
             let mut parents = Vec::new();
@@ -408,6 +744,333 @@ impl DatabaseManager {
         Ok(commits)
     }
 
+    // === COMMIT GRAPH METHODS ===
+
+    /// Recursive CTEs collecting every ancestor of ?1 (anc_a) and ?2 (anc_b),
+    /// each including the starting commit itself
+    const ANCESTOR_CTES: &'static str = "WITH RECURSIVE
+        anc_a(hash) AS (
+            SELECT ?1
+            UNION
+            SELECT cp.parent_hash FROM commit_parents cp JOIN anc_a ON cp.commit_hash = anc_a.hash
+        ),
+        anc_b(hash) AS (
+            SELECT ?2
+            UNION
+            SELECT cp.parent_hash FROM commit_parents cp JOIN anc_b ON cp.commit_hash = anc_b.hash
+        )";
+
+    /// Find all lowest common ancestors of two commits
+    ///
+    /// A common ancestor is "lowest" when none of its children is itself a
+    /// common ancestor. Criss-cross histories can have several of them.
+    ///
+    /// # Arguments
+    /// * `a` - First commit hash
+    /// * `b` - Second commit hash
+    ///
+    /// # Returns
+    /// The merge bases, newest first (empty if the histories are unrelated)
+    pub fn merge_bases(&self, a: &str, b: &str) -> Result<Vec<String>> {
+        let query = format!(
+            "{},
+            common(hash) AS (SELECT hash FROM anc_a INTERSECT SELECT hash FROM anc_b)
+            SELECT common.hash FROM common
+            LEFT JOIN commits ON commits.hash = common.hash
+            WHERE NOT EXISTS (
+                SELECT 1 FROM commit_parents cp
+                JOIN common child ON cp.commit_hash = child.hash
+                WHERE cp.parent_hash = common.hash
+            )
+            ORDER BY commits.timestamp DESC, common.hash",
+            Self::ANCESTOR_CTES
+        );
+
+        let mut stmt = self.conn.prepare(&query)?;
+        let rows = stmt.query_map(params![a, b], |row| row.get(0))?;
+
+        let mut bases = Vec::new();
+        for row in rows {
+            bases.push(row?);
+        }
+
+        Ok(bases)
+    }
+
+    /// Find the merge base (nearest common ancestor) of two commits
+    ///
+    /// # Arguments
+    /// * `a` - First commit hash
+    /// * `b` - Second commit hash
+    ///
+    /// # Returns
+    /// The newest lowest common ancestor, or None if the histories are unrelated
+    pub fn find_merge_base(&self, a: &str, b: &str) -> Result<Option<String>> {
+        Ok(self.merge_bases(a, b)?.into_iter().next())
+    }
+
+    /// Count how far two commits have diverged
+    ///
+    /// # Arguments
+    /// * `a` - Commit hash being compared (e.g. the local branch head)
+    /// * `b` - Commit hash compared against (e.g. the upstream head)
+    ///
+    /// # Returns
+    /// Tuple of (ahead, behind): commits reachable only from `a`, and commits reachable only from `b`
+    pub fn ahead_behind(&self, a: &str, b: &str) -> Result<(usize, usize)> {
+        let query = format!(
+            "{}
+            SELECT
+                (SELECT COUNT(*) FROM (SELECT hash FROM anc_a EXCEPT SELECT hash FROM anc_b)),
+                (SELECT COUNT(*) FROM (SELECT hash FROM anc_b EXCEPT SELECT hash FROM anc_a))",
+            Self::ANCESTOR_CTES
+        );
+
+        let (ahead, behind): (i64, i64) = self
+            .conn
+            .query_row(&query, params![a, b], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        Ok((ahead as usize, behind as usize))
+    }
+
+    /// Check whether one commit is an ancestor of (or equal to) another
+    ///
+    /// # Arguments
+    /// * `ancestor` - Candidate ancestor commit hash
+    /// * `descendant` - Commit hash whose history is searched
+    pub fn is_ancestor(&self, ancestor: &str, descendant: &str) -> Result<bool> {
+        let query = format!(
+            "{}
+            SELECT EXISTS (SELECT 1 FROM anc_a WHERE hash = ?2)",
+            Self::ANCESTOR_CTES
+        );
+
+        let found: bool = self
+            .conn
+            .query_row(&query, params![descendant, ancestor], |row| row.get(0))?;
+
+        Ok(found)
+    }
+
+    /// Resolve a revision expression to a full commit hash
+    ///
+    /// Accepts `HEAD`, a branch or tag name, a remote-tracking ref
+    /// (`<remote>/<branch>`), a full commit hash or a unique hash
+    /// prefix (at least 4 characters), optionally followed by `~N` to walk N
+    /// commits back along the first recorded parent. `<ref>@{N}` selects the
+    /// value a ref had N updates ago according to the reflog.
+    ///
+    /// # Arguments
+    /// * `rev` - Revision expression (e.g. "HEAD~1", "main", "a1b2c3d4")
+    ///
+    /// # Returns
+    /// The resolved commit hash
+    pub fn resolve_revision(&self, rev: &str) -> Result<String> {
+        if let Some((ref_name, n)) = rev.strip_suffix('}').and_then(|r| r.split_once("@{")) {
+            return self.resolve_reflog_revision(rev, ref_name, n);
+        }
+
+        let (base, steps) = match rev.split_once('~') {
+            Some((base, "")) => (base, 1),
+            Some((base, n)) => (
+                base,
+                n.parse::<usize>()
+                    .map_err(|_| anyhow::anyhow!("Invalid revision: {}", rev))?,
+            ),
+            None => (rev, 0),
+        };
+
+        let mut hash = if base == "HEAD" {
+            let branch = self.get_current_branch()?;
+            self.get_branch_head(&branch)?
+                .filter(|hash| hash != EMPTY_COMMIT_HASH)
+                .ok_or_else(|| anyhow::anyhow!("Branch '{}' has no commits", branch))?
+        } else if let Some(head) = self.get_branch_head(base)? {
+            if head == EMPTY_COMMIT_HASH {
+                return Err(anyhow::anyhow!("Branch '{}' has no commits", base));
+            }
+            head
+        } else if let Some(tag) = self.get_tag(base)? {
+            tag.target
+        } else if let Some(target) = self.resolve_remote_ref(base)? {
+            target
+        } else {
+            self.resolve_commit_prefix(base)?
+        };
+
+        for _ in 0..steps {
+            let commit = self
+                .get_commit(&hash)?
+                .ok_or_else(|| anyhow::anyhow!("Commit not found: {}", hash))?;
+            hash = commit
+                .parents
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Revision '{}' goes past the root commit", rev))?;
+        }
+
+        Ok(hash)
+    }
+
+    /// Resolve `<ref>@{N}` to the value the ref had N updates ago
+    fn resolve_reflog_revision(&self, rev: &str, ref_name: &str, n: &str) -> Result<String> {
+        let n: usize = n
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid revision: {}", rev))?;
+        let ref_name = if ref_name.is_empty() { "HEAD" } else { ref_name };
+
+        self.reflog(Some(ref_name), None)?
+            .into_iter()
+            .nth(n)
+            .and_then(|entry| entry.new_hash)
+            .ok_or_else(|| anyhow::anyhow!("Reflog for '{}' has no entry {}", ref_name, n))
+    }
+
+    /// Resolve "<remote>/<branch>" or "refs/remotes/<remote>/<branch>"
+    fn resolve_remote_ref(&self, name: &str) -> Result<Option<String>> {
+        match name.trim_start_matches("refs/remotes/").split_once('/') {
+            Some((remote, branch)) => self.get_remote_ref(remote, branch),
+            None => Ok(None),
+        }
+    }
+
+    /// Expand an abbreviated commit hash to the unique full hash
+    fn resolve_commit_prefix(&self, prefix: &str) -> Result<String> {
+        if self.get_commit(prefix)?.is_some() {
+            return Ok(prefix.to_string());
+        }
+        if prefix.len() < 4 || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(anyhow::anyhow!("Unknown revision: {}", prefix));
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare("SELECT hash FROM commits WHERE substr(hash, 1, ?2) = ?1 LIMIT 2")?;
+        let rows = stmt.query_map(params![prefix, prefix.len() as i64], |row| row.get(0))?;
+
+        let mut matches: Vec<String> = Vec::new();
+        for row in rows {
+            matches.push(row?);
+        }
+
+        match matches.len() {
+            0 => Err(anyhow::anyhow!("Unknown revision: {}", prefix)),
+            1 => Ok(matches.remove(0)),
+            _ => Err(anyhow::anyhow!("Ambiguous commit prefix: {}", prefix)),
+        }
+    }
+
+    /// Collect every commit reachable from the given roots
+    ///
+    /// # Arguments
+    /// * `roots` - Commit hashes to start from (unknown hashes are ignored)
+    pub fn reachable_commits(&self, roots: &[String]) -> Result<std::collections::HashSet<String>> {
+        let mut stmt = self.conn.prepare(
+            "WITH RECURSIVE anc(hash) AS (
+                SELECT hash FROM commits WHERE hash = ?1
+                UNION
+                SELECT cp.parent_hash FROM commit_parents cp JOIN anc ON cp.commit_hash = anc.hash
+            )
+            SELECT hash FROM anc",
+        )?;
+
+        let mut reachable = std::collections::HashSet::new();
+        for root in roots {
+            if reachable.contains(root) {
+                continue;
+            }
+            let rows = stmt.query_map([root], |row| row.get::<_, String>(0))?;
+            for row in rows {
+                reachable.insert(row?);
+            }
+        }
+
+        Ok(reachable)
+    }
+
+    /// Commits reachable from `wants` but not from `haves`, newest first
+    ///
+    /// # Arguments
+    /// * `wants` - Commits the other side asked for
+    /// * `haves` - Commits the other side already has (unknown hashes are ignored)
+    pub fn commits_between(&self, wants: &[String], haves: &[String]) -> Result<Vec<Commit>> {
+        let common = self.reachable_commits(haves)?;
+        let mut commits = Vec::new();
+        for hash in self.reachable_commits(wants)? {
+            if common.contains(&hash) {
+                continue;
+            }
+            if let Some(commit) = self.get_commit(&hash)? {
+                commits.push(commit);
+            }
+        }
+        commits.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then_with(|| a.hash.cmp(&b.hash)));
+        Ok(commits)
+    }
+
+    /// Commits that branch heads and tags point at
+    ///
+    /// # Arguments
+    /// * `include_remote` - Also include remote-tracking refs
+    pub fn ref_tips(&self, include_remote: bool) -> Result<Vec<String>> {
+        let mut tips: Vec<String> = self
+            .list_branches()?
+            .into_iter()
+            .map(|(_, head)| head)
+            .filter(|head| head != EMPTY_COMMIT_HASH)
+            .collect();
+        tips.extend(self.list_tags()?.into_iter().map(|tag| tag.target));
+        if include_remote {
+            tips.extend(self.list_remote_refs(None)?.into_iter().map(|remote_ref| remote_ref.target));
+        }
+
+        let mut seen = std::collections::HashSet::new();
+        tips.retain(|tip| seen.insert(tip.clone()));
+        Ok(tips)
+    }
+
+    /// Commits to advertise as "have" when fetching from a peer
+    ///
+    /// Every ref tip plus ancestors at exponentially growing distances
+    /// (1, 2, 4, ...), so a peer that doesn't know our newest commits still
+    /// finds a recent common ancestor in a single round trip.
+    pub fn fetch_haves(&self) -> Result<Vec<String>> {
+        let mut haves = Vec::new();
+        let mut seen = std::collections::HashSet::new();
+
+        for tip in self.ref_tips(true)? {
+            let mut current = Some(tip);
+            let mut distance = 0usize;
+            let mut next_mark = 0usize;
+            while let Some(hash) = current {
+                if distance == next_mark {
+                    if !seen.insert(hash.clone()) {
+                        // Everything older has been covered from another tip
+                        break;
+                    }
+                    haves.push(hash.clone());
+                    next_mark = (next_mark * 2).max(1);
+                }
+                current = self.get_commit(&hash)?.and_then(|commit| commit.parents.into_iter().next());
+                distance += 1;
+            }
+        }
+
+        Ok(haves)
+    }
+
+    /// Remove a commit together with its parent links and file list
+    pub fn delete_commit(&self, hash: &str) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM commit_files WHERE commit_hash = ?1", [hash])?;
+        self.conn.execute(
+            "DELETE FROM commit_parents WHERE commit_hash = ?1 OR parent_hash = ?1",
+            [hash],
+        )?;
+        self.conn.execute("DELETE FROM commits WHERE hash = ?1", [hash])?;
+        Ok(())
+    }
+
     // === BRANCH MANAGEMENT METHODS ===
 
     /// Create a new branch
@@ -416,11 +1079,20 @@ impl DatabaseManager {
     /// * `name` - Branch name
     /// * `commit_hash` - Commit hash to point branch to
     pub fn create_branch(&self, name: &str, commit_hash: &str) -> Result<()> {
+        check_ref_name("branch", name)?;
+        if self.branch_exists(name)? {
+            return Err(anyhow::anyhow!("Branch '{}' already exists", name));
+        }
         self.conn.execute(
-            "INSERT OR REPLACE INTO branches (name, head_commit) VALUES (?1, ?2)",
+            "INSERT INTO branches (name, head_commit) VALUES (?1, ?2)",
             params![name, commit_hash],
         )?;
-        Ok(())
+        self.record_branch_update(
+            name,
+            None,
+            Some(commit_hash),
+            &format!("branch: Created from {}", &commit_hash[..8.min(commit_hash.len())]),
+        )
     }
 
     /// Get the head commit of a branch
@@ -450,6 +1122,18 @@ impl DatabaseManager {
     /// * `name` - Branch name
     /// * `commit_hash` - New commit hash
     pub fn update_branch_head(&self, name: &str, commit_hash: &str) -> Result<()> {
+        self.update_branch_head_with_reason(name, commit_hash, "update")
+    }
+
+    /// Update the head commit of a branch, recording why in the reflog
+    ///
+    /// # Arguments
+    /// * `name` - Branch name
+    /// * `commit_hash` - New commit hash
+    /// * `operation` - Reflog description (e.g., "commit: Add model")
+    pub fn update_branch_head_with_reason(&self, name: &str, commit_hash: &str, operation: &str) -> Result<()> {
+        let old_hash = self.get_branch_head(name)?;
+
         let rows_affected = self.conn.execute(
             "UPDATE branches SET head_commit = ?1 WHERE name = ?2",
             params![commit_hash, name],
@@ -459,7 +1143,7 @@ impl DatabaseManager {
             return Err(anyhow::anyhow!("Branch '{}' does not exist", name));
         }
 
-        Ok(())
+        self.record_branch_update(name, old_hash.as_deref(), Some(commit_hash), operation)
     }
 
     /// List all branches
@@ -495,6 +1179,8 @@ impl DatabaseManager {
             }
         }
 
+        let old_hash = self.get_branch_head(name)?;
+
         let rows_affected = self.conn.execute(
             "DELETE FROM branches WHERE name = ?1",
             [name],
@@ -504,46 +1190,322 @@ impl DatabaseManager {
             return Err(anyhow::anyhow!("Branch '{}' does not exist", name));
         }
 
-        Ok(())
+        // Keep the deleted head in the reflog so it can be recovered
+        self.record_branch_update(name, old_hash.as_deref(), None, "branch: deleted")
     }
 
-    /// Get current branch reference
+    // === TAG METHODS ===
+
+    /// Create a tag pointing to a commit
     ///
-    /// # Returns
-    /// Current branch reference (e.g., "refs/heads/main")
-    pub fn get_current_ref(&self) -> Result<String> {
-        let ref_target = self.conn.query_row(
-            "SELECT target FROM branch_refs WHERE ref_name = 'HEAD'",
-            [],
-            |row| row.get(0),
-        )?;
-        Ok(ref_target)
+    /// # Arguments
+    /// * `name` - Tag name (e.g., "v1.2")
+    /// * `commit_hash` - Commit to tag
+    /// * `message` - Annotation message; None creates a lightweight tag
+    pub fn create_tag(&self, name: &str, commit_hash: &str, message: Option<&str>) -> Result<Tag> {
+        let tag = Tag {
+            name: name.to_string(),
+            target: commit_hash.to_string(),
+            message: message.map(str::to_string),
+            created_at: message.map(|_| Utc::now()),
+        };
+        self.save_tag(&tag)?;
+        Ok(tag)
     }
 
-    /// Set current branch reference
+    /// Store a tag, e.g. one received from a peer
     ///
-    /// # Arguments
-    /// * `ref_name` - Reference to switch to (e.g., "refs/heads/feature")
-    pub fn set_current_ref(&self, ref_name: &str) -> Result<()> {
+    /// Fails if the name is invalid, the tag already exists or the target
+    /// commit is not in the database.
+    pub fn save_tag(&self, tag: &Tag) -> Result<()> {
+        check_ref_name("tag", &tag.name)?;
+        if self.get_tag(&tag.name)?.is_some() {
+            return Err(anyhow::anyhow!("Tag '{}' already exists", tag.name));
+        }
+        if self.get_commit(&tag.target)?.is_none() {
+            return Err(anyhow::anyhow!("Commit not found: {}", tag.target));
+        }
+
+        let ref_name = format!("refs/tags/{}", tag.name);
         self.conn.execute(
-            "UPDATE branch_refs SET target = ?1 WHERE ref_name = 'HEAD'",
-            [ref_name],
+            "INSERT INTO branch_refs (ref_name, target) VALUES (?1, ?2)",
+            params![ref_name, tag.target],
         )?;
+        if let Some(message) = &tag.message {
+            let created_at = tag.created_at.unwrap_or_else(Utc::now);
+            self.conn.execute(
+                "INSERT OR REPLACE INTO tag_annotations (ref_name, message, created_at) VALUES (?1, ?2, ?3)",
+                params![ref_name, message, created_at.timestamp_millis()],
+            )?;
+        }
+
         Ok(())
     }
 
-    /// Check if a branch exists
-    ///
-    /// # Arguments
-    /// * `name` - Branch name
+    /// Get a tag by name
     ///
     /// # Returns
-    /// True if branch exists
-    pub fn branch_exists(&self, name: &str) -> Result<bool> {
-        let count: i64 = self.conn.query_row(
-            "SELECT COUNT(*) FROM branches WHERE name = ?1",
-            [name],
-            |row| row.get(0),
+    /// The tag if it exists
+    pub fn get_tag(&self, name: &str) -> Result<Option<Tag>> {
+        Ok(self
+            .list_tags()?
+            .into_iter()
+            .find(|tag| tag.name == name))
+    }
+
+    /// List all tags ordered by name
+    pub fn list_tags(&self) -> Result<Vec<Tag>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT r.ref_name, r.target, a.message, a.created_at
+             FROM branch_refs r
+             LEFT JOIN tag_annotations a ON a.ref_name = r.ref_name
+             WHERE r.ref_name LIKE 'refs/tags/%'
+             ORDER BY r.ref_name",
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            let ref_name: String = row.get(0)?;
+            let created_at: Option<i64> = row.get(3)?;
+            Ok(Tag {
+                name: ref_name.trim_start_matches("refs/tags/").to_string(),
+                target: row.get(1)?,
+                message: row.get(2)?,
+                created_at: created_at.and_then(DateTime::from_timestamp_millis),
+            })
+        })?;
+
+        let mut tags = Vec::new();
+        for row in rows {
+            tags.push(row?);
+        }
+
+        Ok(tags)
+    }
+
+    /// Delete a tag
+    ///
+    /// # Arguments
+    /// * `name` - Tag name to delete
+    pub fn delete_tag(&self, name: &str) -> Result<()> {
+        let ref_name = format!("refs/tags/{}", name);
+        let rows_affected = self
+            .conn
+            .execute("DELETE FROM branch_refs WHERE ref_name = ?1", [&ref_name])?;
+
+        if rows_affected == 0 {
+            return Err(anyhow::anyhow!("Tag '{}' does not exist", name));
+        }
+
+        self.conn
+            .execute("DELETE FROM tag_annotations WHERE ref_name = ?1", [&ref_name])?;
+
+        Ok(())
+    }
+
+    // === REMOTE-TRACKING REF METHODS ===
+
+    /// Record where a remote branch points
+    ///
+    /// Creates or moves `refs/remotes/<remote>/<branch>` and logs the update.
+    ///
+    /// # Arguments
+    /// * `remote` - Remote name
+    /// * `branch` - Branch name on the remote
+    /// * `commit_hash` - Commit the remote branch points to (must be in the database)
+    /// * `operation` - Reflog message (e.g., "pull: fast-forward")
+    pub fn update_remote_ref(&self, remote: &str, branch: &str, commit_hash: &str, operation: &str) -> Result<()> {
+        if remote.is_empty() || remote.contains('/') || branch.is_empty() {
+            return Err(anyhow::anyhow!("Invalid remote-tracking ref: '{}/{}'", remote, branch));
+        }
+        if self.get_commit(commit_hash)?.is_none() {
+            return Err(anyhow::anyhow!("Commit not found: {}", commit_hash));
+        }
+
+        let old_hash = self.get_remote_ref(remote, branch)?;
+        if old_hash.as_deref() == Some(commit_hash) {
+            return Ok(());
+        }
+
+        let ref_name = format!("refs/remotes/{}/{}", remote, branch);
+        self.conn.execute(
+            "INSERT OR REPLACE INTO branch_refs (ref_name, target) VALUES (?1, ?2)",
+            params![ref_name, commit_hash],
+        )?;
+        self.record_ref_update(&ref_name, old_hash.as_deref(), Some(commit_hash), operation)
+    }
+
+    /// Get the commit a remote-tracking ref points to
+    ///
+    /// # Returns
+    /// Commit hash if the ref exists
+    pub fn get_remote_ref(&self, remote: &str, branch: &str) -> Result<Option<String>> {
+        let ref_name = format!("refs/remotes/{}/{}", remote, branch);
+        let result = self.conn.query_row(
+            "SELECT target FROM branch_refs WHERE ref_name = ?1",
+            [&ref_name],
+            |row| row.get(0),
+        );
+
+        match result {
+            Ok(hash) => Ok(Some(hash)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// List remote-tracking refs ordered by remote and branch
+    ///
+    /// # Arguments
+    /// * `remote` - Only list refs of this remote (None for all remotes)
+    pub fn list_remote_refs(&self, remote: Option<&str>) -> Result<Vec<RemoteRef>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT ref_name, target FROM branch_refs
+             WHERE ref_name LIKE 'refs/remotes/%'
+             ORDER BY ref_name",
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+
+        let mut refs = Vec::new();
+        for row in rows {
+            let (ref_name, target) = row?;
+            let Some((ref_remote, branch)) = ref_name
+                .trim_start_matches("refs/remotes/")
+                .split_once('/')
+            else {
+                continue;
+            };
+            if remote.is_some_and(|remote| remote != ref_remote) {
+                continue;
+            }
+            refs.push(RemoteRef {
+                remote: ref_remote.to_string(),
+                branch: branch.to_string(),
+                target,
+            });
+        }
+
+        Ok(refs)
+    }
+
+    /// Delete all remote-tracking refs of a remote
+    ///
+    /// # Returns
+    /// Number of refs deleted
+    pub fn delete_remote_refs(&self, remote: &str) -> Result<usize> {
+        let refs = self.list_remote_refs(Some(remote))?;
+        for remote_ref in &refs {
+            let ref_name = format!("refs/remotes/{}", remote_ref.name());
+            self.conn.execute("DELETE FROM branch_refs WHERE ref_name = ?1", [&ref_name])?;
+            self.record_ref_update(&ref_name, Some(&remote_ref.target), None, &format!("remote: remove {}", remote))?;
+        }
+        Ok(refs.len())
+    }
+
+    /// Rename a branch, carrying over its reflog
+    ///
+    /// If the branch is checked out, HEAD is moved to the new name.
+    ///
+    /// # Arguments
+    /// * `old_name` - Existing branch name
+    /// * `new_name` - New branch name (must not exist)
+    pub fn rename_branch(&self, old_name: &str, new_name: &str) -> Result<()> {
+        check_ref_name("branch", new_name)?;
+        if self.branch_exists(new_name)? {
+            return Err(anyhow::anyhow!("Branch '{}' already exists", new_name));
+        }
+        let head = self
+            .get_branch_head(old_name)?
+            .ok_or_else(|| anyhow::anyhow!("Branch '{}' does not exist", old_name))?;
+
+        let old_ref = format!("refs/heads/{}", old_name);
+        let new_ref = format!("refs/heads/{}", new_name);
+
+        // All or nothing, so HEAD and the reflog never point at a missing branch
+        self.transaction(|db| {
+            db.conn.execute(
+                "UPDATE branches SET name = ?1 WHERE name = ?2",
+                params![new_name, old_name],
+            )?;
+            db.conn.execute(
+                "UPDATE branch_refs SET target = ?1 WHERE ref_name = 'HEAD' AND target = ?2",
+                params![new_ref, old_ref],
+            )?;
+            db.conn.execute(
+                "UPDATE reflog SET ref_name = ?1 WHERE ref_name = ?2",
+                params![new_ref, old_ref],
+            )?;
+
+            db.record_ref_update(
+                &new_ref,
+                Some(&head),
+                Some(&head),
+                &format!("branch: renamed {} to {}", old_ref, new_ref),
+            )
+        })
+    }
+
+    /// Get current branch reference
+    ///
+    /// # Returns
+    /// Current branch reference (e.g., "refs/heads/main")
+    pub fn get_current_ref(&self) -> Result<String> {
+        let ref_target = self.conn.query_row(
+            "SELECT target FROM branch_refs WHERE ref_name = 'HEAD'",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(ref_target)
+    }
+
+    /// Set current branch reference
+    ///
+    /// # Arguments
+    /// * `ref_name` - Reference to switch to (e.g., "refs/heads/feature")
+    pub fn set_current_ref(&self, ref_name: &str) -> Result<()> {
+        let old_ref = self.get_current_ref()?;
+        let head_of = |target: &str| -> Result<Option<String>> {
+            match target.strip_prefix("refs/heads/") {
+                Some(branch) => Ok(self
+                    .get_branch_head(branch)?
+                    .filter(|hash| hash != EMPTY_COMMIT_HASH)),
+                None => Ok(None),
+            }
+        };
+        let old_hash = head_of(&old_ref)?;
+        let new_hash = head_of(ref_name)?;
+
+        self.conn.execute(
+            "UPDATE branch_refs SET target = ?1 WHERE ref_name = 'HEAD'",
+            [ref_name],
+        )?;
+
+        self.record_ref_update(
+            "HEAD",
+            old_hash.as_deref(),
+            new_hash.as_deref(),
+            &format!(
+                "checkout: moving from {} to {}",
+                old_ref.trim_start_matches("refs/heads/"),
+                ref_name.trim_start_matches("refs/heads/")
+            ),
+        )
+    }
+
+    /// Check if a branch exists
+    ///
+    /// # Arguments
+    /// * `name` - Branch name
+    ///
+    /// # Returns
+    /// True if branch exists
+    pub fn branch_exists(&self, name: &str) -> Result<bool> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM branches WHERE name = ?1",
+            [name],
+            |row| row.get(0),
         )?;
         Ok(count > 0)
     }
@@ -592,12 +1554,579 @@ impl DatabaseManager {
 
     /// Update HEAD file
     pub fn update_head(&self, commit_hash: &str) -> Result<()> {
+        let old_hash = self.get_head_commit()?;
         let fai_path = std::path::Path::new(".fai");
         let head_path = fai_path.join("HEAD");
         std::fs::write(head_path, commit_hash)?;
+        self.record_ref_update("HEAD", old_hash.as_deref(), Some(commit_hash), "update HEAD")
+    }
+
+    // === ADDRESS BOOK METHODS ===
+
+    /// Remember that a peer was seen at an address
+    ///
+    /// # Arguments
+    /// * `peer_id` - Peer ID of the peer
+    /// * `address` - Multiaddr the peer announced or was discovered at
+    pub fn record_peer_address(&self, peer_id: &str, address: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO peer_addresses (peer_id, address, last_seen) VALUES (?1, ?2, ?3)
+             ON CONFLICT (peer_id, address) DO UPDATE SET last_seen = excluded.last_seen",
+            params![peer_id, address, Utc::now().timestamp_millis()],
+        )?;
+        Ok(())
+    }
+
+    /// Record the outcome of dialing a peer at an address
+    ///
+    /// Addresses that failed `MAX_ADDRESS_FAILURES` times without ever
+    /// working are forgotten.
+    ///
+    /// # Arguments
+    /// * `peer_id` - Peer ID of the peer
+    /// * `address` - Multiaddr that was dialed
+    /// * `success` - Whether the connection was established
+    pub fn record_dial_result(&self, peer_id: &str, address: &str, success: bool) -> Result<()> {
+        if success {
+            self.conn.execute(
+                "INSERT INTO peer_addresses (peer_id, address, last_seen, successes) VALUES (?1, ?2, ?3, 1)
+                 ON CONFLICT (peer_id, address) DO UPDATE
+                 SET last_seen = excluded.last_seen, successes = successes + 1",
+                params![peer_id, address, Utc::now().timestamp_millis()],
+            )?;
+        } else {
+            self.conn.execute(
+                "UPDATE peer_addresses SET failures = failures + 1 WHERE peer_id = ?1 AND address = ?2",
+                params![peer_id, address],
+            )?;
+            self.conn.execute(
+                "DELETE FROM peer_addresses
+                 WHERE peer_id = ?1 AND address = ?2 AND successes = 0 AND failures >= ?3",
+                params![peer_id, address, MAX_ADDRESS_FAILURES],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Known addresses of a peer, most promising first
+    ///
+    /// Addresses are ranked by successful minus failed dials, then by how
+    /// recently they were seen.
+    pub fn peer_addresses(&self, peer_id: &str) -> Result<Vec<PeerAddress>> {
+        self.query_peer_addresses("WHERE peer_id = ?1", [peer_id])
+    }
+
+    /// The whole address book, grouped by peer, most promising address first
+    pub fn list_peer_addresses(&self) -> Result<Vec<PeerAddress>> {
+        self.query_peer_addresses("", [])
+    }
+
+    fn query_peer_addresses<P: rusqlite::Params>(&self, filter: &str, params: P) -> Result<Vec<PeerAddress>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT peer_id, address, last_seen, successes, failures FROM peer_addresses {}
+             ORDER BY peer_id, successes - failures DESC, last_seen DESC",
+            filter
+        ))?;
+
+        let rows = stmt.query_map(params, |row| {
+            Ok(PeerAddress {
+                peer_id: row.get(0)?,
+                address: row.get(1)?,
+                last_seen: DateTime::from_timestamp_millis(row.get(2)?).unwrap_or_default(),
+                successes: row.get(3)?,
+                failures: row.get(4)?,
+            })
+        })?;
+
+        let mut addresses = Vec::new();
+        for row in rows {
+            addresses.push(row?);
+        }
+        Ok(addresses)
+    }
+
+    /// Record that a peer sent an object that failed verification
+    ///
+    /// Every `MAX_BAD_OBJECTS` bad objects ban the peer for `PEER_BAN_SECS`.
+    ///
+    /// # Returns
+    /// The end of the ban if this object got the peer banned
+    pub fn record_bad_object(&self, peer_id: &str) -> Result<Option<DateTime<Utc>>> {
+        let bad_objects: u32 = self.conn.query_row(
+            "INSERT INTO peer_reputation (peer_id, bad_objects) VALUES (?1, 1)
+             ON CONFLICT (peer_id) DO UPDATE SET bad_objects = bad_objects + 1
+             RETURNING bad_objects",
+            [peer_id],
+            |row| row.get(0),
+        )?;
+        if !bad_objects.is_multiple_of(MAX_BAD_OBJECTS) {
+            return Ok(None);
+        }
+
+        let banned_until = Utc::now() + chrono::Duration::seconds(PEER_BAN_SECS);
+        self.conn.execute(
+            "UPDATE peer_reputation SET banned_until = ?2 WHERE peer_id = ?1",
+            params![peer_id, banned_until.timestamp_millis()],
+        )?;
+        Ok(Some(banned_until))
+    }
+
+    /// Whether a peer is currently banned for sending bad objects
+    pub fn is_peer_banned(&self, peer_id: &str) -> Result<bool> {
+        Ok(self
+            .query_peer_reputations("WHERE peer_id = ?1", [peer_id])?
+            .first()
+            .is_some_and(PeerReputation::is_banned))
+    }
+
+    /// Every peer that ever sent a bad object
+    pub fn list_peer_reputations(&self) -> Result<Vec<PeerReputation>> {
+        self.query_peer_reputations("", [])
+    }
+
+    fn query_peer_reputations<P: rusqlite::Params>(&self, filter: &str, params: P) -> Result<Vec<PeerReputation>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT peer_id, bad_objects, banned_until FROM peer_reputation {} ORDER BY peer_id",
+            filter
+        ))?;
+
+        let rows = stmt.query_map(params, |row| {
+            Ok(PeerReputation {
+                peer_id: row.get(0)?,
+                bad_objects: row.get(1)?,
+                banned_until: row
+                    .get::<_, Option<i64>>(2)?
+                    .and_then(DateTime::from_timestamp_millis),
+            })
+        })?;
+
+        let mut reputations = Vec::new();
+        for row in rows {
+            reputations.push(row?);
+        }
+        Ok(reputations)
+    }
+
+    // === TRANSFER JOURNAL METHODS ===
+
+    /// Start journaling a pull or clone, or pick up the interrupted one
+    ///
+    /// The remote's tracking refs are saved so an abort can put them back.
+    ///
+    /// # Arguments
+    /// * `remote` - Remote name
+    /// * `peer_id` - Peer ID of the remote
+    ///
+    /// # Returns
+    /// The interrupted transfer from the same remote, if there is one
+    pub fn begin_transfer(&self, remote: &str, peer_id: &str) -> Result<Option<Transfer>> {
+        if let Some(transfer) = self.get_transfer()? {
+            if transfer.remote != remote {
+                return Err(anyhow::anyhow!(
+                    "An interrupted transfer from '{}' is in progress; pull from '{}' again to resume it or run 'fai pull --abort'",
+                    transfer.remote,
+                    transfer.remote
+                ));
+            }
+            return Ok(Some(transfer));
+        }
+
+        self.transaction(|db| {
+            db.conn.execute(
+                "INSERT INTO transfer_journal (id, remote, peer_id, started_at) VALUES (1, ?1, ?2, ?3)",
+                params![remote, peer_id, Utc::now().timestamp_millis()],
+            )?;
+            for remote_ref in db.list_remote_refs(Some(remote))? {
+                db.conn.execute(
+                    "INSERT INTO transfer_refs (branch, target) VALUES (?1, ?2)",
+                    params![remote_ref.branch, remote_ref.target],
+                )?;
+            }
+            Ok(None)
+        })
+    }
+
+    /// Add what the peer sent to the journal
+    ///
+    /// # Arguments
+    /// * `target` - Commit being fetched (keeps the current target if None)
+    /// * `commits` - Commits received
+    /// * `files` - Files those commits need
+    pub fn add_to_transfer(&self, target: Option<&str>, commits: &[String], files: &[String]) -> Result<()> {
+        self.transaction(|db| {
+            if let Some(target) = target {
+                db.conn
+                    .execute("UPDATE transfer_journal SET target = ?1", [target])?;
+            }
+            for hash in commits {
+                db.conn
+                    .execute("INSERT OR IGNORE INTO transfer_commits (hash) VALUES (?1)", [hash])?;
+            }
+            for hash in files {
+                db.conn
+                    .execute("INSERT OR IGNORE INTO transfer_files (hash) VALUES (?1)", [hash])?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Record an object that was downloaded and verified
+    ///
+    /// Does nothing unless a transfer is being journaled.
+    pub fn record_transfer_object(&self, hash: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO transfer_objects (hash)
+             SELECT ?1 WHERE EXISTS (SELECT 1 FROM transfer_journal)",
+            [hash],
+        )?;
+        Ok(())
+    }
+
+    /// Get the journal of the transfer in progress
+    pub fn get_transfer(&self) -> Result<Option<Transfer>> {
+        let result = self.conn.query_row(
+            "SELECT remote, peer_id, target, started_at FROM transfer_journal",
+            [],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, i64>(3)?,
+                ))
+            },
+        );
+        let (remote, peer_id, target, started_at) = match result {
+            Ok(row) => row,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let previous_refs = self
+            .conn
+            .prepare("SELECT branch, target FROM transfer_refs ORDER BY branch")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(Some(Transfer {
+            remote,
+            peer_id,
+            target,
+            started_at: DateTime::from_timestamp_millis(started_at).unwrap_or_default(),
+            commits: self.transfer_hashes("transfer_commits")?,
+            files: self.transfer_hashes("transfer_files")?,
+            verified_objects: self.transfer_hashes("transfer_objects")?,
+            previous_refs,
+        }))
+    }
+
+    /// Forget the journal once the transfer is complete
+    pub fn finish_transfer(&self) -> Result<()> {
+        for table in [
+            "transfer_journal",
+            "transfer_commits",
+            "transfer_files",
+            "transfer_objects",
+            "transfer_refs",
+        ] {
+            self.conn.execute(&format!("DELETE FROM {}", table), [])?;
+        }
+        Ok(())
+    }
+
+    /// Undo an interrupted transfer
+    ///
+    /// Puts the remote's tracking refs back where they were and removes the
+    /// received commits that no branch, tag or remote-tracking ref reaches
+    /// any more. Removing downloaded objects is left to the caller.
+    ///
+    /// # Returns
+    /// The discarded transfer, None if no transfer was in progress
+    pub fn abort_transfer(&self) -> Result<Option<Transfer>> {
+        let Some(transfer) = self.get_transfer()? else {
+            return Ok(None);
+        };
+
+        self.transaction(|db| {
+            let operation = format!("pull: abort transfer from {}", transfer.remote);
+            for remote_ref in db.list_remote_refs(Some(&transfer.remote))? {
+                if !transfer.previous_refs.iter().any(|(branch, _)| *branch == remote_ref.branch) {
+                    let ref_name = format!("refs/remotes/{}", remote_ref.name());
+                    db.conn.execute("DELETE FROM branch_refs WHERE ref_name = ?1", [&ref_name])?;
+                    db.record_ref_update(&ref_name, Some(&remote_ref.target), None, &operation)?;
+                }
+            }
+            for (branch, target) in &transfer.previous_refs {
+                db.update_remote_ref(&transfer.remote, branch, target, &operation)?;
+            }
+
+            let reachable = db.reachable_commits(&db.ref_tips(true)?)?;
+            for hash in &transfer.commits {
+                if !reachable.contains(hash) {
+                    db.delete_commit(hash)?;
+                }
+            }
+
+            db.finish_transfer()
+        })?;
+
+        Ok(Some(transfer))
+    }
+
+    fn transfer_hashes(&self, table: &str) -> Result<Vec<String>> {
+        let hashes = self
+            .conn
+            .prepare(&format!("SELECT hash FROM {} ORDER BY rowid", table))?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(hashes)
+    }
+
+    // === STASH METHODS ===
+
+    /// Save a stash entry
+    ///
+    /// # Arguments
+    /// * `branch` - Branch the changes come from
+    /// * `base_commit` - Branch head the changes are relative to
+    /// * `message` - Description of the stash
+    /// * `files` - Staged and working tree files to save
+    ///
+    /// # Returns
+    /// The new stash entry
+    pub fn create_stash(
+        &self,
+        branch: &str,
+        base_commit: Option<&str>,
+        message: &str,
+        files: &[StashFile],
+    ) -> Result<StashEntry> {
+        let created_at = Utc::now();
+        self.conn.execute(
+            "INSERT INTO stashes (branch, base_commit, message, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![branch, base_commit, message, created_at.timestamp_millis()],
+        )?;
+        let id = self.conn.last_insert_rowid();
+
+        for file in files {
+            self.conn.execute(
+                "INSERT INTO stash_files (stash_id, file_path, file_hash, file_size, staged) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![id, file.path, file.hash, file.size, file.staged],
+            )?;
+        }
+
+        Ok(StashEntry {
+            id,
+            branch: branch.to_string(),
+            base_commit: base_commit.map(str::to_string),
+            message: message.to_string(),
+            created_at: DateTime::from_timestamp_millis(created_at.timestamp_millis()).unwrap_or_default(),
+        })
+    }
+
+    /// List stash entries, newest first (index 0 is `stash@{0}`)
+    pub fn list_stashes(&self) -> Result<Vec<StashEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, branch, base_commit, message, created_at FROM stashes ORDER BY id DESC",
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok(StashEntry {
+                id: row.get(0)?,
+                branch: row.get(1)?,
+                base_commit: row.get(2)?,
+                message: row.get(3)?,
+                created_at: DateTime::from_timestamp_millis(row.get(4)?).unwrap_or_default(),
+            })
+        })?;
+
+        let mut stashes = Vec::new();
+        for row in rows {
+            stashes.push(row?);
+        }
+
+        Ok(stashes)
+    }
+
+    /// Get the files saved in a stash, ordered by path
+    pub fn get_stash_files(&self, stash_id: i64) -> Result<Vec<StashFile>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT file_path, file_hash, file_size, staged FROM stash_files
+             WHERE stash_id = ?1 ORDER BY file_path, staged",
+        )?;
+
+        let rows = stmt.query_map([stash_id], |row| {
+            Ok(StashFile {
+                path: row.get(0)?,
+                hash: row.get(1)?,
+                size: row.get(2)?,
+                staged: row.get(3)?,
+            })
+        })?;
+
+        let mut files = Vec::new();
+        for row in rows {
+            files.push(row?);
+        }
+
+        Ok(files)
+    }
+
+    /// Delete a stash entry and its files
+    pub fn delete_stash(&self, stash_id: i64) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM stash_files WHERE stash_id = ?1", [stash_id])?;
+        let rows_affected = self
+            .conn
+            .execute("DELETE FROM stashes WHERE id = ?1", [stash_id])?;
+
+        if rows_affected == 0 {
+            return Err(anyhow::anyhow!("Stash {} does not exist", stash_id));
+        }
+
+        Ok(())
+    }
+
+    /// Hashes of every object referenced by a stash
+    ///
+    /// Garbage collection keeps these alive until the stash is dropped.
+    pub fn stashed_file_hashes(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT file_hash FROM stash_files")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+
+        let mut hashes = Vec::new();
+        for row in rows {
+            hashes.push(row?);
+        }
+
+        Ok(hashes)
+    }
+
+    // === REFLOG METHODS ===
+
+    /// Record a branch head update in the reflog
+    ///
+    /// Updates of the current branch are also recorded under HEAD.
+    fn record_branch_update(
+        &self,
+        branch: &str,
+        old_hash: Option<&str>,
+        new_hash: Option<&str>,
+        operation: &str,
+    ) -> Result<()> {
+        self.record_ref_update(&format!("refs/heads/{}", branch), old_hash, new_hash, operation)?;
+        if self.get_current_branch().ok().as_deref() == Some(branch) {
+            self.record_ref_update("HEAD", old_hash, new_hash, operation)?;
+        }
+        Ok(())
+    }
+
+    /// Append an entry to the reflog
+    ///
+    /// The empty-branch placeholder hash is stored as NULL.
+    fn record_ref_update(
+        &self,
+        ref_name: &str,
+        old_hash: Option<&str>,
+        new_hash: Option<&str>,
+        operation: &str,
+    ) -> Result<()> {
+        let old_hash = old_hash.filter(|hash| *hash != EMPTY_COMMIT_HASH);
+        let new_hash = new_hash.filter(|hash| *hash != EMPTY_COMMIT_HASH);
+        if old_hash.is_none() && new_hash.is_none() {
+            return Ok(());
+        }
+
+        self.conn.execute(
+            "INSERT INTO reflog (ref_name, old_hash, new_hash, operation, user, timestamp)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                ref_name,
+                old_hash,
+                new_hash,
+                operation,
+                current_user(),
+                Utc::now().timestamp_millis()
+            ],
+        )?;
         Ok(())
     }
 
+    /// Get reflog entries, newest first
+    ///
+    /// # Arguments
+    /// * `ref_name` - "HEAD", a branch name or a full ref (None for all refs)
+    /// * `limit` - Maximum number of entries to return (None for all)
+    pub fn reflog(&self, ref_name: Option<&str>, limit: Option<usize>) -> Result<Vec<ReflogEntry>> {
+        let ref_name = ref_name.map(|name| {
+            if name == "HEAD" || name.starts_with("refs/") {
+                name.to_string()
+            } else {
+                format!("refs/heads/{}", name)
+            }
+        });
+
+        let mut stmt = self.conn.prepare(
+            "SELECT ref_name, old_hash, new_hash, operation, user, timestamp FROM reflog
+             WHERE ?1 IS NULL OR ref_name = ?1
+             ORDER BY id DESC
+             LIMIT ?2",
+        )?;
+
+        let limit = limit.map(|l| l as i64).unwrap_or(-1);
+        let rows = stmt.query_map(params![ref_name, limit], |row| {
+            Ok(ReflogEntry {
+                ref_name: row.get(0)?,
+                old_hash: row.get(1)?,
+                new_hash: row.get(2)?,
+                operation: row.get(3)?,
+                user: row.get(4)?,
+                timestamp: DateTime::from_timestamp_millis(row.get(5)?).unwrap_or_default(),
+            })
+        })?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            entries.push(row?);
+        }
+
+        Ok(entries)
+    }
+
+    /// Commits referenced by reflog entries newer than `since`
+    ///
+    /// Garbage collection treats these as reachable so recent ref updates
+    /// can still be undone.
+    pub fn reflog_commits_since(&self, since: DateTime<Utc>) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT old_hash FROM reflog WHERE timestamp >= ?1 AND old_hash IS NOT NULL
+             UNION
+             SELECT new_hash FROM reflog WHERE timestamp >= ?1 AND new_hash IS NOT NULL",
+        )?;
+
+        let rows = stmt.query_map([since.timestamp_millis()], |row| row.get(0))?;
+
+        let mut hashes = Vec::new();
+        for row in rows {
+            hashes.push(row?);
+        }
+
+        Ok(hashes)
+    }
+
+    /// Delete reflog entries older than `before`
+    ///
+    /// # Returns
+    /// Number of entries removed
+    pub fn expire_reflog(&self, before: DateTime<Utc>) -> Result<usize> {
+        Ok(self.conn.execute(
+            "DELETE FROM reflog WHERE timestamp < ?1",
+            [before.timestamp_millis()],
+        )?)
+    }
+
     /// Get all commits in repository
     pub fn get_all_commits(&self) -> Result<Vec<Commit>> {
         let mut stmt = self
@@ -639,6 +2168,16 @@ impl DatabaseManager {
 
     }
 
+/// Name of the user performing ref updates, for the reflog
+///
+/// Taken from `FAI_USER`, falling back to the login name.
+fn current_user() -> String {
+    ["FAI_USER", "USER", "USERNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|value| !value.is_empty()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -651,6 +2190,17 @@ mod tests {
         (db, temp_dir)
     }
 
+    #[test]
+    fn test_check_file_path() {
+        assert!(check_file_path("model.onnx").is_ok());
+        assert!(check_file_path("data/./train.csv").is_ok());
+
+        assert!(check_file_path("").is_err());
+        assert!(check_file_path("/etc/passwd").is_err());
+        assert!(check_file_path("../outside").is_err());
+        assert!(check_file_path("data/../../outside").is_err());
+    }
+
     #[test]
     fn test_staging_operations() {
         let (db, _temp_dir) = create_temp_database();
@@ -691,7 +2241,7 @@ mod tests {
         assert_eq!(commit.hash, "commit1");
         assert_eq!(commit.message, "Initial commit");
         assert_eq!(commit.parents, Vec::<String>::new());
-        assert_eq!(commit.is_merge, false);
+        assert!(!commit.is_merge);
 
         // Test getting commit files
         let commit_files = db.get_commit_files("commit1").unwrap();
@@ -728,4 +2278,316 @@ mod tests {
         // Most recent commit should be first
         assert_eq!(history[0].hash, "commit2");
     }
+
+    #[test]
+    fn test_snapshot_inherits_parent_files() {
+        let (db, _temp_dir) = create_temp_database();
+
+        let files = vec![
+            ("a.bin".to_string(), "hash_a".to_string(), 10),
+            ("b.bin".to_string(), "hash_b".to_string(), 20),
+        ];
+        db.create_commit("commit1", "First", &[], &files, false).unwrap();
+
+        // Stage a modification of a.bin and a brand new file
+        db.add_to_staging("a.bin", "hash_a2", 11).unwrap();
+        db.add_to_staging("c.bin", "hash_c", 30).unwrap();
+
+        let snapshot = db.snapshot_with_staged(Some("commit1")).unwrap();
+        assert_eq!(
+            snapshot,
+            vec![
+                ("a.bin".to_string(), "hash_a2".to_string(), 11),
+                ("b.bin".to_string(), "hash_b".to_string(), 20),
+                ("c.bin".to_string(), "hash_c".to_string(), 30),
+            ]
+        );
+
+        // Without a base commit only the staged files are included
+        let root = db.snapshot_with_staged(None).unwrap();
+        assert_eq!(root.len(), 2);
+    }
+
+    #[test]
+    fn test_tags() {
+        let (db, _temp_dir) = create_temp_database();
+
+        db.create_commit("c1", "First", &[], &[], false).unwrap();
+        db.create_commit("c2", "Second", &["c1".to_string()], &[], false).unwrap();
+        db.update_branch_head("main", "c2").unwrap();
+
+        db.create_tag("v1.0", "c1", None).unwrap();
+        let release = db.create_tag("v1.1", "c2", Some("Release 1.1")).unwrap();
+        assert!(release.is_annotated());
+
+        let tags = db.list_tags().unwrap();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags[0].name, "v1.0");
+        assert!(!tags[0].is_annotated());
+        assert_eq!(tags[1].message.as_deref(), Some("Release 1.1"));
+        assert!(tags[1].created_at.is_some());
+
+        // Tags resolve as revisions and do not disturb HEAD
+        assert_eq!(db.resolve_revision("v1.0").unwrap(), "c1");
+        assert_eq!(db.resolve_revision("v1.1~1").unwrap(), "c1");
+        assert_eq!(db.get_current_branch().unwrap(), "main");
+
+        assert!(db.create_tag("v1.0", "c2", None).is_err()); // already exists
+        assert!(db.create_tag("bad name", "c2", None).is_err());
+        assert!(db.create_tag("v2.0", "missing", None).is_err());
+
+        db.delete_tag("v1.1").unwrap();
+        assert!(db.get_tag("v1.1").unwrap().is_none());
+        assert!(db.delete_tag("v1.1").is_err());
+    }
+
+    #[test]
+    fn test_reflog_records_ref_updates() {
+        let (db, _temp_dir) = create_temp_database();
+
+        db.create_commit("c1", "First", &[], &[], false).unwrap();
+        db.create_commit("c2", "Second", &["c1".to_string()], &[], false).unwrap();
+        db.update_branch_head_with_reason("main", "c1", "commit: First").unwrap();
+        db.update_branch_head_with_reason("main", "c2", "commit: Second").unwrap();
+        db.create_branch("feature", "c1").unwrap();
+        db.delete_branch("feature").unwrap();
+
+        let main_log = db.reflog(Some("main"), None).unwrap();
+        assert_eq!(main_log.len(), 2);
+        assert_eq!(main_log[0].operation, "commit: Second");
+        assert_eq!(main_log[0].old_hash.as_deref(), Some("c1"));
+        assert_eq!(main_log[1].old_hash, None);
+
+        // Updates of the current branch also move HEAD
+        assert_eq!(db.reflog(Some("HEAD"), None).unwrap().len(), 2);
+        assert_eq!(db.resolve_revision("HEAD@{1}").unwrap(), "c1");
+
+        // A deleted branch keeps its last head in the reflog
+        let feature_log = db.reflog(Some("feature"), Some(1)).unwrap();
+        assert_eq!(feature_log[0].operation, "branch: deleted");
+        assert_eq!(feature_log[0].old_hash.as_deref(), Some("c1"));
+
+        assert_eq!(db.reflog_commits_since(Utc::now() - chrono::Duration::hours(1)).unwrap().len(), 2);
+        assert_eq!(db.expire_reflog(Utc::now() + chrono::Duration::seconds(1)).unwrap(), 6);
+        assert!(db.reflog(None, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_transaction_rolls_back_on_error() {
+        let (db, _temp_dir) = create_temp_database();
+
+        let result: Result<()> = db.transaction(|db| {
+            db.create_commit("c1", "First", &[], &[], false)?;
+            db.update_branch_head("missing", "c1")
+        });
+        assert!(result.is_err());
+        assert!(db.get_commit("c1").unwrap().is_none());
+
+        db.transaction(|db| db.create_commit("c1", "First", &[], &[], false)).unwrap();
+        assert!(db.get_commit("c1").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_remote_tracking_refs() {
+        let (db, _temp_dir) = create_temp_database();
+
+        db.create_commit("c1", "First", &[], &[], false).unwrap();
+        db.create_commit("c2", "Second", &["c1".to_string()], &[], false).unwrap();
+        db.update_remote_ref("origin", "main", "c1", "pull: storing head").unwrap();
+        db.update_remote_ref("origin", "feature/x", "c2", "pull: storing head").unwrap();
+        db.update_remote_ref("origin", "main", "c2", "pull: fast-forward").unwrap();
+
+        assert_eq!(db.get_remote_ref("origin", "main").unwrap().as_deref(), Some("c2"));
+        assert_eq!(db.get_remote_ref("backup", "main").unwrap(), None);
+        let refs = db.list_remote_refs(Some("origin")).unwrap();
+        assert_eq!(refs.len(), 2);
+        assert_eq!(refs[0].name(), "origin/feature/x");
+        assert!(db.list_remote_refs(Some("backup")).unwrap().is_empty());
+
+        // Remote-tracking refs resolve as revisions but are not branches or tags
+        assert_eq!(db.resolve_revision("origin/main~1").unwrap(), "c1");
+        assert_eq!(db.resolve_revision("refs/remotes/origin/feature/x").unwrap(), "c2");
+        assert!(db.list_tags().unwrap().is_empty());
+        assert!(!db.branch_exists("origin/main").unwrap());
+
+        assert_eq!(db.reflog(Some("refs/remotes/origin/main"), None).unwrap().len(), 2);
+        assert!(db.update_remote_ref("origin", "main", "missing", "pull").is_err());
+
+        db.update_remote_ref("backup", "main", "c1", "pull: storing head").unwrap();
+        assert_eq!(db.delete_remote_refs("origin").unwrap(), 2);
+        assert_eq!(db.list_remote_refs(None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_fetch_negotiation() {
+        let (db, _temp_dir) = create_temp_database();
+
+        // c1 <- c2 <- c3 on main, c4 on top of c2 on feature
+        db.create_commit("c1", "First", &[], &[], false).unwrap();
+        db.create_commit("c2", "Second", &["c1".to_string()], &[], false).unwrap();
+        db.create_commit("c3", "Third", &["c2".to_string()], &[], false).unwrap();
+        db.create_commit("c4", "Feature", &["c2".to_string()], &[], false).unwrap();
+        db.update_branch_head("main", "c3").unwrap();
+        db.create_branch("feature", "c4").unwrap();
+
+        let tips = db.ref_tips(false).unwrap();
+        assert_eq!(tips.len(), 2);
+
+        let hashes = |commits: Vec<Commit>| commits.into_iter().map(|c| c.hash).collect::<Vec<_>>();
+        assert_eq!(db.commits_between(&tips, &[]).unwrap().len(), 4);
+        let mut missing = hashes(db.commits_between(&tips, &["c2".to_string()]).unwrap());
+        missing.sort();
+        assert_eq!(missing, vec!["c3", "c4"]);
+        // Unknown haves are ignored
+        assert_eq!(db.commits_between(&tips, &["elsewhere".to_string()]).unwrap().len(), 4);
+
+        // Tips plus ancestors at distance 1, 2, 4, ...; shared history is listed once
+        assert_eq!(db.fetch_haves().unwrap(), vec!["c4", "c2", "c1", "c3"]);
+    }
+
+    #[test]
+    fn test_address_book() {
+        let (db, _temp_dir) = create_temp_database();
+
+        db.record_peer_address("peer-a", "/ip4/10.0.0.2/tcp/4001").unwrap();
+        db.record_peer_address("peer-a", "/ip4/192.168.1.5/tcp/4001").unwrap();
+        db.record_peer_address("peer-b", "/ip4/10.0.0.3/tcp/4001").unwrap();
+        db.record_dial_result("peer-a", "/ip4/192.168.1.5/tcp/4001", true).unwrap();
+        db.record_dial_result("peer-a", "/ip4/10.0.0.2/tcp/4001", false).unwrap();
+
+        // Addresses that worked come first
+        let addresses = db.peer_addresses("peer-a").unwrap();
+        assert_eq!(addresses[0].address, "/ip4/192.168.1.5/tcp/4001");
+        assert_eq!(addresses[0].successes, 1);
+        assert_eq!(addresses[1].failures, 1);
+        assert_eq!(db.list_peer_addresses().unwrap().len(), 3);
+
+        // An address that never worked is dropped after repeated failures
+        for _ in 1..MAX_ADDRESS_FAILURES {
+            db.record_dial_result("peer-a", "/ip4/10.0.0.2/tcp/4001", false).unwrap();
+        }
+        assert_eq!(db.peer_addresses("peer-a").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_peer_reputation() {
+        let (db, _temp_dir) = create_temp_database();
+
+        for _ in 1..MAX_BAD_OBJECTS {
+            assert_eq!(db.record_bad_object("peer-a").unwrap(), None);
+        }
+        assert!(!db.is_peer_banned("peer-a").unwrap());
+
+        // The last bad object allowed gets the peer banned
+        let banned_until = db.record_bad_object("peer-a").unwrap().unwrap();
+        assert!(banned_until > Utc::now());
+        assert!(db.is_peer_banned("peer-a").unwrap());
+        assert!(!db.is_peer_banned("peer-b").unwrap());
+
+        let reputations = db.list_peer_reputations().unwrap();
+        assert_eq!(reputations.len(), 1);
+        assert_eq!(reputations[0].bad_objects, MAX_BAD_OBJECTS);
+        assert!(reputations[0].is_banned());
+    }
+
+    #[test]
+    fn test_transfer_journal() {
+        let (db, _temp_dir) = create_temp_database();
+        let files = vec![("model.bin".to_string(), "f1".to_string(), 10)];
+
+        db.create_commit("c1", "Base", &[], &files, false).unwrap();
+        db.update_branch_head("main", "c1").unwrap();
+        db.update_remote_ref("origin", "main", "c1", "pull").unwrap();
+        assert!(db.begin_transfer("origin", "peer-a").unwrap().is_none());
+
+        // The peer sends c2 and c3; an object is verified before the pull dies
+        db.create_commit("c2", "Second", &["c1".to_string()], &files, false).unwrap();
+        db.create_commit("c3", "Third", &["c2".to_string()], &[("model.bin".to_string(), "f3".to_string(), 10)], false)
+            .unwrap();
+        db.update_remote_ref("origin", "main", "c3", "pull").unwrap();
+        db.update_remote_ref("origin", "dev", "c2", "pull").unwrap();
+        db.add_to_transfer(Some("c3"), &["c3".to_string(), "c2".to_string()], &["f3".to_string()])
+            .unwrap();
+        db.record_transfer_object("f3").unwrap();
+
+        // Re-running resumes the same transfer; another remote has to wait
+        let transfer = db.begin_transfer("origin", "peer-a").unwrap().unwrap();
+        assert_eq!(transfer.target.as_deref(), Some("c3"));
+        assert_eq!(transfer.commits, vec!["c3".to_string(), "c2".to_string()]);
+        assert_eq!(transfer.files, vec!["f3".to_string()]);
+        assert_eq!(transfer.verified_objects, vec!["f3".to_string()]);
+        assert_eq!(transfer.previous_refs, vec![("main".to_string(), "c1".to_string())]);
+        assert!(db.begin_transfer("backup", "peer-b").is_err());
+
+        // Aborting restores the remote refs and drops the received commits
+        db.abort_transfer().unwrap().unwrap();
+        assert_eq!(db.get_remote_ref("origin", "main").unwrap().as_deref(), Some("c1"));
+        assert!(db.get_remote_ref("origin", "dev").unwrap().is_none());
+        assert!(db.get_commit("c2").unwrap().is_none());
+        assert!(db.get_commit("c3").unwrap().is_none());
+        assert_eq!(db.get_commit_files("c1").unwrap().len(), 1);
+        assert!(db.get_commit_files("c3").unwrap().is_empty());
+        assert!(db.get_transfer().unwrap().is_none());
+        assert!(db.abort_transfer().unwrap().is_none());
+
+        // Objects are only journaled while a transfer is in progress
+        db.record_transfer_object("f4").unwrap();
+        db.begin_transfer("backup", "peer-b").unwrap();
+        assert!(db.get_transfer().unwrap().unwrap().verified_objects.is_empty());
+        db.finish_transfer().unwrap();
+        assert!(db.get_transfer().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_resolve_revision() {
+        let (db, _temp_dir) = create_temp_database();
+
+        db.create_commit("aaaa1111", "Root", &[], &[], false).unwrap();
+        db.create_commit("aaaa2222", "Second", &["aaaa1111".to_string()], &[], false).unwrap();
+        db.create_commit("bbbb3333", "Third", &["aaaa2222".to_string()], &[], false).unwrap();
+        db.update_branch_head("main", "bbbb3333").unwrap();
+        db.create_branch("feature", "aaaa2222").unwrap();
+
+        assert_eq!(db.resolve_revision("HEAD").unwrap(), "bbbb3333");
+        assert_eq!(db.resolve_revision("HEAD~").unwrap(), "aaaa2222");
+        assert_eq!(db.resolve_revision("HEAD~2").unwrap(), "aaaa1111");
+        assert_eq!(db.resolve_revision("feature~1").unwrap(), "aaaa1111");
+        assert_eq!(db.resolve_revision("bbbb").unwrap(), "bbbb3333");
+        assert_eq!(db.resolve_revision("aaaa1111").unwrap(), "aaaa1111");
+
+        assert!(db.resolve_revision("aaaa").is_err()); // ambiguous
+        assert!(db.resolve_revision("cccc").is_err()); // unknown
+        assert!(db.resolve_revision("HEAD~3").is_err()); // past the root
+    }
+
+    #[test]
+    fn test_merge_base_and_ahead_behind() {
+        let (db, _temp_dir) = create_temp_database();
+        let parents = |hashes: &[&str]| hashes.iter().map(|h| h.to_string()).collect::<Vec<_>>();
+
+        //   root - a1 - a2          (branch a)
+        //      \
+        //       b1 - b2 - b3       (branch b)
+        db.create_commit("root", "root", &[], &[], false).unwrap();
+        db.create_commit("a1", "a1", &parents(&["root"]), &[], false).unwrap();
+        db.create_commit("a2", "a2", &parents(&["a1"]), &[], false).unwrap();
+        db.create_commit("b1", "b1", &parents(&["root"]), &[], false).unwrap();
+        db.create_commit("b2", "b2", &parents(&["b1"]), &[], false).unwrap();
+        db.create_commit("b3", "b3", &parents(&["b2"]), &[], false).unwrap();
+
+        assert_eq!(db.find_merge_base("a2", "b3").unwrap(), Some("root".to_string()));
+        assert_eq!(db.ahead_behind("a2", "b3").unwrap(), (2, 3));
+        assert_eq!(db.ahead_behind("b3", "b1").unwrap(), (2, 0));
+        assert!(db.is_ancestor("b1", "b3").unwrap());
+        assert!(!db.is_ancestor("a1", "b3").unwrap());
+
+        // Merging b into a makes b3 the base for further merges
+        db.create_commit("m", "merge", &parents(&["a2", "b3"]), &[], true).unwrap();
+        assert_eq!(db.merge_bases("m", "b3").unwrap(), vec!["b3".to_string()]);
+        assert_eq!(db.ahead_behind("m", "b3").unwrap(), (3, 0));
+
+        // Unrelated histories have no merge base
+        db.create_commit("orphan", "orphan", &[], &[], false).unwrap();
+        assert_eq!(db.find_merge_base("orphan", "a2").unwrap(), None);
+    }
 }
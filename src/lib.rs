@@ -5,6 +5,8 @@
 //! datasets, AI models, and any files that are too large for traditional version
 //! control systems.
 
+pub mod config;
+pub mod daemon;
 pub mod database;
 pub mod network;
 pub mod storage;
@@ -154,6 +156,9 @@ impl FaiProtocol {
     }
 
     /// Create a commit from staged files
+    ///
+    /// The new commit records a full snapshot: the parent's files with the
+    /// staged changes applied on top.
     pub fn commit(&self, message: &str) -> Result<String> {
         // Get staged files
         let staged_files = self.database.get_staged_files()?;
@@ -165,12 +170,16 @@ impl FaiProtocol {
         // Read current HEAD
         let parent_hash = self.get_head()?;
 
+        // Inherit the parent's files and apply the staged changes
+        let snapshot = self.database.snapshot_with_staged(parent_hash.as_deref())?;
+
         // Generate commit hash
         let commit_data = format!(
-            "{}{}{:?}",
+            "{}{}{:?}{:?}",
             Utc::now().timestamp_millis(),
             message,
-            staged_files
+            parent_hash,
+            snapshot
         );
         let mut hasher = blake3::Hasher::new();
         hasher.update(commit_data.as_bytes());
@@ -185,12 +194,19 @@ impl FaiProtocol {
             &commit_hash,
             message,
             &parents,
-            &staged_files,
+            &snapshot,
             false, // Not a merge commit
         )?;
 
-        // Update HEAD file
-        std::fs::write(self.fai_path.join("HEAD"), &commit_hash)?;
+        // Advance the current branch, or move a detached HEAD
+        match self.head_branch()? {
+            Some(branch) => self.database.update_branch_head_with_reason(
+                &branch,
+                &commit_hash,
+                &format!("commit: {}", message),
+            )?,
+            None => std::fs::write(self.fai_path.join("HEAD"), &commit_hash)?,
+        }
 
         // Clear staging area
         self.database.clear_staging()?;
@@ -214,22 +230,42 @@ impl FaiProtocol {
     }
 
     /// Read current HEAD commit hash
+    ///
+    /// Branch references (`ref: refs/heads/<name>`) are resolved through the
+    /// branches table; a branch without commits yields None.
     fn get_head(&self) -> Result<Option<String>> {
+        if let Some(branch) = self.head_branch()? {
+            return Ok(self
+                .database
+                .get_branch_head(&branch)?
+                .filter(|hash| hash != database::EMPTY_COMMIT_HASH));
+        }
+
         let head_path = self.fai_path.join("HEAD");
         if head_path.exists() {
             let content = std::fs::read_to_string(&head_path)?;
-            // Handle both direct hash and ref: refs/heads/main format
-            if content.starts_with("ref:") {
-                // For now, return None for branch refs (not implemented yet)
-                Ok(None)
-            } else {
-                Ok(Some(content.trim().to_string()))
-            }
+            Ok(Some(content.trim().to_string()))
         } else {
             Ok(None)
         }
     }
 
+    /// Name of the branch HEAD points to, or None if HEAD is detached
+    fn head_branch(&self) -> Result<Option<String>> {
+        let head_path = self.fai_path.join("HEAD");
+        if !head_path.exists() {
+            return Ok(None);
+        }
+
+        let content = std::fs::read_to_string(&head_path)?;
+        Ok(content
+            .trim()
+            .strip_prefix("ref:")
+            .map(str::trim)
+            .and_then(|target| target.strip_prefix("refs/heads/"))
+            .map(str::to_string))
+    }
+
     /// Get the current HEAD commit hash
     pub fn get_head_commit(&self) -> Result<Option<String>> {
         self.get_head()
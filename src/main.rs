@@ -1,9 +1,8 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use fai_protocol::network::RefStatus;
 use fai_protocol::FaiProtocol;
-use libp2p::PeerId;
 use std::path::Path;
-use std::str::FromStr;
 use std::sync::Arc;
 
 // Import services
@@ -38,34 +37,85 @@ enum Commands {
     Peers,
     /// Fetch a chunk of data from a peer
     Fetch {
-        /// Peer ID to fetch from
-        peer_id: String,
+        /// Remote name, peer ID or /p2p/ address to fetch from
+        remote: String,
         /// Hash of the data to fetch
         hash: String,
     },
     /// Start server to serve chunks to other peers
-    Serve,
+    Serve {
+        #[command(flatten)]
+        addresses: ListenArgs,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    /// Relay connections between peers that can't reach each other directly
+    Relay {
+        #[command(flatten)]
+        addresses: ListenArgs,
+    },
+    /// Keep serving in the background and run peers, fetch and push for other commands
+    Daemon {
+        #[command(subcommand)]
+        action: Option<DaemonAction>,
+        #[command(flatten)]
+        addresses: ListenArgs,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
     /// List chunks for a multi-chunk file
     Chunks { hash: String },
+    /// Print this repository's peer ID
+    Id {
+        /// Encrypt the identity with the passphrase in FAI_IDENTITY_PASSPHRASE
+        #[arg(long, conflicts_with = "decrypt")]
+        encrypt: bool,
+        /// Store the identity unencrypted
+        #[arg(long)]
+        decrypt: bool,
+    },
     /// Push commits to a peer
     Push {
-        /// Peer ID to push to
-        peer_id: String,
+        /// Remote name, peer ID or /p2p/ address to push to
+        remote: String,
+        /// Branch to push (defaults to the current branch)
+        branch: Option<String>,
+        /// Update the peer's branch even if it is not a fast-forward
+        #[arg(long, short)]
+        force: bool,
     },
     /// Pull commits and files from a peer
     Pull {
-        /// Peer ID to pull from
-        peer_id: String,
-        /// Optional specific commit hash to pull (pulls all if not specified)
-        commit_hash: Option<String>,
+        /// Remote name, peer ID or /p2p/ address to pull from
+        #[arg(required_unless_present = "abort")]
+        remote: Option<String>,
+        /// Remote branch to merge into the current branch (defaults to its upstream)
+        branch: Option<String>,
+        /// Only fetch this commit
+        #[arg(long, conflicts_with = "branch")]
+        commit: Option<String>,
+        /// Discard an interrupted pull or clone instead of resuming it
+        #[arg(long, conflicts_with_all = ["remote", "branch", "commit"])]
+        abort: bool,
     },
     /// Clone an entire repository from a peer
     Clone {
-        /// Peer ID to clone from
-        peer_id: String,
+        /// Peer ID or /p2p/ address to clone from (saved as remote "origin")
+        remote: String,
         /// Optional target directory (defaults to current directory)
         directory: Option<String>,
     },
+    /// List the branches and tags a peer advertises
+    LsRemote {
+        /// Remote name, peer ID or /p2p/ address to query
+        remote: String,
+        /// Only show branches
+        #[arg(long)]
+        heads: bool,
+        /// Only show tags
+        #[arg(long)]
+        tags: bool,
+    },
     /// Compare two commits or versions
     Diff {
         /// First commit hash
@@ -73,6 +123,11 @@ enum Commands {
         /// Second commit hash
         hash2: String,
     },
+    /// Manage named remotes
+    Remote {
+        #[command(subcommand)]
+        action: Option<RemoteAction>,
+    },
     /// Generate shell completion script
     Completion {
         /// Shell type (bash, fish, zsh, powershell, elvish)
@@ -85,15 +140,115 @@ enum Commands {
         /// Delete the specified branch
         #[arg(long)]
         delete: bool,
+        /// Delete even if the branch is not fully merged
+        #[arg(long, short, requires = "delete")]
+        force: bool,
+        /// Rename a branch
+        #[arg(short = 'm', long = "move", num_args = 2, value_names = ["OLD", "NEW"])]
+        rename: Option<Vec<String>>,
         /// List all branches
         #[arg(long, short)]
         list: bool,
+        /// List branches with head commit and ahead/behind counts
+        #[arg(long, short)]
+        verbose: bool,
+        /// List remote-tracking branches
+        #[arg(long, short)]
+        remotes: bool,
+        /// Track a remote branch (<remote>/<branch>) from the given or current branch
+        #[arg(long, short = 'u', value_name = "UPSTREAM")]
+        set_upstream_to: Option<String>,
+        /// Stop tracking the upstream of the given or current branch
+        #[arg(long, conflicts_with = "set_upstream_to")]
+        unset_upstream: bool,
     },
     /// Switch to a different branch
     Checkout {
         /// Branch name to switch to
         branch_name: String,
     },
+    /// Merge another branch into the current branch
+    Merge {
+        /// Branch to merge
+        branch_name: String,
+        /// Resolve conflicts by keeping the current branch's version
+        #[arg(long, conflicts_with = "theirs")]
+        ours: bool,
+        /// Resolve conflicts by taking the merged branch's version
+        #[arg(long)]
+        theirs: bool,
+        /// Merge commit message
+        #[arg(short, long)]
+        message: Option<String>,
+    },
+    /// Apply the changes of a single commit onto the current branch
+    CherryPick {
+        /// Revision of the commit to apply
+        rev: String,
+        /// Resolve conflicts by keeping the current branch's version
+        #[arg(long, conflicts_with = "theirs")]
+        ours: bool,
+        /// Resolve conflicts by taking the picked commit's version
+        #[arg(long)]
+        theirs: bool,
+    },
+    /// Create, list or delete tags
+    Tag {
+        /// Tag name to create or delete
+        tag_name: Option<String>,
+        /// Revision to tag (defaults to HEAD)
+        rev: Option<String>,
+        /// Create an annotated tag with this message
+        #[arg(short, long)]
+        message: Option<String>,
+        /// List all tags
+        #[arg(long, short)]
+        list: bool,
+        /// Delete the specified tag
+        #[arg(long, short)]
+        delete: bool,
+    },
+    /// Show the history of ref updates
+    Reflog {
+        /// Ref to show: HEAD (default) or a branch name
+        ref_name: Option<String>,
+        /// Show entries for all refs
+        #[arg(long, conflicts_with = "ref_name")]
+        all: bool,
+        /// Maximum number of entries to show
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+    },
+    /// Remove unreachable commits and unreferenced objects
+    Gc {
+        /// Only report what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Save staged changes and modified tracked files for later
+    Stash {
+        #[command(subcommand)]
+        action: Option<StashAction>,
+    },
+    /// Move the current branch to another commit
+    Reset {
+        /// Target revision (commit hash or prefix, branch name, HEAD~N)
+        rev: String,
+        /// Only move the branch head; keep the undone changes staged
+        #[arg(long, conflicts_with_all = ["mixed", "hard"])]
+        soft: bool,
+        /// Move the branch head and clear staging (default)
+        #[arg(long, conflicts_with = "hard")]
+        mixed: bool,
+        /// Move the branch head, clear staging and restore the working tree
+        #[arg(long)]
+        hard: bool,
+    },
+    /// Create a commit that undoes an earlier commit
+    Revert {
+        /// Revision of the commit to revert
+        rev: String,
+    },
     /// Amend the last commit
     CommitAmend {
         /// New commit message (optional, keeps original if not provided)
@@ -111,68 +266,180 @@ enum Commands {
     },
 }
 
+#[derive(Subcommand)]
+enum StashAction {
+    /// Save changes and reset to the branch head (default)
+    Push {
+        /// Stash description
+        #[arg(short, long)]
+        message: Option<String>,
+    },
+    /// Restore a stash and remove it
+    Pop {
+        /// Stash index (0 is the most recent)
+        #[arg(default_value = "0")]
+        index: usize,
+    },
+    /// List saved stashes
+    List,
+    /// Remove a stash without restoring it
+    Drop {
+        /// Stash index (0 is the most recent)
+        #[arg(default_value = "0")]
+        index: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum RemoteAction {
+    /// Add a remote
+    Add {
+        /// Remote name
+        name: String,
+        /// Peer ID of the remote
+        peer_id: String,
+        /// Address to dial the remote at (repeatable)
+        #[arg(long = "addr", value_name = "MULTIADDR")]
+        addresses: Vec<String>,
+    },
+    /// List remotes (default)
+    List {
+        /// Show peer IDs and addresses
+        #[arg(long, short)]
+        verbose: bool,
+    },
+    /// Remove a remote and its remote-tracking branches
+    Remove {
+        /// Remote name
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum DaemonAction {
+    /// Run the daemon in the foreground (default)
+    Start,
+    /// Show what the running daemon is doing
+    Status,
+    /// Stop the running daemon
+    Stop,
+}
+
+/// Addresses of `fai serve`, `fai relay` and `fai daemon`, overriding `[network]` in the repo config
+#[derive(Args)]
+struct ListenArgs {
+    /// Address to listen on, e.g. /ip4/0.0.0.0/tcp/4001 or /ip6/::/udp/4001/quic-v1 (repeatable)
+    #[arg(long = "listen", value_name = "MULTIADDR")]
+    listen: Vec<String>,
+    /// Address peers can reach this node at, announced through identify (repeatable)
+    #[arg(long = "external", value_name = "MULTIADDR")]
+    external: Vec<String>,
+}
+
+impl ListenArgs {
+    /// The repository's network settings with the given flags applied
+    fn apply(self, mut network: fai_protocol::config::NetworkConfig) -> fai_protocol::config::NetworkConfig {
+        if !self.listen.is_empty() {
+            network.listen = self.listen;
+        }
+        if !self.external.is_empty() {
+            network.external = self.external;
+        }
+        network
+    }
+}
+
+/// Bandwidth settings of `fai serve` and `fai daemon`, overriding `[transfer]` in the repo config
+#[derive(Args)]
+struct LimitArgs {
+    /// Upload limit for all peers together, e.g. 10M (bytes per second)
+    #[arg(long, value_name = "RATE", value_parser = fai_protocol::config::parse_rate)]
+    upload_limit: Option<u64>,
+    /// Upload limit for each peer
+    #[arg(long, value_name = "RATE", value_parser = fai_protocol::config::parse_rate)]
+    peer_upload_limit: Option<u64>,
+    /// Download limit for all peers together
+    #[arg(long, value_name = "RATE", value_parser = fai_protocol::config::parse_rate)]
+    download_limit: Option<u64>,
+    /// Download limit for each peer
+    #[arg(long, value_name = "RATE", value_parser = fai_protocol::config::parse_rate)]
+    peer_download_limit: Option<u64>,
+    /// Chunk requests to serve at once; the rest wait their turn
+    #[arg(long, value_name = "N")]
+    max_inbound_requests: Option<usize>,
+}
+
+impl LimitArgs {
+    /// The repository's transfer settings with the given flags applied
+    fn apply(self, mut transfer: fai_protocol::config::TransferConfig) -> fai_protocol::config::TransferConfig {
+        transfer.upload_limit = self.upload_limit.or(transfer.upload_limit);
+        transfer.peer_upload_limit = self.peer_upload_limit.or(transfer.peer_upload_limit);
+        transfer.download_limit = self.download_limit.or(transfer.download_limit);
+        transfer.peer_download_limit = self.peer_download_limit.or(transfer.peer_download_limit);
+        transfer.max_inbound_requests = self.max_inbound_requests.unwrap_or(transfer.max_inbound_requests);
+        transfer
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
 
     // Handle completion commands
-    match &cli.command {
-        Commands::Completion { shell } => {
-            use clap::CommandFactory;
-            use std::io;
-
-            let mut cmd = Cli::command();
-            let name = "fai";
-
-            match shell {
-                clap_complete::Shell::Bash => {
-                    clap_complete::generate(
-                        clap_complete::shells::Bash,
-                        &mut cmd,
-                        name,
-                        &mut io::stdout(),
-                    );
-                }
-                clap_complete::Shell::Fish => {
-                    clap_complete::generate(
-                        clap_complete::shells::Fish,
-                        &mut cmd,
-                        name,
-                        &mut io::stdout(),
-                    );
-                }
-                clap_complete::Shell::Zsh => {
-                    clap_complete::generate(
-                        clap_complete::shells::Zsh,
-                        &mut cmd,
-                        name,
-                        &mut io::stdout(),
-                    );
-                }
-                clap_complete::Shell::PowerShell => {
-                    clap_complete::generate(
-                        clap_complete::shells::PowerShell,
-                        &mut cmd,
-                        name,
-                        &mut io::stdout(),
-                    );
-                }
-                clap_complete::Shell::Elvish => {
-                    clap_complete::generate(
-                        clap_complete::shells::Elvish,
-                        &mut cmd,
-                        name,
-                        &mut io::stdout(),
-                    );
-                }
-                _ => {
-                    eprintln!("Shell not supported for completion generation");
-                    return Ok(());
-                }
+    if let Commands::Completion { shell } = &cli.command {
+        use clap::CommandFactory;
+        use std::io;
+
+        let mut cmd = Cli::command();
+        let name = "fai";
+
+        match shell {
+            clap_complete::Shell::Bash => {
+                clap_complete::generate(
+                    clap_complete::shells::Bash,
+                    &mut cmd,
+                    name,
+                    &mut io::stdout(),
+                );
+            }
+            clap_complete::Shell::Fish => {
+                clap_complete::generate(
+                    clap_complete::shells::Fish,
+                    &mut cmd,
+                    name,
+                    &mut io::stdout(),
+                );
+            }
+            clap_complete::Shell::Zsh => {
+                clap_complete::generate(
+                    clap_complete::shells::Zsh,
+                    &mut cmd,
+                    name,
+                    &mut io::stdout(),
+                );
+            }
+            clap_complete::Shell::PowerShell => {
+                clap_complete::generate(
+                    clap_complete::shells::PowerShell,
+                    &mut cmd,
+                    name,
+                    &mut io::stdout(),
+                );
+            }
+            clap_complete::Shell::Elvish => {
+                clap_complete::generate(
+                    clap_complete::shells::Elvish,
+                    &mut cmd,
+                    name,
+                    &mut io::stdout(),
+                );
+            }
+            _ => {
+                eprintln!("Shell not supported for completion generation");
+                return Ok(());
             }
-            return Ok(());
         }
-        _ => {}
+        return Ok(());
     }
 
     match cli.command {
@@ -302,14 +569,54 @@ async fn main() -> Result<()> {
             // Get staged files
             let staged_files = fai.get_status()?;
 
+            // Files tracked by the current HEAD snapshot
+            let head_files: std::collections::HashMap<String, String> = match fai.get_head_commit()? {
+                Some(head) => fai
+                    .get_commit_files(&head)?
+                    .into_iter()
+                    .map(|(path, hash, _)| (path, hash))
+                    .collect(),
+                None => std::collections::HashMap::new(),
+            };
+
+            if let Ok(branch) = fai.database().get_current_branch() {
+                println!("On branch {}", branch);
+
+                let branch_service = services::BranchService::from_repo_path(Path::new(".fai"))?;
+                if let Some(tracking) = branch_service.tracking_status(&branch)? {
+                    match &tracking.divergence {
+                        Some(d) if d.ahead == 0 && d.behind == 0 => {
+                            println!("Your branch is up to date with '{}'", tracking.upstream)
+                        }
+                        Some(_) => println!(
+                            "Your branch is {} relative to '{}'",
+                            tracking.summary(),
+                            tracking.upstream
+                        ),
+                        None => println!(
+                            "Your branch is based on '{}', but the upstream is gone",
+                            tracking.upstream
+                        ),
+                    }
+                }
+            }
+            println!("Tracked files: {}", head_files.len());
+            println!();
+
             if staged_files.is_empty() {
                 println!("No changes staged for commit");
             } else {
                 println!("Changes to be committed:");
                 println!();
                 for (file_path, file_hash, file_size) in staged_files {
+                    let change = match head_files.get(&file_path) {
+                        None => "new file",
+                        Some(hash) if *hash == file_hash => "unchanged",
+                        Some(_) => "modified",
+                    };
                     println!(
-                        "  {} ({} - {} bytes)",
+                        "  {}: {} ({} - {} bytes)",
+                        change,
                         file_path,
                         &file_hash[..8],
                         file_size
@@ -344,47 +651,63 @@ async fn main() -> Result<()> {
             }
         }
         Commands::Peers => {
-            println!("Discovering peers on local network...");
-
-            // Create storage manager
-            let storage = Arc::new(fai_protocol::storage::StorageManager::new(
-                Path::new(".fai").to_path_buf(),
-            )?);
-
-            // Create database manager
-            let database =
-                fai_protocol::database::DatabaseManager::new(&Path::new(".fai").join("db.sqlite"))?;
-
-            // Create network manager
-            let mut network_manager =
-                match fai_protocol::network::NetworkManager::new(storage.clone(), database) {
+            let daemon = if Path::new(".fai").exists() {
+                fai_protocol::daemon::Client::connect(Path::new(".fai")).await?
+            } else {
+                None
+            };
+            let peers = if let Some(mut daemon) = daemon {
+                println!("Asking the daemon for peers...");
+                println!();
+                daemon.peers().await?
+            } else {
+                println!("Discovering peers on local network...");
+
+                // Create storage manager
+                let storage = Arc::new(fai_protocol::storage::StorageManager::new(
+                    Path::new(".fai").to_path_buf(),
+                )?);
+
+                // Create database manager
+                let database = fai_protocol::database::DatabaseManager::new(
+                    &Path::new(".fai").join("db.sqlite"),
+                )?;
+
+                // Create network manager
+                let identity = fai_protocol::network::identity::repo_identity(Path::new(".fai"))?;
+                let mut network_manager = match fai_protocol::network::NetworkManager::new(
+                    storage.clone(),
+                    database,
+                    identity,
+                ) {
                     Ok(nm) => nm,
                     Err(e) => {
                         return Err(anyhow::anyhow!("Failed to create network manager: {}", e));
                     }
                 };
 
-            // Start the network manager
-            if let Err(e) = network_manager.start().await {
-                return Err(anyhow::anyhow!("Failed to start network manager: {}", e));
-            }
+                // Start the network manager
+                if let Err(e) = network_manager.start().await {
+                    return Err(anyhow::anyhow!("Failed to start network manager: {}", e));
+                }
 
-            // Discover peers for 5 seconds
-            let start_time = std::time::Instant::now();
-            let discovery_duration = std::time::Duration::from_secs(5);
+                // Discover peers for 5 seconds
+                let start_time = std::time::Instant::now();
+                let discovery_duration = std::time::Duration::from_secs(5);
 
-            println!("Local peer ID: {}", network_manager.local_peer_id());
-            println!();
+                println!("Local peer ID: {}", network_manager.local_peer_id());
+                println!();
 
-            while start_time.elapsed() < discovery_duration {
-                if let Err(e) = network_manager.poll_events().await {
-                    eprintln!("Error during peer discovery: {}", e);
+                while start_time.elapsed() < discovery_duration {
+                    if let Err(e) = network_manager.poll_events().await {
+                        eprintln!("Error during peer discovery: {}", e);
+                    }
+                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
                 }
-                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-            }
 
-            // List discovered peers
-            let peers = network_manager.list_peers();
+                // List discovered peers
+                network_manager.list_peers()
+            };
 
             if peers.is_empty() {
                 println!("No peers discovered");
@@ -414,8 +737,26 @@ async fn main() -> Result<()> {
             }
 
             println!("Found {} peer(s)", peers.len());
+
+            // Peers that sent data failing verification
+            let reputations =
+                fai_protocol::database::DatabaseManager::new(&Path::new(".fai").join("db.sqlite"))?
+                    .list_peer_reputations()?;
+            if !reputations.is_empty() {
+                println!();
+                println!("Peers that sent bad data:");
+                for reputation in &reputations {
+                    let status = match reputation.banned_until {
+                        Some(until) if reputation.is_banned() => {
+                            format!(", banned until {}", until.format("%Y-%m-%d %H:%M:%S UTC"))
+                        }
+                        _ => String::new(),
+                    };
+                    println!("  {}: {} bad object(s){}", reputation.peer_id, reputation.bad_objects, status);
+                }
+            }
         }
-        Commands::Fetch { peer_id, hash } => {
+        Commands::Fetch { remote, hash } => {
             // Check if repository is initialized
             if !Path::new(".fai").exists() {
                 return Err(anyhow::anyhow!(
@@ -423,9 +764,36 @@ async fn main() -> Result<()> {
                 ));
             }
 
-            // Parse peer ID
-            let target_peer = PeerId::from_str(&peer_id)
-                .map_err(|_| anyhow::anyhow!("Invalid peer ID format: {}", peer_id))?;
+            // Let a running daemon download the file into the object store
+            if let Some(mut daemon) =
+                fai_protocol::daemon::Client::connect(Path::new(".fai")).await?
+            {
+                println!(
+                    "Fetching {} from {} through the daemon...",
+                    &hash[..8.min(hash.len())],
+                    remote
+                );
+                let downloaded = daemon.fetch(&remote, &hash).await?;
+                let data =
+                    fai_protocol::storage::StorageManager::new(Path::new(".fai").to_path_buf())?
+                        .retrieve(&hash)?;
+                let filename = format!("fetched_{}.dat", hash);
+                std::fs::write(&filename, &data)?;
+                println!(
+                    "✓ Received {} bytes ({} objects downloaded)",
+                    data.len(),
+                    downloaded
+                );
+                println!("Saved to: {}", filename);
+                return Ok(());
+            }
+
+            // Resolve the remote name, peer ID or address
+            let remote = fai_protocol::network::remote::Remote::resolve(
+                &fai_protocol::config::RepoConfig::load(Path::new(".fai"))?,
+                &remote,
+            )?;
+            let target_peer = remote.peer_id;
 
             println!("Discovering peers...");
 
@@ -439,13 +807,17 @@ async fn main() -> Result<()> {
                 fai_protocol::database::DatabaseManager::new(&Path::new(".fai").join("db.sqlite"))?;
 
             // Create network manager (single threaded for now to avoid complex async issues)
+            let identity = fai_protocol::network::identity::repo_identity(Path::new(".fai"))?;
             let mut network_manager =
-                match fai_protocol::network::NetworkManager::new(storage.clone(), database) {
+                match fai_protocol::network::NetworkManager::new(storage.clone(), database, identity) {
                     Ok(nm) => nm,
                     Err(e) => {
                         return Err(anyhow::anyhow!("Failed to create network manager: {}", e));
                     }
                 };
+            network_manager.set_transfer_config(
+                fai_protocol::config::RepoConfig::load(Path::new(".fai"))?.transfer,
+            );
 
             // Start the network manager
             if let Err(e) = network_manager.start().await {
@@ -454,10 +826,12 @@ async fn main() -> Result<()> {
 
             println!("Local peer ID: {}", network_manager.local_peer_id());
 
-            // Load peers from shared files for test discovery
-            if let Ok(loaded) = network_manager.load_peers_from_files() {
-                println!("Loaded {} peers from shared files", loaded);
-            }
+            // Load peers seen in earlier sessions
+            let loaded = network_manager.load_address_book()?;
+            println!("Loaded {} peers from the address book", loaded);
+
+            // Dial the addresses configured for the remote
+            network_manager.add_remote(&remote);
 
             // Discover peers for 10 seconds
             let discovery_duration = std::time::Duration::from_secs(10);
@@ -508,18 +882,18 @@ async fn main() -> Result<()> {
                 println!(
                     "Discovered {} peers, but target peer {} not found",
                     peers.len(),
-                    peer_id
+                    remote.name
                 );
                 for peer in &peers {
                     println!("  - {}", peer.peer_id);
                 }
                 return Err(anyhow::anyhow!(
                     "Peer {} not discovered in local network",
-                    peer_id
+                    remote.name
                 ));
             }
 
-            println!("Found peer {}", peer_id);
+            println!("Found peer {}", remote.name);
 
             // Check if this is a manifest file by reading it directly
             let manifest_path = format!(".fai/objects/{}/{}", &hash[..2], &hash[2..]);
@@ -538,8 +912,7 @@ async fn main() -> Result<()> {
                 // Clone the chunks array to avoid lifetime issues
                 let chunks_array = manifest
                     .get("chunks")
-                    .and_then(|c| c.as_array())
-                    .map(|c| c.clone())
+                    .and_then(|c| c.as_array()).cloned()
                     .unwrap_or_default();
 
                 let total_chunks = chunks_array.len();
@@ -558,7 +931,7 @@ async fn main() -> Result<()> {
                             &chunk_hash[..8]
                         );
                         match network_manager
-                            .request_chunk(target_peer.clone(), chunk_hash)
+                            .request_chunk(target_peer, chunk_hash)
                             .await
                         {
                             Ok(Some(data)) => {
@@ -594,10 +967,8 @@ async fn main() -> Result<()> {
                 // Assemble complete file
                 println!("Assembling complete file from {} chunks...", total_chunks);
                 let mut complete_data = Vec::new();
-                for chunk_data in chunks_data {
-                    if let Some(data) = chunk_data {
-                        complete_data.extend_from_slice(&data);
-                    }
+                for data in chunks_data.into_iter().flatten() {
+                    complete_data.extend_from_slice(&data);
                 }
 
                 // Save complete file
@@ -630,10 +1001,10 @@ async fn main() -> Result<()> {
                         );
                     }
                     Ok(None) => {
-                        println!("DEBUG: Chunk {} not available from peer {}", hash, peer_id);
+                        println!("DEBUG: Chunk {} not available from peer {}", hash, remote.name);
                         return Err(anyhow::anyhow!(
                             "✗ Chunk not available from peer {}",
-                            peer_id
+                            remote.name
                         ));
                     }
                     Err(e) => {
@@ -677,8 +1048,7 @@ async fn main() -> Result<()> {
                                 {
                                     for (i, chunk) in chunks.iter().enumerate() {
                                         if let Some(chunk_hash) = chunk.as_str() {
-                                            if let Some(chunk_data) =
-                                                fai.storage().retrieve(chunk_hash).ok()
+                                            if let Ok(chunk_data) = fai.storage().retrieve(chunk_hash)
                                             {
                                                 println!(
                                                     "  {}: {} ({} bytes)",
@@ -757,7 +1127,7 @@ async fn main() -> Result<()> {
                 return Err(anyhow::anyhow!("File not found in storage"));
             }
         }
-        Commands::Push { peer_id } => {
+        Commands::Push { remote, branch, force } => {
             // Check if repository is initialized
             if !Path::new(".fai").exists() {
                 return Err(anyhow::anyhow!(
@@ -765,153 +1135,140 @@ async fn main() -> Result<()> {
                 ));
             }
 
-            // Parse peer ID
-            let target_peer = PeerId::from_str(&peer_id)
-                .map_err(|_| anyhow::anyhow!("Invalid peer ID format: {}", peer_id))?;
-
-            println!("Discovering peers...");
-            println!("DEBUG: About to create storage manager");
-
-            // Create storage manager
-            let storage = Arc::new(fai_protocol::storage::StorageManager::new(
-                Path::new(".fai").to_path_buf(),
-            )?);
-            println!("DEBUG: Storage manager created");
+            // Resolve the remote name, peer ID or address
+            let remote_spec = remote;
+            let remote = fai_protocol::network::remote::Remote::resolve(
+                &fai_protocol::config::RepoConfig::load(Path::new(".fai"))?,
+                &remote_spec,
+            )?;
+            let target_peer = remote.peer_id;
 
-            // Create database manager
+            // Push the branch head; the peer fetches what it lacks
             let database =
                 fai_protocol::database::DatabaseManager::new(&Path::new(".fai").join("db.sqlite"))?;
-            println!("DEBUG: Database manager created");
-
-            // Create network manager
-            println!("DEBUG: About to create network manager");
-            let mut network_manager =
-                match fai_protocol::network::NetworkManager::new(storage.clone(), database) {
-                    Ok(nm) => nm,
-                    Err(e) => {
-                        return Err(anyhow::anyhow!("Failed to create network manager: {}", e));
-                    }
-                };
-            println!("DEBUG: Network manager created");
-
-            // Start the network manager
-            println!("DEBUG: About to start network manager");
-            if let Err(e) = network_manager.start().await {
-                return Err(anyhow::anyhow!("Failed to start network manager: {}", e));
-            }
-            println!("DEBUG: Network manager started");
-
-            println!("Local peer ID: {}", network_manager.local_peer_id());
+            let branch = match branch {
+                Some(branch) => branch,
+                None => database.get_current_branch()?,
+            };
+            let head = database
+                .get_branch_head(&branch)?
+                .filter(|hash| hash != fai_protocol::database::EMPTY_COMMIT_HASH)
+                .ok_or_else(|| anyhow::anyhow!("Branch '{}' has no commits to push", branch))?;
+            let update = fai_protocol::network::RefUpdate {
+                branch: branch.clone(),
+                new: head,
+                force,
+            };
 
-            // Load peers from shared files for test discovery
-            if let Ok(loaded) = network_manager.load_peers_from_files() {
-                println!("Loaded {} peers from shared files", loaded);
-            }
+            let response = if let Some(mut daemon) =
+                fai_protocol::daemon::Client::connect(Path::new(".fai")).await?
+            {
+                println!(
+                    "Pushing {} ({}) to peer {} through the daemon...",
+                    branch,
+                    &update.new[..8.min(update.new.len())],
+                    remote.name
+                );
+                daemon
+                    .push(&remote_spec, vec![update])
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Push failed: {}", e))?
+            } else {
+                // Create storage manager
+                let storage = Arc::new(fai_protocol::storage::StorageManager::new(
+                    Path::new(".fai").to_path_buf(),
+                )?);
+
+                // Create database manager
+                let network_db =
+                    fai_protocol::database::DatabaseManager::new(&Path::new(".fai").join("db.sqlite"))?;
+
+                // Create and start the network manager
+                let identity = fai_protocol::network::identity::repo_identity(Path::new(".fai"))?;
+                let mut network_manager =
+                    fai_protocol::network::NetworkManager::new(storage.clone(), network_db, identity)
+                        .map_err(|e| anyhow::anyhow!("Failed to create network manager: {}", e))?;
+                network_manager
+                    .start()
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Failed to start network manager: {}", e))?;
 
-            // Discover peers for 10 seconds
-            let discovery_duration = std::time::Duration::from_secs(10);
+                println!("Local peer ID: {}", network_manager.local_peer_id());
 
-            println!(
-                "Discovering peers for {} seconds...",
-                discovery_duration.as_secs()
-            );
+                // Load peers seen in earlier sessions
+                let loaded = network_manager.load_address_book()?;
+                println!("Loaded {} peers from the address book", loaded);
 
-            // Simplified discovery loop
-            // while discovery_start.elapsed() < discovery_duration {
-            //     if let Err(e) = network_manager.poll_events().await {
-            //         eprintln!("Error during peer discovery: {}", e);
-            //     }
-            //     tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-            // }
+                // Dial the addresses configured for the remote
+                network_manager.add_remote(&remote);
 
-            // Check if target peer was discovered
-            let peers = network_manager.list_peers();
-            let target_peer_found = peers.iter().any(|p| p.peer_id == target_peer);
+                // Check if target peer is known
+                let peers = network_manager.list_peers();
+                let target_peer_found = peers.iter().any(|p| p.peer_id == target_peer);
 
-            if !target_peer_found {
-                println!(
-                    "Discovered {} peers, but target peer {} not found",
-                    peers.len(),
-                    peer_id
-                );
-                for peer in &peers {
-                    println!("  - {}", peer.peer_id);
+                if !target_peer_found {
+                    println!(
+                        "Discovered {} peers, but target peer {} not found",
+                        peers.len(),
+                        remote.name
+                    );
+                    for peer in &peers {
+                        println!("  - {}", peer.peer_id);
+                    }
+                    return Err(anyhow::anyhow!(
+                        "Peer {} not discovered in local network",
+                        remote.name
+                    ));
                 }
-                return Err(anyhow::anyhow!(
-                    "Peer {} not discovered in local network",
-                    peer_id
-                ));
-            }
 
-            println!("Found peer {}", peer_id);
+                println!("Found peer {}", remote.name);
 
-            // Check if target peer was discovered
-            let peers = network_manager.list_peers();
-            let target_peer_found = peers.iter().any(|p| p.peer_id == target_peer);
-
-            if !target_peer_found {
                 println!(
-                    "Discovered {} peers, but target peer {} not found",
-                    peers.len(),
-                    peer_id
+                    "Pushing {} ({}) to peer {}...",
+                    branch,
+                    &update.new[..8.min(update.new.len())],
+                    remote.name
                 );
-                for peer in &peers {
-                    println!("  - {}", peer.peer_id);
-                }
-                return Err(anyhow::anyhow!(
-                    "Peer {} not discovered in local network",
-                    peer_id
-                ));
-            }
-
-            // Get all commits to push
-            let commits = storage.get_all_commits()?;
+                network_manager
+                    .push(target_peer, &remote.name, vec![update])
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Push failed: {}", e))?
+            };
 
-            if commits.is_empty() {
-                println!("No commits to push");
-                return Ok(());
+            println!("To {}", remote.name);
+            for result in &response.results {
+                let name = result
+                    .ref_name
+                    .trim_start_matches("refs/heads/")
+                    .trim_start_matches("refs/tags/");
+                let is_tag = result.ref_name.starts_with("refs/tags/");
+                let new = &result.new[..8.min(result.new.len())];
+                let old = result.old.as_deref().map(|old| &old[..8.min(old.len())]).unwrap_or("");
+                match &result.status {
+                    RefStatus::Created if is_tag => println!(" * [new tag]         {} -> {}", name, name),
+                    RefStatus::Created => println!(" * [new branch]      {} -> {}", name, name),
+                    RefStatus::FastForward => println!("   {}..{}  {} -> {}", old, new, name, name),
+                    RefStatus::Forced => println!(" + {}...{} {} -> {} (forced update)", old, new, name, name),
+                    RefStatus::UpToDate => println!(" = [up to date]      {} -> {}", name, name),
+                    RefStatus::Rejected(reason) => println!(" ! [rejected]        {} -> {} ({})", name, name, reason),
+                }
             }
 
-            println!("Found {} commits to push", commits.len());
-
-            // Create commit info for network transfer (convert timestamp from i64 to proper format)
-            let commit_infos: Vec<fai_protocol::storage::CommitInfo> = commits
-                .into_iter()
-                .map(|c| fai_protocol::storage::CommitInfo {
-                    hash: c.hash,
-                    message: c.message,
-                    timestamp: c.timestamp,
-                    parents: c.parents,
-                    is_merge: c.is_merge,
-                })
-                .collect();
-
-            // Push commits to peer
-            println!("Pushing commits to peer {}...", peer_id);
-            println!("DEBUG: About to call network_manager.send_commits");
-
-            // Actually send commits to the peer
-            match network_manager
-                .send_commits(target_peer.clone(), commit_infos.clone())
-                .await
-            {
-                Ok(_) => {
-                    println!(
-                        "✓ Successfully pushed {} commits to peer {}",
-                        commit_infos.len(),
-                        peer_id
-                    );
-                }
-                Err(e) => {
-                    eprintln!("Failed to push commits: {}", e);
-                    return Err(anyhow::anyhow!("Push failed: {}", e));
-                }
+            if response.results.iter().any(|result| !result.is_accepted()) {
+                return Err(anyhow::anyhow!("Failed to push some refs to {}", remote.name));
             }
         }
         Commands::Pull {
-            peer_id,
-            commit_hash,
+            remote,
+            branch,
+            commit,
+            abort,
         } => {
+            if abort {
+                services::CliService::new(".").handle_pull_abort()?;
+                return Ok(());
+            }
+
             // Check if repository is initialized
             if !Path::new(".fai").exists() {
                 return Err(anyhow::anyhow!(
@@ -919,11 +1276,14 @@ async fn main() -> Result<()> {
                 ));
             }
 
-            // Parse peer ID
-            let target_peer = PeerId::from_str(&peer_id)
-                .map_err(|_| anyhow::anyhow!("Invalid peer ID format: {}", peer_id))?;
+            // Resolve the remote name, peer ID or address
+            let remote = fai_protocol::network::remote::Remote::resolve(
+                &fai_protocol::config::RepoConfig::load(Path::new(".fai"))?,
+                &remote.ok_or_else(|| anyhow::anyhow!("No remote given"))?,
+            )?;
+            let target_peer = remote.peer_id;
 
-            println!("Pulling commits from peer {}...", peer_id);
+            println!("Pulling commits from peer {}...", remote.name);
 
             // Create storage manager
             let storage = Arc::new(fai_protocol::storage::StorageManager::new(
@@ -935,8 +1295,9 @@ async fn main() -> Result<()> {
             let database = fai_protocol::database::DatabaseManager::new(&Path::new(".fai").join("db.sqlite"))?;
 
             // Create network manager using network_db (to avoid move issues)
+            let identity = fai_protocol::network::identity::repo_identity(Path::new(".fai"))?;
             let mut network_manager =
-                match fai_protocol::network::NetworkManager::new(storage.clone(), network_db) {
+                match fai_protocol::network::NetworkManager::new(storage.clone(), network_db, identity) {
                     Ok(nm) => nm,
                     Err(e) => {
                         return Err(anyhow::anyhow!("Failed to create network manager: {}", e));
@@ -950,10 +1311,12 @@ async fn main() -> Result<()> {
 
             println!("Local peer ID: {}", network_manager.local_peer_id());
 
-            // Load peers from shared files for test discovery
-            if let Ok(loaded) = network_manager.load_peers_from_files() {
-                println!("Loaded {} peers from shared files", loaded);
-            }
+            // Load peers seen in earlier sessions
+            let loaded = network_manager.load_address_book()?;
+            println!("Loaded {} peers from the address book", loaded);
+
+            // Dial the addresses configured for the remote
+            network_manager.add_remote(&remote);
 
             // Discover peers for 10 seconds
             let discovery_duration = std::time::Duration::from_secs(10);
@@ -979,99 +1342,168 @@ async fn main() -> Result<()> {
                 println!(
                     "Discovered {} peers, but target peer {} not found",
                     peers.len(),
-                    peer_id
+                    remote.name
                 );
                 for peer in &peers {
                     println!("  - {}", peer.peer_id);
                 }
                 return Err(anyhow::anyhow!(
                     "Peer {} not discovered in local network",
-                    peer_id
+                    remote.name
                 ));
             }
 
-            println!("Found peer {}", peer_id);
+            println!("Found peer {}", remote.name);
+
+            // Fetch the requested remote branch, or the current branch's upstream on this
+            // remote; everything if neither is known
+            let current_branch = database.get_current_branch()?;
+            let branch = match branch {
+                Some(branch) => Some(branch),
+                None => fai_protocol::config::RepoConfig::load(Path::new(".fai"))?
+                    .branches
+                    .upstream
+                    .get(&current_branch)
+                    .filter(|upstream| upstream.remote == remote.name)
+                    .map(|upstream| upstream.branch.clone()),
+            };
+            let want = match (commit, &branch) {
+                (Some(commit), _) => Some(commit),
+                (None, Some(branch)) => {
+                    let advertised = network_manager.list_remote_refs(target_peer).await?;
+                    let target = advertised.branch(branch).ok_or_else(|| {
+                        anyhow::anyhow!("Couldn't find remote ref '{}' on {}", branch, remote.name)
+                    })?;
+                    Some(target.to_string())
+                }
+                (None, None) => None,
+            };
+
+            // Journal the transfer so an interrupted pull can be resumed or aborted
+            if let Some(transfer) = database.begin_transfer(&remote.name, &remote.peer_id.to_string())? {
+                println!(
+                    "Resuming interrupted pull from {} ({} of the objects needed already verified)",
+                    transfer.remote,
+                    transfer.verified_objects.len()
+                );
+            }
 
             // Request commits from peer
-            println!("Requesting commits from peer {}...", peer_id);
+            println!("Requesting commits from peer {}...", remote.name);
             println!("DEBUG: About to call network_manager.request_commits");
             let commits = network_manager
-                .request_commits(target_peer.clone(), commit_hash.clone())
+                .request_commits(target_peer, &remote.name, want.clone())
                 .await?;
             println!("DEBUG: request_commits returned");
 
             if commits.is_empty() {
                 println!("No new commits to pull");
-                return Ok(());
+            } else {
+                println!("Found {} commits to pull", commits.len());
             }
 
-            println!("Found {} commits to pull", commits.len());
-
-            // For each commit, pull the files
+            let mut file_hashes = Vec::new();
             for commit in &commits {
-                println!("Pulling commit: {} - {}", &commit.hash[..8], commit.message);
-
-                // Get files for this commit from database
-                let commit_files = match database.get_commit_files(&commit.hash) {
-                    Ok(files) => files,
-                    Err(e) => {
-                        println!("Warning: Could not get files for commit {}: {}", &commit.hash[..8], e);
-                        continue;
+                for (_, file_hash, _) in database.get_commit_files(&commit.hash)? {
+                    if !file_hashes.contains(&file_hash) {
+                        file_hashes.push(file_hash);
                     }
-                };
-
-                // Download all files referenced in this commit
-                for (file_path, file_hash, file_size) in commit_files {
-                    println!("  Fetching file {}...", &file_hash[..8]);
-
-                    // Check if we already have this file
-                    if storage.retrieve(&file_hash).is_ok() {
-                        println!("  ✓ Already have file {}", &file_hash[..8]);
-                        continue;
-                    }
-
-                    // Download the file (reuse fetch logic)
-                    match network_manager
-                        .request_chunk(target_peer.clone(), &file_hash)
-                        .await
-                    {
-                        Ok(Some(data)) => {
-                            storage.store(&data)?;
-                            println!(
-                                "  ✓ Downloaded file {} ({} bytes)",
-                                &file_hash[..8],
-                                data.len()
-                            );
-                        }
-                        Ok(None) => {
-                            println!("  ✗ File {} not available", &file_hash[..8]);
-                        }
-                        Err(e) => {
-                            println!("  ✗ Failed to download file {}: {}", &file_hash[..8], e);
-                        }
+                }
+            }
+            let commit_hashes: Vec<String> = commits.iter().map(|commit| commit.hash.clone()).collect();
+            database.add_to_transfer(
+                want.as_deref().or(commit_hashes.first().map(String::as_str)),
+                &commit_hashes,
+                &file_hashes,
+            )?;
+
+            // Download from the remote and every other peer we're connected to
+            network_manager.set_transfer_config(
+                fai_protocol::config::RepoConfig::load(Path::new(".fai"))?.transfer,
+            );
+            let mut seeders = network_manager.download_peers(target_peer);
+            if let Some(target) = database.get_transfer()?.and_then(|transfer| transfer.target) {
+                for provider in network_manager
+                    .find_providers(fai_protocol::network::Content::Commit(&target))
+                    .await?
+                {
+                    if !seeders.contains(&provider) {
+                        seeders.push(provider);
                     }
                 }
+            }
+            if seeders.len() > 1 {
+                println!("Downloading from {} peers", seeders.len());
+            }
 
-                // Save the commit to local database
+            // Save the commits; peers send them newest first, so parents go before their children
+            for commit in commits.iter().rev() {
                 storage.save_remote_commit(commit)?;
-                println!("✓ Pulled commit: {}", &commit.hash[..8]);
+                println!("✓ Pulled commit: {} - {}", &commit.hash[..8], commit.message);
+            }
+
+            // Download the objects of every file the transfer needs that we don't have yet,
+            // including files of commits received by an interrupted earlier run
+            let transfer = database
+                .get_transfer()?
+                .ok_or_else(|| anyhow::anyhow!("Transfer journal disappeared during pull"))?;
+            let mut failed = 0;
+            for file_hash in &transfer.files {
+                match network_manager.fetch_file(&seeders, file_hash).await {
+                    Ok(0) => println!("  ✓ Already have file {}", &file_hash[..8]),
+                    Ok(objects) => println!("  ✓ Downloaded file {} ({} objects)", &file_hash[..8], objects),
+                    Err(e) => {
+                        println!("  ✗ Failed to download file {}: {}", &file_hash[..8], e);
+                        failed += 1;
+                    }
+                }
+            }
+            if failed > 0 {
+                return Err(anyhow::anyhow!(
+                    "{} file(s) could not be downloaded; run 'fai pull {}' again to resume or 'fai pull --abort' to discard the transfer",
+                    failed,
+                    remote.name
+                ));
             }
+            database.finish_transfer()?;
 
-            println!("✓ Pull complete! Pulled {} commits", commits.len());
+            println!("✓ Pull complete! Pulled {} commits", transfer.commits.len());
+
+            // Merge the fetched remote branch
+            if let Some(branch) = branch {
+                if database.get_remote_ref(&remote.name, &branch)?.is_none() {
+                    return Err(anyhow::anyhow!(
+                        "Couldn't find remote ref '{}' on {}",
+                        branch,
+                        remote.name
+                    ));
+                }
+                let tracking = format!("{}/{}", remote.name, branch);
+                println!("Merging {} into {}...", tracking, current_branch);
+                services::CliService::new(".").handle_merge_command(&tracking, false, false, None)?;
+            }
         }
-        Commands::Clone { peer_id, directory } => {
-            println!("Cloning repository from peer {}...", peer_id);
+        Commands::Clone { remote, directory } => {
+            println!("Cloning repository from {}...", remote);
 
-            // Parse peer ID
-            let target_peer = PeerId::from_str(&peer_id)
-                .map_err(|_| anyhow::anyhow!("Invalid peer ID format: {}", peer_id))?;
+            // The peer becomes the "origin" remote of the new repository
+            let mut remote = fai_protocol::network::remote::Remote::resolve(
+                &fai_protocol::config::RepoConfig::default(),
+                &remote,
+            )?;
+            remote.name = fai_protocol::network::remote::DEFAULT_REMOTE.to_string();
+            let target_peer = remote.peer_id;
 
             // Determine target directory
             let target_dir = directory.unwrap_or_else(|| ".".to_string());
             let repo_path = std::path::Path::new(&target_dir).join(".fai");
 
-            // Check if repo already exists
-            if repo_path.exists() {
+            // Check if repo already exists, unless it is an interrupted clone from the same peer
+            let resuming = repo_path.join("db.sqlite").exists()
+                && fai_protocol::database::DatabaseManager::new(&repo_path.join("db.sqlite"))?
+                    .get_transfer()?
+                    .is_some_and(|transfer| transfer.peer_id == remote.peer_id.to_string());
+            if repo_path.exists() && !resuming {
                 return Err(anyhow::anyhow!(
                     "Repository already exists at {}",
                     repo_path.display()
@@ -1089,7 +1521,16 @@ async fn main() -> Result<()> {
 
             // Create the .fai directory structure
             let fai_path = repo_path.clone();
-            std::fs::create_dir_all(fai_path.join("objects"))?;
+            let mut config = if resuming {
+                println!("Resuming interrupted clone...");
+                fai_protocol::config::RepoConfig::load(&fai_path)?
+            } else {
+                std::fs::create_dir_all(fai_path.join("objects"))?;
+                let mut config = fai_protocol::config::RepoConfig::default();
+                config.remotes.insert(remote.name.clone(), remote.to_config());
+                config.save(&fai_path)?;
+                config
+            };
 
             // Create storage manager
             let storage = Arc::new(fai_protocol::storage::StorageManager::new(
@@ -1101,22 +1542,26 @@ async fn main() -> Result<()> {
                 fai_protocol::database::DatabaseManager::new(&fai_path.join("db.sqlite"))?;
 
             // Initialize network
+            let identity = fai_protocol::network::identity::repo_identity(&fai_path)?;
             let mut network_manager =
-                match fai_protocol::network::NetworkManager::new(storage.clone(), database) {
+                match fai_protocol::network::NetworkManager::new(storage.clone(), database, identity) {
                     Ok(nm) => nm,
                     Err(e) => {
                         return Err(anyhow::anyhow!("Failed to create network manager: {}", e));
                     }
                 };
+            network_manager.set_transfer_config(config.transfer.clone());
 
             network_manager.start().await?;
 
             println!("Local peer ID: {}", network_manager.local_peer_id());
 
-            // Load peers from shared files for test discovery
-            if let Ok(loaded) = network_manager.load_peers_from_files() {
-                println!("Loaded {} peers from shared files", loaded);
-            }
+            // Load peers seen in earlier sessions
+            let loaded = network_manager.load_address_book()?;
+            println!("Loaded {} peers from the address book", loaded);
+
+            // Dial the address given on the command line, if any
+            network_manager.add_remote(&remote);
 
             // Discover peer
             println!("Discovering peer...");
@@ -1137,79 +1582,91 @@ async fn main() -> Result<()> {
                 println!(
                     "Discovered {} peers, but target peer {} not found",
                     peers.len(),
-                    peer_id
+                    remote.peer_id
                 );
                 for peer in &peers {
                     println!("  - {}", peer.peer_id);
                 }
                 return Err(anyhow::anyhow!(
                     "Peer {} not discovered in local network",
-                    peer_id
+                    remote.peer_id
                 ));
             }
 
-            println!("Found peer {}", peer_id);
+            println!("Found peer {}", remote.peer_id);
+
+            // Ask for the peer's branches to pick the one to check out
+            let advertised = network_manager.list_remote_refs(target_peer).await?;
+            let Some((default_branch, head_target)) = advertised
+                .default_branch()
+                .map(|(branch, target)| (branch.to_string(), target.to_string()))
+            else {
+                println!("⚠️  Peer has no commits");
+                return Ok(());
+            };
+
+            // Journal the transfer so an interrupted clone can be resumed
+            let clone_db =
+                fai_protocol::database::DatabaseManager::new(&fai_path.join("db.sqlite"))?;
+            clone_db.begin_transfer(&remote.name, &remote.peer_id.to_string())?;
 
             // Request ALL commits from peer
             println!("Fetching commit history...");
             let commits = network_manager
-                .request_commits(target_peer.clone(), None)
+                .request_commits(target_peer, &remote.name, None)
                 .await?;
 
-            if commits.is_empty() {
+            if commits.is_empty() && !resuming {
+                clone_db.finish_transfer()?;
                 println!("⚠️  Peer has no commits");
                 return Ok(());
             }
 
             println!("Found {} commits to clone", commits.len());
 
-            // Collect all unique file hashes across all commits
-            // Note: In v0.3.0, files are stored separately from commits
-            // We'll need to fetch file info from the remote peer
-            let mut all_file_hashes: std::collections::HashSet<String> =
-                std::collections::HashSet::new();
-
-            // For now, we'll clone all files by requesting file info for each commit
-            // This is a simplified approach for v0.3.0 migration
+            // Every commit is a full snapshot, so the union of all snapshots is
+            // the set of objects needed to check out any point in history
+            let mut file_hashes = Vec::new();
             for commit in &commits {
-                // In a full implementation, we'd fetch file lists from remote
-                // For now, we'll use a placeholder since the network protocol needs updating
-                println!("Note: Commit {} files need to be fetched from remote", &commit.hash[..8]);
+                for (_, file_hash, _) in clone_db.get_commit_files(&commit.hash)? {
+                    if !file_hashes.contains(&file_hash) {
+                        file_hashes.push(file_hash);
+                    }
+                }
             }
+            let commit_hashes: Vec<String> = commits.iter().map(|commit| commit.hash.clone()).collect();
+            clone_db.add_to_transfer(Some(&head_target), &commit_hashes, &file_hashes)?;
+            let transfer = clone_db
+                .get_transfer()?
+                .ok_or_else(|| anyhow::anyhow!("Transfer journal disappeared during clone"))?;
+            let all_file_hashes = &transfer.files;
 
             println!("Downloading {} unique files...", all_file_hashes.len());
+            let seeders = network_manager.download_peers(target_peer);
 
             // Download all files if we have any
             let mut downloaded = 0;
             if all_file_hashes.is_empty() {
                 println!("No files to download - commits may not contain file references");
             } else {
-                for file_hash in &all_file_hashes {
-                print!(
-                    "  Downloading file {}/{} ({})... ",
-                    downloaded + 1,
-                    all_file_hashes.len(),
-                    &file_hash[..8]
-                );
+                for (i, file_hash) in all_file_hashes.iter().enumerate() {
+                    print!(
+                        "  Downloading file {}/{} ({})... ",
+                        i + 1,
+                        all_file_hashes.len(),
+                        &file_hash[..8]
+                    );
 
-                match network_manager
-                    .request_chunk(target_peer.clone(), &file_hash)
-                    .await
-                {
-                    Ok(Some(data)) => {
-                        storage.store(&data)?;
-                        println!("✓ {} bytes", data.len());
-                        downloaded += 1;
-                    }
-                    Ok(None) => {
-                        println!("✗ Not available");
-                    }
-                    Err(e) => {
-                        println!("✗ Failed: {}", e);
+                    match network_manager.fetch_file(&seeders, file_hash).await {
+                        Ok(objects) => {
+                            println!("✓ {} objects", objects);
+                            downloaded += 1;
+                        }
+                        Err(e) => {
+                            println!("✗ Failed: {}", e);
+                        }
                     }
                 }
-                downloaded += 1;
-            }
             }
 
             println!(
@@ -1217,10 +1674,18 @@ async fn main() -> Result<()> {
                 downloaded,
                 all_file_hashes.len()
             );
+            if downloaded < all_file_hashes.len() {
+                return Err(anyhow::anyhow!(
+                    "{} file(s) could not be downloaded; run the clone again to resume or 'fai pull --abort' in {} to discard it",
+                    all_file_hashes.len() - downloaded,
+                    target_dir
+                ));
+            }
 
             // Save all commits to local database
             println!("Importing commit history...");
-            for (i, commit) in commits.iter().enumerate() {
+            // Peers send commits newest first; save parents before their children
+            for (i, commit) in commits.iter().rev().enumerate() {
                 storage.save_remote_commit(commit)?;
                 println!(
                     "  Imported commit {}/{}: {} - {}",
@@ -1231,11 +1696,82 @@ async fn main() -> Result<()> {
                 );
             }
 
+            // Check out the peer's current branch under the same name
+            if default_branch != "main" && !clone_db.branch_exists(&default_branch)? {
+                clone_db.rename_branch("main", &default_branch)?;
+            }
+            clone_db.update_branch_head_with_reason(
+                &default_branch,
+                &head_target,
+                &format!("clone: from {}", remote.peer_id),
+            )?;
+            std::fs::write(fai_path.join("HEAD"), format!("ref: refs/heads/{}", default_branch))?;
+
+            // Track the peer's branch
+            if clone_db.get_remote_ref(&remote.name, &default_branch)?.is_some() {
+                config.branches.upstream.insert(
+                    default_branch.clone(),
+                    fai_protocol::config::Upstream {
+                        remote: remote.name.clone(),
+                        branch: default_branch.clone(),
+                    },
+                );
+                config.save(&fai_path)?;
+            }
+
+            clone_db.finish_transfer()?;
+
             println!("\n✓ Clone complete!");
             println!("  Repository: {}", repo_path.display());
-            println!("  Commits: {}", commits.len());
+            println!("  Branch: {}", default_branch);
+            println!("  Commits: {}", transfer.commits.len());
             println!("  Files: {}", downloaded);
         }
+        Commands::LsRemote { remote, heads, tags } => {
+            // Check if repository is initialized
+            if !Path::new(".fai").exists() {
+                return Err(anyhow::anyhow!(
+                    "Not a FAI repository. Run 'fai init' first."
+                ));
+            }
+
+            // Resolve the remote name, peer ID or address
+            let remote = fai_protocol::network::remote::Remote::resolve(
+                &fai_protocol::config::RepoConfig::load(Path::new(".fai"))?,
+                &remote,
+            )?;
+
+            let storage = Arc::new(fai_protocol::storage::StorageManager::new(
+                Path::new(".fai").to_path_buf(),
+            )?);
+            let network_db = fai_protocol::database::DatabaseManager::new(&Path::new(".fai").join("db.sqlite"))?;
+            let identity = fai_protocol::network::identity::repo_identity(Path::new(".fai"))?;
+            let mut network_manager =
+                fai_protocol::network::NetworkManager::new(storage, network_db, identity)
+                    .map_err(|e| anyhow::anyhow!("Failed to create network manager: {}", e))?;
+            network_manager
+                .start()
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to start network manager: {}", e))?;
+
+            // Load peers seen in earlier sessions and dial the remote's addresses
+            network_manager.load_address_book()?;
+            network_manager.add_remote(&remote);
+
+            let advertised = network_manager.list_remote_refs(remote.peer_id).await?;
+            let show_all = !heads && !tags;
+            if show_all {
+                if let Some(target) = advertised.head_target() {
+                    println!("{}\tHEAD", target);
+                }
+            }
+            for advertised_ref in &advertised.refs {
+                let is_head = advertised_ref.name.starts_with("refs/heads/");
+                if show_all || (heads && is_head) || (tags && !is_head) {
+                    println!("{}\t{}", advertised_ref.target, advertised_ref.name);
+                }
+            }
+        }
         Commands::Diff { hash1, hash2 } => {
             // Check if repository is initialized
             if !Path::new(".fai").exists() {
@@ -1249,9 +1785,7 @@ async fn main() -> Result<()> {
             println!("  Version 2: {}", &hash2[..8]);
             println!();
 
-            // Create storage and database managers
-            let storage =
-                fai_protocol::storage::StorageManager::new(Path::new(".fai").to_path_buf())?;
+            // Create database manager
             let database =
                 fai_protocol::database::DatabaseManager::new(&Path::new(".fai").join("db.sqlite"))?;
 
@@ -1319,49 +1853,68 @@ async fn main() -> Result<()> {
             println!();
             println!("=== Changes ===");
 
-            // Convert to HashSets for comparison (use only file hashes)
-            let files1_hashes: std::collections::HashSet<_> =
-                files1.iter().map(|(_, hash, _)| hash).collect();
-            let files2_hashes: std::collections::HashSet<_> =
-                files2.iter().map(|(_, hash, _)| hash).collect();
-
-            // Files only in commit1 (removed in commit2)
-            let removed: Vec<_> = files1_hashes.difference(&files2_hashes).collect();
+            // Index both snapshots by path for comparison
+            let files1_by_path: std::collections::BTreeMap<_, _> = files1
+                .iter()
+                .map(|(path, hash, size)| (path.as_str(), (hash.as_str(), *size)))
+                .collect();
+            let files2_by_path: std::collections::BTreeMap<_, _> = files2
+                .iter()
+                .map(|(path, hash, size)| (path.as_str(), (hash.as_str(), *size)))
+                .collect();
 
-            // Files only in commit2 (added in commit2)
-            let added: Vec<_> = files2_hashes.difference(&files1_hashes).collect();
+            let mut added = Vec::new();
+            let mut removed = Vec::new();
+            let mut modified = Vec::new();
+            let mut unchanged = Vec::new();
 
-            // Files in both (unchanged)
-            let unchanged: Vec<_> = files1_hashes.intersection(&files2_hashes).collect();
+            for (path, (hash, size)) in &files1_by_path {
+                match files2_by_path.get(path) {
+                    None => removed.push((*path, *hash, *size)),
+                    Some((new_hash, new_size)) if new_hash != hash => {
+                        modified.push((*path, *hash, *size, *new_hash, *new_size))
+                    }
+                    Some(_) => unchanged.push((*path, *hash)),
+                }
+            }
+            for (path, (hash, size)) in &files2_by_path {
+                if !files1_by_path.contains_key(path) {
+                    added.push((*path, *hash, *size));
+                }
+            }
 
             if !removed.is_empty() {
                 println!("\n❌ Removed files ({}):", removed.len());
-                for file_hash in &removed {
-                    // Try to get file size
-                    if let Ok(data) = storage.retrieve(file_hash) {
-                        println!("  - {} ({} bytes)", &file_hash[..8], data.len());
-                    } else {
-                        println!("  - {}", &file_hash[..8]);
-                    }
+                for (path, hash, size) in &removed {
+                    println!("  - {} ({}, {} bytes)", path, &hash[..8], size);
                 }
             }
 
             if !added.is_empty() {
                 println!("\n✅ Added files ({}):", added.len());
-                for file_hash in &added {
-                    // Try to get file size
-                    if let Ok(data) = storage.retrieve(file_hash) {
-                        println!("  + {} ({} bytes)", &file_hash[..8], data.len());
-                    } else {
-                        println!("  + {}", &file_hash[..8]);
-                    }
+                for (path, hash, size) in &added {
+                    println!("  + {} ({}, {} bytes)", path, &hash[..8], size);
+                }
+            }
+
+            if !modified.is_empty() {
+                println!("\n📝 Modified files ({}):", modified.len());
+                for (path, old_hash, old_size, new_hash, new_size) in &modified {
+                    println!(
+                        "  ~ {} ({} -> {}, {} -> {} bytes)",
+                        path,
+                        &old_hash[..8],
+                        &new_hash[..8],
+                        old_size,
+                        new_size
+                    );
                 }
             }
 
             if !unchanged.is_empty() {
                 println!("\n⚪ Unchanged files ({}):", unchanged.len());
-                for file_hash in unchanged.iter().take(5) {
-                    println!("  = {}", &file_hash[..8]);
+                for (path, hash) in unchanged.iter().take(5) {
+                    println!("  = {} ({})", path, &hash[..8]);
                 }
                 if unchanged.len() > 5 {
                     println!("  ... and {} more", unchanged.len() - 5);
@@ -1373,20 +1926,13 @@ async fn main() -> Result<()> {
             println!("=== Summary ===");
             println!("  Added:     {} files", added.len());
             println!("  Removed:   {} files", removed.len());
+            println!("  Modified:  {} files", modified.len());
             println!("  Unchanged: {} files", unchanged.len());
 
-            // Calculate total size change
-            let mut size_change: i64 = 0;
-            for file_hash in &added {
-                if let Ok(data) = storage.retrieve(file_hash) {
-                    size_change += data.len() as i64;
-                }
-            }
-            for file_hash in &removed {
-                if let Ok(data) = storage.retrieve(file_hash) {
-                    size_change -= data.len() as i64;
-                }
-            }
+            // Calculate total size change from the recorded file sizes
+            let size1: i64 = files1.iter().map(|(_, _, size)| *size as i64).sum();
+            let size2: i64 = files2.iter().map(|(_, _, size)| *size as i64).sum();
+            let size_change = size2 - size1;
 
             if size_change > 0 {
                 println!(
@@ -1404,7 +1950,7 @@ async fn main() -> Result<()> {
                 println!("  Size:      No change");
             }
         }
-        Commands::Serve => {
+        Commands::Serve { addresses, limits } => {
             // Check if repository is initialized
             if !Path::new(".fai").exists() {
                 return Err(anyhow::anyhow!(
@@ -1413,6 +1959,7 @@ async fn main() -> Result<()> {
             }
 
             println!("FAI server starting...");
+            let config = fai_protocol::config::RepoConfig::load(Path::new(".fai"))?;
 
             // Create storage manager
             let storage = Arc::new(fai_protocol::storage::StorageManager::new(
@@ -1424,13 +1971,17 @@ async fn main() -> Result<()> {
                 fai_protocol::database::DatabaseManager::new(&Path::new(".fai").join("db.sqlite"))?;
 
             // Create network manager
+            let identity = fai_protocol::network::identity::repo_identity(Path::new(".fai"))?;
             let mut network_manager =
-                match fai_protocol::network::NetworkManager::new(storage.clone(), database) {
+                match fai_protocol::network::NetworkManager::new(storage.clone(), database, identity) {
                     Ok(nm) => nm,
                     Err(e) => {
                         return Err(anyhow::anyhow!("Failed to create network manager: {}", e));
                     }
                 };
+            network_manager.set_transfer_config(limits.apply(config.transfer));
+            network_manager.set_network_config(addresses.apply(config.network));
+            network_manager.set_receive_config(config.receive);
 
             // Start the network manager
             if let Err(e) = network_manager.start().await {
@@ -1439,27 +1990,252 @@ async fn main() -> Result<()> {
 
             println!("FAI server started");
             println!("Local peer ID: {}", network_manager.local_peer_id());
+            for addr in network_manager.external_addresses() {
+                println!("Announcing external address {}", addr);
+            }
+
+            // Join the DHT through peers seen in earlier sessions and announce what we host
+            let loaded = network_manager.load_address_book()?;
+            println!("Loaded {} peers from the address book", loaded);
+            let announced = network_manager.provide_local_content()?;
+            println!("Announcing {} commits and files in the DHT", announced);
             println!("Ready to serve chunks...");
             println!("Press Ctrl+C to stop");
 
-            // Run event loop indefinitely
+            // Run event loop indefinitely; polling waits for events, and
+            // the transfer limits pace the uploads
             loop {
                 if let Err(e) = network_manager.poll_events().await {
                     eprintln!("Error during event polling: {}", e);
                 }
+            }
+        }
+        Commands::Daemon { action, addresses, limits } => {
+            // Check if repository is initialized
+            if !Path::new(".fai").exists() {
+                return Err(anyhow::anyhow!(
+                    "Not a FAI repository. Run 'fai init' first."
+                ));
+            }
+
+            match action.unwrap_or(DaemonAction::Start) {
+                DaemonAction::Status => {
+                    let Some(mut daemon) =
+                        fai_protocol::daemon::Client::connect(Path::new(".fai")).await?
+                    else {
+                        println!("No daemon is running");
+                        return Ok(());
+                    };
+                    let status = daemon.status().await?;
+                    println!(
+                        "Daemon running as {} since {}",
+                        status.peer_id,
+                        status
+                            .started_at
+                            .with_timezone(&chrono::Local)
+                            .format("%Y-%m-%d %H:%M:%S")
+                    );
+                    for addr in &status.listeners {
+                        println!("Listening on {}", addr);
+                    }
+                    println!("Connected peers: {}", status.connected_peers);
+                    match &status.current {
+                        Some(current) if status.progress.objects_total > 0 => println!(
+                            "Busy: {} ({}/{} objects)",
+                            current, status.progress.objects_done, status.progress.objects_total
+                        ),
+                        Some(current) => println!("Busy: {}", current),
+                        None => println!("Idle"),
+                    }
+                    if status.queued > 0 {
+                        println!("Queued requests: {}", status.queued);
+                    }
+                }
+                DaemonAction::Stop => {
+                    match fai_protocol::daemon::Client::connect(Path::new(".fai")).await? {
+                        Some(mut daemon) => {
+                            daemon.stop().await?;
+                            println!("Stopped the daemon");
+                        }
+                        None => println!("No daemon is running"),
+                    }
+                }
+                DaemonAction::Start if !cfg!(unix) => {
+                    return Err(anyhow::anyhow!(
+                        "fai daemon needs Unix domain sockets and is not supported on this platform"
+                    ));
+                }
+                DaemonAction::Start => {
+                    println!("FAI daemon starting...");
+                    let config = fai_protocol::config::RepoConfig::load(Path::new(".fai"))?;
+
+                    let storage = Arc::new(fai_protocol::storage::StorageManager::new(
+                        Path::new(".fai").to_path_buf(),
+                    )?);
+                    let database = fai_protocol::database::DatabaseManager::new(
+                        &Path::new(".fai").join("db.sqlite"),
+                    )?;
+
+                    let identity =
+                        fai_protocol::network::identity::repo_identity(Path::new(".fai"))?;
+                    let mut network_manager =
+                        fai_protocol::network::NetworkManager::new(storage, database, identity)
+                            .map_err(|e| {
+                                anyhow::anyhow!("Failed to create network manager: {}", e)
+                            })?;
+                    network_manager.set_transfer_config(limits.apply(config.transfer));
+                    network_manager.set_network_config(addresses.apply(config.network));
+                    network_manager.set_receive_config(config.receive);
+                    network_manager
+                        .start()
+                        .await
+                        .map_err(|e| anyhow::anyhow!("Failed to start network manager: {}", e))?;
+
+                    println!("Local peer ID: {}", network_manager.local_peer_id());
+                    for addr in network_manager.external_addresses() {
+                        println!("Announcing external address {}", addr);
+                    }
+
+                    // Join the DHT through peers seen in earlier sessions and announce what we host
+                    let loaded = network_manager.load_address_book()?;
+                    println!("Loaded {} peers from the address book", loaded);
+                    let announced = network_manager.provide_local_content()?;
+                    println!("Announcing {} commits and files in the DHT", announced);
+                    println!("FAI daemon started; stop it with 'fai daemon stop' or Ctrl+C");
+
+                    fai_protocol::daemon::run(network_manager, Path::new(".fai")).await?;
+                }
+            }
+        }
+        Commands::Relay { addresses } => {
+            // Check if repository is initialized
+            if !Path::new(".fai").exists() {
+                return Err(anyhow::anyhow!(
+                    "Not a FAI repository. Run 'fai init' first."
+                ));
+            }
+
+            println!("FAI relay starting...");
+            let config = fai_protocol::config::RepoConfig::load(Path::new(".fai"))?;
+
+            let storage = Arc::new(fai_protocol::storage::StorageManager::new(
+                Path::new(".fai").to_path_buf(),
+            )?);
+            let database =
+                fai_protocol::database::DatabaseManager::new(&Path::new(".fai").join("db.sqlite"))?;
+
+            // The relay keeps the repository's identity, so its address stays stable
+            let identity = fai_protocol::network::identity::repo_identity(Path::new(".fai"))?;
+            let mut network_manager =
+                fai_protocol::network::NetworkManager::new_relay(storage, database, identity)
+                    .map_err(|e| anyhow::anyhow!("Failed to create network manager: {}", e))?;
+            network_manager.set_network_config(fai_protocol::config::NetworkConfig {
+                relays: Vec::new(),
+                ..addresses.apply(config.network)
+            });
+            network_manager
+                .start()
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to start network manager: {}", e))?;
+
+            let local_peer_id = network_manager.local_peer_id();
+            println!("FAI relay started");
+            println!("Local peer ID: {}", local_peer_id);
+            println!("Peers behind NAT can add one of these to [network] relays:");
+            for addr in network_manager.listeners() {
+                println!("  {}/p2p/{}", addr, local_peer_id);
+            }
+            println!("Press Ctrl+C to stop");
 
-                // Small delay to prevent busy-waiting
-                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            loop {
+                if let Err(e) = network_manager.poll_events().await {
+                    eprintln!("Error during event polling: {}", e);
+                }
             }
         }
-        Commands::Branch { branch_name, delete, list } => {
+        Commands::Id { encrypt, decrypt } => {
             let cli_service = services::CliService::new(".");
-            cli_service.handle_branch_command(branch_name, delete, list)?;
+            cli_service.handle_id_command(encrypt, decrypt)?;
+        }
+        Commands::Branch {
+            branch_name,
+            delete,
+            force,
+            rename,
+            list,
+            verbose,
+            remotes,
+            set_upstream_to,
+            unset_upstream,
+        } => {
+            let cli_service = services::CliService::new(".");
+            if set_upstream_to.is_some() || unset_upstream {
+                cli_service.handle_set_upstream_command(branch_name, set_upstream_to.as_deref())?;
+            } else if remotes {
+                cli_service.handle_remote_branches_command()?;
+            } else {
+                cli_service.handle_branch_command(branch_name, delete, force, rename, list, verbose)?;
+            }
         }
         Commands::Checkout { branch_name } => {
             let cli_service = services::CliService::new(".");
             cli_service.handle_checkout_command(&branch_name)?;
         }
+        Commands::Merge { branch_name, ours, theirs, message } => {
+            let cli_service = services::CliService::new(".");
+            cli_service.handle_merge_command(&branch_name, ours, theirs, message)?;
+        }
+        Commands::CherryPick { rev, ours, theirs } => {
+            let cli_service = services::CliService::new(".");
+            cli_service.handle_cherry_pick_command(&rev, ours, theirs)?;
+        }
+        Commands::Tag { tag_name, rev, message, list, delete } => {
+            let cli_service = services::CliService::new(".");
+            cli_service.handle_tag_command(tag_name, rev, message, list, delete)?;
+        }
+        Commands::Reflog { ref_name, all, limit } => {
+            let cli_service = services::CliService::new(".");
+            let ref_name = if all { None } else { Some(ref_name.unwrap_or_else(|| "HEAD".to_string())) };
+            cli_service.handle_reflog_command(ref_name.as_deref(), limit)?;
+        }
+        Commands::Gc { dry_run } => {
+            let cli_service = services::CliService::new(".");
+            cli_service.handle_gc_command(dry_run)?;
+        }
+        Commands::Remote { action } => {
+            let cli_service = services::CliService::new(".");
+            match action.unwrap_or(RemoteAction::List { verbose: false }) {
+                RemoteAction::Add { name, peer_id, addresses } => {
+                    cli_service.handle_remote_add(&name, &peer_id, addresses)?
+                }
+                RemoteAction::List { verbose } => cli_service.handle_remote_list(verbose)?,
+                RemoteAction::Remove { name } => cli_service.handle_remote_remove(&name)?,
+            }
+        }
+        Commands::Stash { action } => {
+            let cli_service = services::CliService::new(".");
+            match action.unwrap_or(StashAction::Push { message: None }) {
+                StashAction::Push { message } => cli_service.handle_stash_push(message)?,
+                StashAction::Pop { index } => cli_service.handle_stash_pop(index)?,
+                StashAction::List => cli_service.handle_stash_list()?,
+                StashAction::Drop { index } => cli_service.handle_stash_drop(index)?,
+            }
+        }
+        Commands::Reset { rev, soft, mixed: _, hard } => {
+            let mode = if soft {
+                services::ResetMode::Soft
+            } else if hard {
+                services::ResetMode::Hard
+            } else {
+                services::ResetMode::Mixed
+            };
+            let cli_service = services::CliService::new(".");
+            cli_service.handle_reset_command(&rev, mode)?;
+        }
+        Commands::Revert { rev } => {
+            let cli_service = services::CliService::new(".");
+            cli_service.handle_revert_command(&rev)?;
+        }
         Commands::CommitAmend { message } => {
             let cli_service = services::CliService::new(".");
             cli_service.handle_commit_amend(message)?;
@@ -0,0 +1,337 @@
+//! Bandwidth limits and fair sharing of uploads
+//!
+//! A [`BandwidthLimiter`] keeps a token bucket for all peers together and
+//! one per peer. Transfers are charged after the fact, so a bucket can go
+//! into debt; a peer is ready again once both its bucket and the shared one
+//! are paid off. Limits hold on average rather than for every chunk.
+//!
+//! An [`UploadQueue`] holds inbound chunk requests until they can be served.
+//! It serves at most a fixed number at once and takes turns between peers,
+//! so one peer cloning a large repository doesn't shut everyone else out.
+//! The queue is bounded in total and per peer; requests beyond that are
+//! handed back to be refused.
+//! Like the download scheduler it does no I/O; [`super::NetworkManager`]
+//! answers the requests it hands out.
+
+use libp2p::PeerId;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// Token bucket allowing `rate` bytes per second, with bursts of one second
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// Bytes per second
+    rate: u64,
+    /// Bytes that may be sent now; negative after a burst
+    balance: f64,
+    /// When `balance` was last brought up to date
+    updated: Instant,
+}
+
+impl RateLimiter {
+    /// Create a limiter with a full bucket
+    pub fn new(rate: u64, now: Instant) -> Self {
+        Self {
+            rate: rate.max(1),
+            balance: rate.max(1) as f64,
+            updated: now,
+        }
+    }
+
+    /// Charge `bytes` that were just sent or received
+    pub fn consume(&mut self, bytes: usize, now: Instant) {
+        self.balance = self.balance_at(now) - bytes as f64;
+        self.updated = now;
+    }
+
+    /// When the bucket is out of debt
+    ///
+    /// # Returns
+    /// `now` if transfers may go ahead right away
+    pub fn ready_at(&self, now: Instant) -> Instant {
+        let balance = self.balance_at(now);
+        if balance >= 0.0 {
+            now
+        } else {
+            now + Duration::from_secs_f64(-balance / self.rate as f64)
+        }
+    }
+
+    fn balance_at(&self, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        (self.balance + elapsed * self.rate as f64).min(self.rate as f64)
+    }
+}
+
+/// Global and per-peer rate limits for one direction of traffic
+#[derive(Debug, Default)]
+pub struct BandwidthLimiter {
+    /// Limit for all peers together
+    total: Option<RateLimiter>,
+    /// Bytes per second allowed for each peer
+    per_peer_rate: Option<u64>,
+    /// Buckets of the peers seen so far
+    peers: HashMap<PeerId, RateLimiter>,
+}
+
+impl BandwidthLimiter {
+    /// Create a limiter; `None` means unlimited
+    ///
+    /// # Arguments
+    /// * `total` - Bytes per second for all peers together
+    /// * `per_peer` - Bytes per second for each peer
+    pub fn new(total: Option<u64>, per_peer: Option<u64>) -> Self {
+        Self {
+            total: total.map(|rate| RateLimiter::new(rate, Instant::now())),
+            per_peer_rate: per_peer,
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Charge `bytes` transferred with `peer`
+    pub fn consume(&mut self, peer: PeerId, bytes: usize, now: Instant) {
+        if let Some(total) = &mut self.total {
+            total.consume(bytes, now);
+        }
+        if let Some(rate) = self.per_peer_rate {
+            self.peers
+                .entry(peer)
+                .or_insert_with(|| RateLimiter::new(rate, now))
+                .consume(bytes, now);
+        }
+    }
+
+    /// When the next transfer with `peer` may start
+    ///
+    /// # Returns
+    /// `now` if it may start right away
+    pub fn ready_at(&self, peer: &PeerId, now: Instant) -> Instant {
+        let total = self.total.as_ref().map_or(now, |total| total.ready_at(now));
+        let peer = self.peers.get(peer).map_or(now, |bucket| bucket.ready_at(now));
+        total.max(peer)
+    }
+
+    /// Whether any limit is set
+    pub fn is_limited(&self) -> bool {
+        self.total.is_some() || self.per_peer_rate.is_some()
+    }
+
+    /// Whether a transfer with `peer` may start now
+    pub fn is_ready(&self, peer: &PeerId, now: Instant) -> bool {
+        self.ready_at(peer, now) <= now
+    }
+
+    /// Drop the bucket of a peer that disconnected
+    ///
+    /// A bucket in debt is kept, so reconnecting doesn't clear the debt.
+    pub fn forget(&mut self, peer: &PeerId, now: Instant) {
+        if self.is_ready(peer, now) {
+            self.peers.remove(peer);
+        }
+    }
+}
+
+/// Inbound requests waiting to be served, taken in turns between peers
+#[derive(Debug)]
+pub struct UploadQueue<K, T> {
+    /// Requests served at once
+    max_active: usize,
+    /// Requests that may wait, from all peers together
+    max_waiting: usize,
+    /// Requests that may wait from each peer
+    max_waiting_per_peer: usize,
+    /// Waiting requests of each peer, oldest first
+    waiting: HashMap<PeerId, VecDeque<(K, T)>>,
+    /// Peers with waiting requests, next in turn first
+    turns: VecDeque<PeerId>,
+    /// Requests handed out and not finished yet
+    active: HashSet<K>,
+}
+
+impl<K: Copy + Eq + Hash, T> UploadQueue<K, T> {
+    /// Create a queue serving up to `max_active` requests at once (at least 1)
+    ///
+    /// # Arguments
+    /// * `max_active` - Requests served at once
+    /// * `max_waiting` - Requests that may wait, from all peers together
+    /// * `max_waiting_per_peer` - Requests that may wait from each peer
+    pub fn new(max_active: usize, max_waiting: usize, max_waiting_per_peer: usize) -> Self {
+        Self {
+            max_active: max_active.max(1),
+            max_waiting,
+            max_waiting_per_peer,
+            waiting: HashMap::new(),
+            turns: VecDeque::new(),
+            active: HashSet::new(),
+        }
+    }
+
+    /// Queue a request from `peer`
+    ///
+    /// # Returns
+    /// The request back if the queue or the peer's share of it is full
+    pub fn push(&mut self, peer: PeerId, id: K, request: T) -> Result<(), T> {
+        let queued = self.waiting.get(&peer).map_or(0, VecDeque::len);
+        if queued >= self.max_waiting_per_peer || self.len() >= self.max_waiting {
+            return Err(request);
+        }
+        let queue = self.waiting.entry(peer).or_default();
+        if queue.is_empty() {
+            self.turns.push_back(peer);
+        }
+        queue.push_back((id, request));
+        Ok(())
+    }
+
+    /// Take the next request to serve
+    ///
+    /// # Arguments
+    /// * `ready` - Whether a peer may be served now; peers that may not keep
+    ///   their turn
+    ///
+    /// # Returns
+    /// None if enough requests are active already or no ready peer is waiting
+    pub fn pop(&mut self, ready: impl Fn(&PeerId) -> bool) -> Option<(PeerId, K, T)> {
+        if self.is_full() {
+            return None;
+        }
+        let position = self.turns.iter().position(ready)?;
+        let peer = self.turns.remove(position)?;
+        let queue = self.waiting.get_mut(&peer)?;
+        let (id, request) = queue.pop_front()?;
+        if queue.is_empty() {
+            self.waiting.remove(&peer);
+        } else {
+            self.turns.push_back(peer);
+        }
+        self.active.insert(id);
+        Some((peer, id, request))
+    }
+
+    /// Record that a request handed out by [`Self::pop`] was answered or failed
+    ///
+    /// # Returns
+    /// Whether the request was active
+    pub fn finished(&mut self, id: &K) -> bool {
+        self.active.remove(id)
+    }
+
+    /// Drop the waiting requests of a peer that disconnected
+    pub fn remove_peer(&mut self, peer: &PeerId) {
+        self.waiting.remove(peer);
+        self.turns.retain(|p| p != peer);
+    }
+
+    /// Whether as many requests as allowed are active
+    pub fn is_full(&self) -> bool {
+        self.active.len() >= self.max_active
+    }
+
+    /// Peers with waiting requests, next in turn first
+    pub fn waiting_peers(&self) -> impl Iterator<Item = &PeerId> {
+        self.turns.iter()
+    }
+
+    /// Number of requests waiting
+    pub fn len(&self) -> usize {
+        self.waiting.values().map(VecDeque::len).sum()
+    }
+
+    /// Whether no requests are waiting
+    pub fn is_empty(&self) -> bool {
+        self.waiting.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter_debt() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(1000, now);
+        assert_eq!(limiter.ready_at(now), now);
+
+        // A full bucket pays for one second; the rest is debt
+        limiter.consume(3000, now);
+        assert_eq!(limiter.ready_at(now), now + Duration::from_secs(2));
+        assert_eq!(limiter.ready_at(now + Duration::from_secs(3)), now + Duration::from_secs(3));
+
+        // Idle time doesn't build up more than one second of allowance
+        let later = now + Duration::from_secs(60);
+        limiter.consume(2000, later);
+        assert_eq!(limiter.ready_at(later), later + Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_bandwidth_limiter_per_peer() {
+        let now = Instant::now();
+        let (a, b) = (PeerId::random(), PeerId::random());
+
+        let mut limiter = BandwidthLimiter::new(None, Some(100));
+        limiter.consume(a, 300, now);
+        assert!(!limiter.is_ready(&a, now));
+        assert!(limiter.is_ready(&b, now));
+
+        let mut limiter = BandwidthLimiter::new(Some(100), Some(1000));
+        limiter.consume(a, 300, now);
+        assert!(!limiter.is_ready(&b, now));
+        assert_eq!(limiter.ready_at(&b, now), now + Duration::from_secs(2));
+
+        limiter.forget(&a, now);
+        assert!(!limiter.is_ready(&b, now), "a debt survives reconnecting");
+
+        let mut unlimited = BandwidthLimiter::default();
+        unlimited.consume(a, usize::MAX, now);
+        assert!(unlimited.is_ready(&a, now));
+    }
+
+    #[test]
+    fn test_upload_queue_takes_turns() {
+        let (a, b) = (PeerId::random(), PeerId::random());
+        let mut queue = UploadQueue::new(2, 16, 4);
+        for id in 0..3 {
+            queue.push(a, id, "a").unwrap();
+        }
+        queue.push(b, 10, "b").unwrap();
+        assert_eq!(queue.len(), 4);
+
+        // The second request goes to b even though a asked first
+        assert_eq!(queue.pop(|_| true), Some((a, 0, "a")));
+        assert_eq!(queue.pop(|_| true), Some((b, 10, "b")));
+        assert!(queue.is_full());
+        assert_eq!(queue.pop(|_| true), None, "only two requests may be active");
+
+        assert!(queue.finished(&0));
+        assert!(!queue.finished(&0));
+        assert_eq!(queue.pop(|_| true), Some((a, 1, "a")));
+        assert!(queue.finished(&10));
+        assert!(queue.finished(&1));
+
+        // A peer that may not be served keeps its requests
+        assert_eq!(queue.pop(|peer| *peer != a), None);
+        assert_eq!(queue.waiting_peers().collect::<Vec<_>>(), vec![&a]);
+
+        queue.remove_peer(&a);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_upload_queue_is_bounded() {
+        let (a, b, c) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let mut queue = UploadQueue::new(1, 3, 2);
+        queue.push(a, 0, "a").unwrap();
+        queue.push(a, 1, "a").unwrap();
+        assert_eq!(queue.push(a, 2, "a"), Err("a"), "a peer can't take more than its share");
+
+        queue.push(b, 10, "b").unwrap();
+        assert_eq!(queue.push(c, 20, "c"), Err("c"), "the queue as a whole is full");
+
+        // Requests being served don't count against the bounds
+        assert_eq!(queue.pop(|_| true), Some((a, 0, "a")));
+        queue.push(c, 20, "c").unwrap();
+        assert_eq!(queue.len(), 3);
+    }
+}
@@ -0,0 +1,278 @@
+//! Multi-peer chunk download scheduling
+//!
+//! A [`DownloadScheduler`] decides which chunk to request from which peer.
+//! Every peer gets up to a window of requests in flight at once. A chunk a
+//! peer reports missing is handed to another peer, and a peer whose request
+//! fails is dropped with its outstanding chunks going back in the queue.
+//! The scheduler does no I/O; [`super::NetworkManager::fetch_file`]
+//! sends the requests it hands out and reports the results back.
+
+use libp2p::PeerId;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::config::ChunkSelection;
+
+/// Assigns the chunks of a download to peers
+#[derive(Debug)]
+pub struct DownloadScheduler {
+    /// How the next chunk for a peer is chosen
+    selection: ChunkSelection,
+    /// Requests allowed in flight per peer
+    window: usize,
+    /// Peers still serving this download, in preference order
+    peers: Vec<PeerId>,
+    /// Peer to offer the next request to
+    next_peer: usize,
+    /// Chunks waiting to be requested, in file order
+    pending: VecDeque<String>,
+    /// Chunks requested from each peer and not answered yet
+    in_flight: HashMap<PeerId, HashSet<String>>,
+    /// Peers that reported not having a chunk
+    lacking: HashMap<String, HashSet<PeerId>>,
+    /// Chunks downloaded so far
+    completed: usize,
+}
+
+impl DownloadScheduler {
+    /// Create a scheduler for `chunks`, to be fetched from `peers`
+    ///
+    /// # Arguments
+    /// * `chunks` - Hashes of the chunks to download, in file order
+    /// * `peers` - Peers that may have them, preferred peers first
+    /// * `window` - Requests to keep in flight per peer (at least 1)
+    /// * `selection` - Chunk selection strategy
+    pub fn new(chunks: Vec<String>, peers: Vec<PeerId>, window: usize, selection: ChunkSelection) -> Self {
+        let mut seen = HashSet::new();
+        let peers = peers.into_iter().filter(|peer| seen.insert(*peer)).collect();
+        let mut seen = HashSet::new();
+        let pending = chunks.into_iter().filter(|chunk| seen.insert(chunk.clone())).collect();
+
+        Self {
+            selection,
+            window: window.max(1),
+            peers,
+            next_peer: 0,
+            pending,
+            in_flight: HashMap::new(),
+            lacking: HashMap::new(),
+            completed: 0,
+        }
+    }
+
+    /// Hand out requests until every peer's window is full
+    ///
+    /// # Returns
+    /// (peer, chunk) pairs to request; each chunk is given to one peer at a time
+    pub fn next_requests(&mut self) -> Vec<(PeerId, String)> {
+        self.next_requests_where(|_| true)
+    }
+
+    /// Hand out requests to the peers `ready` accepts until their windows are full
+    ///
+    /// Used to hold back peers that are over a download limit.
+    pub fn next_requests_where(&mut self, ready: impl Fn(&PeerId) -> bool) -> Vec<(PeerId, String)> {
+        let mut requests = Vec::new();
+        loop {
+            let mut assigned = false;
+            for offset in 0..self.peers.len() {
+                let index = (self.next_peer + offset) % self.peers.len();
+                let peer = self.peers[index];
+                if self.in_flight.get(&peer).map_or(0, HashSet::len) >= self.window || !ready(&peer) {
+                    continue;
+                }
+                if let Some(chunk) = self.take_chunk_for(peer) {
+                    self.in_flight.entry(peer).or_default().insert(chunk.clone());
+                    requests.push((peer, chunk));
+                    self.next_peer = (index + 1) % self.peers.len();
+                    assigned = true;
+                    break;
+                }
+            }
+            if !assigned {
+                return requests;
+            }
+        }
+    }
+
+    /// Record a chunk that was downloaded and stored
+    pub fn completed(&mut self, peer: PeerId, chunk: &str) {
+        if self.finish_request(peer, chunk) {
+            self.completed += 1;
+            self.lacking.remove(chunk);
+        }
+    }
+
+    /// Record that a peer doesn't have a chunk, so another peer is asked
+    pub fn not_found(&mut self, peer: PeerId, chunk: &str) {
+        if self.finish_request(peer, chunk) {
+            self.lacking.entry(chunk.to_string()).or_default().insert(peer);
+            self.pending.push_front(chunk.to_string());
+        }
+    }
+
+    /// Stop using a peer, putting its outstanding chunks back in the queue
+    pub fn peer_failed(&mut self, peer: PeerId) {
+        let Some(position) = self.peers.iter().position(|p| *p == peer) else {
+            return;
+        };
+        self.peers.remove(position);
+        if self.next_peer > position {
+            self.next_peer -= 1;
+        }
+        if self.next_peer >= self.peers.len() {
+            self.next_peer = 0;
+        }
+        for chunk in self.in_flight.remove(&peer).unwrap_or_default() {
+            self.pending.push_front(chunk);
+        }
+    }
+
+    /// Whether every chunk has been downloaded
+    pub fn is_done(&self) -> bool {
+        self.pending.is_empty() && self.in_flight.values().all(HashSet::is_empty)
+    }
+
+    /// A waiting chunk that none of the remaining peers can serve, if any
+    ///
+    /// Only meaningful once [`Self::next_requests`] hands out nothing and no
+    /// requests are in flight.
+    pub fn unavailable_chunk(&self) -> Option<&str> {
+        self.pending
+            .iter()
+            .find(|chunk| self.peers.iter().all(|peer| !self.may_have(*peer, chunk)))
+            .map(String::as_str)
+    }
+
+    /// Number of chunks downloaded so far
+    pub fn completed_count(&self) -> usize {
+        self.completed
+    }
+
+    /// Peers still serving this download
+    pub fn peers(&self) -> &[PeerId] {
+        &self.peers
+    }
+
+    /// Clear a request from a peer's window
+    ///
+    /// # Returns
+    /// Whether the request was outstanding (answers from dropped peers are ignored)
+    fn finish_request(&mut self, peer: PeerId, chunk: &str) -> bool {
+        self.in_flight
+            .get_mut(&peer)
+            .is_some_and(|chunks| chunks.remove(chunk))
+    }
+
+    /// Whether a peer hasn't reported lacking a chunk
+    fn may_have(&self, peer: PeerId, chunk: &str) -> bool {
+        !self.lacking.get(chunk).is_some_and(|peers| peers.contains(&peer))
+    }
+
+    /// Remove and return the next waiting chunk to request from a peer
+    fn take_chunk_for(&mut self, peer: PeerId) -> Option<String> {
+        let candidates = self
+            .pending
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| self.may_have(peer, chunk));
+        let position = match self.selection {
+            ChunkSelection::RoundRobin => candidates.map(|(position, _)| position).next(),
+            ChunkSelection::RarestFirst => candidates
+                .min_by_key(|(position, chunk)| {
+                    let holders = self.peers.iter().filter(|p| self.may_have(**p, chunk)).count();
+                    (holders, *position)
+                })
+                .map(|(position, _)| position),
+        }?;
+        self.pending.remove(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("c{}", i)).collect()
+    }
+
+    #[test]
+    fn test_spreads_requests_within_window() {
+        let (a, b) = (PeerId::random(), PeerId::random());
+        let mut scheduler = DownloadScheduler::new(chunks(5), vec![a, b], 2, ChunkSelection::RoundRobin);
+
+        let requests = scheduler.next_requests();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests.iter().filter(|(peer, _)| *peer == a).count(), 2);
+        assert_eq!(requests[0], (a, "c0".to_string()));
+        assert_eq!(requests[1], (b, "c1".to_string()));
+        assert!(scheduler.next_requests().is_empty());
+
+        scheduler.completed(b, "c1");
+        assert_eq!(scheduler.next_requests(), vec![(b, "c4".to_string())]);
+
+        for (peer, chunk) in [(a, "c0"), (a, "c2"), (b, "c3"), (b, "c4")] {
+            scheduler.completed(peer, chunk);
+        }
+        assert!(scheduler.is_done());
+        assert_eq!(scheduler.completed_count(), 5);
+    }
+
+    #[test]
+    fn test_holds_back_peers_that_are_not_ready() {
+        let (a, b) = (PeerId::random(), PeerId::random());
+        let mut scheduler = DownloadScheduler::new(chunks(4), vec![a, b], 2, ChunkSelection::RoundRobin);
+
+        let requests = scheduler.next_requests_where(|peer| *peer != a);
+        assert_eq!(requests, vec![(b, "c0".to_string()), (b, "c1".to_string())]);
+        assert_eq!(scheduler.next_requests(), vec![(a, "c2".to_string()), (a, "c3".to_string())]);
+    }
+
+    #[test]
+    fn test_failover() {
+        let (a, b) = (PeerId::random(), PeerId::random());
+        let mut scheduler = DownloadScheduler::new(chunks(3), vec![a, b], 1, ChunkSelection::RoundRobin);
+        assert_eq!(scheduler.next_requests(), vec![(a, "c0".to_string()), (b, "c1".to_string())]);
+
+        // A missing chunk goes to the other peer once it has room
+        scheduler.not_found(a, "c0");
+        assert_eq!(scheduler.next_requests(), vec![(a, "c2".to_string())]);
+        scheduler.completed(b, "c1");
+        assert_eq!(scheduler.next_requests(), vec![(b, "c0".to_string())]);
+
+        // A dropped peer's chunk is retried elsewhere; late answers are ignored
+        scheduler.peer_failed(a);
+        scheduler.completed(a, "c2");
+        assert_eq!(scheduler.peers(), &[b]);
+        scheduler.completed(b, "c0");
+        assert_eq!(scheduler.next_requests(), vec![(b, "c2".to_string())]);
+        scheduler.completed(b, "c2");
+        assert!(scheduler.is_done());
+        assert_eq!(scheduler.completed_count(), 3);
+    }
+
+    #[test]
+    fn test_unavailable_chunk() {
+        let a = PeerId::random();
+        let mut scheduler = DownloadScheduler::new(chunks(1), vec![a], 4, ChunkSelection::RarestFirst);
+        assert_eq!(scheduler.next_requests().len(), 1);
+        assert_eq!(scheduler.unavailable_chunk(), None);
+
+        scheduler.not_found(a, "c0");
+        assert!(scheduler.next_requests().is_empty());
+        assert_eq!(scheduler.unavailable_chunk(), Some("c0"));
+        assert!(!scheduler.is_done());
+    }
+
+    #[test]
+    fn test_rarest_first() {
+        let (a, b) = (PeerId::random(), PeerId::random());
+        let mut scheduler = DownloadScheduler::new(chunks(3), vec![a, b], 1, ChunkSelection::RarestFirst);
+        assert_eq!(scheduler.next_requests(), vec![(a, "c0".to_string()), (b, "c1".to_string())]);
+
+        // Only a has c1 now, so it is requested before c2, which both may have
+        scheduler.not_found(b, "c1");
+        scheduler.completed(a, "c0");
+        assert_eq!(scheduler.next_requests(), vec![(a, "c1".to_string()), (b, "c2".to_string())]);
+    }
+}
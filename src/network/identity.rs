@@ -0,0 +1,255 @@
+//! Persistent node identity for FAI Protocol
+//!
+//! The libp2p keypair lives in `.fai/identity` so a repository keeps the same
+//! peer ID across restarts. The file is only readable by its owner and can
+//! be encrypted with a passphrase (Argon2 key derivation, AES-256-GCM),
+//! which is read from the `FAI_IDENTITY_PASSPHRASE` environment variable.
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::Result;
+use libp2p::identity::Keypair;
+use libp2p::PeerId;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// File name of the identity inside `.fai/`
+pub const IDENTITY_FILE: &str = "identity";
+
+/// Environment variable holding the identity passphrase
+pub const PASSPHRASE_ENV: &str = "FAI_IDENTITY_PASSPHRASE";
+
+/// On-disk format of the identity file
+#[derive(Debug, Serialize, Deserialize)]
+struct IdentityFile {
+    /// Peer ID derived from the key, for reference
+    peer_id: String,
+    /// Hex of the protobuf-encoded keypair, encrypted if `encryption` is set
+    key: String,
+    /// Parameters of the passphrase encryption
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encryption: Option<Encryption>,
+}
+
+/// Passphrase encryption parameters
+#[derive(Debug, Serialize, Deserialize)]
+struct Encryption {
+    /// Hex of the Argon2 salt
+    salt: String,
+    /// Hex of the AES-GCM nonce
+    nonce: String,
+}
+
+/// Path of the identity file of the repository at `fai_path`
+pub fn identity_path(fai_path: &Path) -> PathBuf {
+    fai_path.join(IDENTITY_FILE)
+}
+
+/// Passphrase from `FAI_IDENTITY_PASSPHRASE`, if set and non-empty
+pub fn passphrase_from_env() -> Option<String> {
+    std::env::var(PASSPHRASE_ENV).ok().filter(|p| !p.is_empty())
+}
+
+/// Load the repository identity, creating it on first use
+///
+/// Uses the passphrase from `FAI_IDENTITY_PASSPHRASE`; a new identity is
+/// encrypted with it if set.
+pub fn repo_identity(fai_path: &Path) -> Result<Keypair> {
+    load_or_generate(fai_path, passphrase_from_env().as_deref())
+}
+
+/// Load the identity at `fai_path`, or generate and save a new one
+///
+/// # Arguments
+/// * `fai_path` - The `.fai` directory
+/// * `passphrase` - Passphrase to decrypt the identity, or to encrypt a new one
+pub fn load_or_generate(fai_path: &Path, passphrase: Option<&str>) -> Result<Keypair> {
+    if let Some(keypair) = load(fai_path, passphrase)? {
+        return Ok(keypair);
+    }
+
+    let keypair = Keypair::generate_ed25519();
+    save(fai_path, &keypair, passphrase)?;
+    Ok(keypair)
+}
+
+/// Load the identity at `fai_path`
+///
+/// # Returns
+/// None if no identity has been created yet
+pub fn load(fai_path: &Path, passphrase: Option<&str>) -> Result<Option<Keypair>> {
+    let path = identity_path(fai_path);
+    if !path.exists() {
+        return Ok(None);
+    }
+    warn_if_readable_by_others(&path);
+
+    let file: IdentityFile = toml::from_str(&std::fs::read_to_string(&path)?)
+        .map_err(|e| anyhow::anyhow!("Invalid identity file {}: {}", path.display(), e))?;
+    let key = decode_hex(&file.key)?;
+
+    let encoded = match &file.encryption {
+        None => key,
+        Some(encryption) => {
+            let passphrase = passphrase.ok_or_else(|| {
+                anyhow::anyhow!("Identity is encrypted. Set {} to unlock it.", PASSPHRASE_ENV)
+            })?;
+            let cipher = cipher(passphrase, &decode_hex(&encryption.salt)?)?;
+            let nonce: [u8; 12] = decode_hex(&encryption.nonce)?
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid identity file {}: bad nonce", path.display()))?;
+            cipher
+                .decrypt(&Nonce::from(nonce), key.as_slice())
+                .map_err(|_| anyhow::anyhow!("Wrong passphrase for identity {}", path.display()))?
+        }
+    };
+
+    let keypair = Keypair::from_protobuf_encoding(&encoded)
+        .map_err(|e| anyhow::anyhow!("Invalid identity key in {}: {}", path.display(), e))?;
+    Ok(Some(keypair))
+}
+
+/// Write the identity to `fai_path`, readable only by the owner
+///
+/// # Arguments
+/// * `passphrase` - Encrypt the key with this passphrase (None stores it unencrypted)
+pub fn save(fai_path: &Path, keypair: &Keypair, passphrase: Option<&str>) -> Result<()> {
+    let encoded = keypair
+        .to_protobuf_encoding()
+        .map_err(|e| anyhow::anyhow!("Failed to encode identity: {}", e))?;
+
+    let (key, encryption) = match passphrase {
+        None => (encoded, None),
+        Some(passphrase) => {
+            let mut salt = [0u8; 16];
+            let mut nonce = [0u8; 12];
+            rand::thread_rng().fill_bytes(&mut salt);
+            rand::thread_rng().fill_bytes(&mut nonce);
+            let ciphertext = cipher(passphrase, &salt)?
+                .encrypt(&Nonce::from(nonce), encoded.as_slice())
+                .map_err(|_| anyhow::anyhow!("Failed to encrypt identity"))?;
+            let encryption = Encryption {
+                salt: encode_hex(&salt),
+                nonce: encode_hex(&nonce),
+            };
+            (ciphertext, Some(encryption))
+        }
+    };
+
+    let file = IdentityFile {
+        peer_id: PeerId::from(keypair.public()).to_string(),
+        key: encode_hex(&key),
+        encryption,
+    };
+    write_private(&identity_path(fai_path), toml::to_string_pretty(&file)?.as_bytes())
+}
+
+/// Whether the identity at `fai_path` is protected by a passphrase
+pub fn is_encrypted(fai_path: &Path) -> Result<bool> {
+    let file: IdentityFile = toml::from_str(&std::fs::read_to_string(identity_path(fai_path))?)?;
+    Ok(file.encryption.is_some())
+}
+
+/// AES-256-GCM cipher keyed with the Argon2 hash of the passphrase
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<Aes256Gcm> {
+    let mut key = [0u8; 32];
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow::anyhow!("Failed to derive identity key: {}", e))?;
+    Aes256Gcm::new_from_slice(&key).map_err(|e| anyhow::anyhow!("Invalid identity key: {}", e))
+}
+
+/// Replace `path` with `data`, creating it with owner-only permissions
+fn write_private(path: &Path, data: &[u8]) -> Result<()> {
+    use std::io::Write;
+
+    let tmp_path = path.with_extension("tmp");
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(&tmp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Print a warning if other users can read the identity file
+fn warn_if_readable_by_others(path: &Path) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Ok(metadata) = std::fs::metadata(path) {
+            if metadata.permissions().mode() & 0o077 != 0 {
+                eprintln!(
+                    "Warning: {} is accessible by other users; run `chmod 600` on it",
+                    path.display()
+                );
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return Err(anyhow::anyhow!("Invalid hex string"));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| anyhow::anyhow!("Invalid hex string"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_identity_is_persistent() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(load(temp_dir.path(), None).unwrap().is_none());
+
+        let first = load_or_generate(temp_dir.path(), None).unwrap();
+        let second = load_or_generate(temp_dir.path(), None).unwrap();
+        assert_eq!(PeerId::from(first.public()), PeerId::from(second.public()));
+        assert!(!is_encrypted(temp_dir.path()).unwrap());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(identity_path(temp_dir.path())).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_encrypted_identity() {
+        let temp_dir = TempDir::new().unwrap();
+        let keypair = load_or_generate(temp_dir.path(), Some("secret")).unwrap();
+        assert!(is_encrypted(temp_dir.path()).unwrap());
+
+        let contents = std::fs::read_to_string(identity_path(temp_dir.path())).unwrap();
+        let plain_key = encode_hex(&keypair.to_protobuf_encoding().unwrap());
+        assert!(!contents.contains(&plain_key));
+
+        assert!(load(temp_dir.path(), None).is_err());
+        assert!(load(temp_dir.path(), Some("wrong")).is_err());
+        let loaded = load(temp_dir.path(), Some("secret")).unwrap().unwrap();
+        assert_eq!(PeerId::from(loaded.public()), PeerId::from(keypair.public()));
+    }
+}
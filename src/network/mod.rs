@@ -2,18 +2,27 @@
 //!
 //! Handles peer-to-peer networking for decentralized model sharing.
 
+pub mod bandwidth;
+pub mod download;
+pub mod identity;
+pub mod remote;
+
+use crate::config::{NetworkConfig, ReceiveConfig, TransferConfig};
 use crate::storage::StorageManager;
 use anyhow::Result;
 use futures::StreamExt;
 use libp2p::{
+    autonat, dcutr, identify,
     identity::Keypair,
-    mdns,
+    kad, mdns,
+    multiaddr::Protocol,
+    relay,
     request_response::ProtocolSupport,
-    swarm::{NetworkBehaviour, SwarmEvent},
+    swarm::{behaviour::toggle::Toggle, dial_opts::DialOpts, DialError, NetworkBehaviour, SwarmEvent},
     yamux, Multiaddr, PeerId, Swarm, SwarmBuilder,
 };
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, sync::Arc, time::SystemTime};
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Instant, SystemTime}};
 
 /// Information about a discovered peer
 #[derive(Debug, Clone)]
@@ -26,11 +35,24 @@ pub struct PeerInfo {
     pub last_seen: SystemTime,
 }
 
+/// Objects downloaded by a network manager since it was created
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferProgress {
+    /// Objects downloaded and verified
+    pub objects_done: usize,
+    /// Objects requested, including those already downloaded
+    pub objects_total: usize,
+}
+
 /// Request for a chunk of data
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChunkRequest {
     /// Hash of the chunk being requested
     pub hash: String,
+    /// Send the stored object as-is: the manifest of a multi-chunk file
+    /// instead of the reassembled file
+    #[serde(default)]
+    pub raw: bool,
 }
 
 /// Response containing chunk data
@@ -45,8 +67,11 @@ pub struct ChunkResponse {
 /// Request for commit information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CommitRequest {
-    /// Optional specific commit hash to request
+    /// Optional specific commit hash to request (defaults to every branch and tag)
     pub commit_hash: Option<String>,
+    /// Commits the requester already has; they and their ancestors are not sent
+    #[serde(default)]
+    pub haves: Vec<String>,
 }
 
 /// Response containing commit information
@@ -54,17 +79,232 @@ pub struct CommitRequest {
 pub struct CommitResponse {
     /// List of commits
     pub commits: Vec<crate::storage::CommitInfo>,
+    /// File snapshot of each commit (commit_hash -> (file_path, file_hash, file_size))
+    #[serde(default)]
+    pub files: HashMap<String, Vec<(String, String, u64)>>,
+    /// Tags pointing at the returned commits
+    #[serde(default)]
+    pub tags: Vec<crate::database::Tag>,
+    /// Branch heads of the responding peer (branch name, head commit)
+    #[serde(default)]
+    pub branches: Vec<(String, String)>,
+}
+
+/// How long a push may take, including the receiver fetching missing objects
+const PUSH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// How long a chunk request may take, including time queued behind other requests
+const CHUNK_REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Inbound chunk requests that may wait to be served, from all peers together
+const MAX_QUEUED_REQUESTS: usize = 1024;
+
+/// Inbound chunk requests that may wait to be served from a single peer
+const MAX_QUEUED_REQUESTS_PER_PEER: usize = 64;
+
+/// How long a download waits without hearing from any peer before giving up
+const DOWNLOAD_STALL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Protocol version announced over identify
+const IDENTIFY_PROTOCOL: &str = "/fai/1.0.0";
+
+/// Kademlia protocol, kept apart from the public IPFS DHT
+const KAD_PROTOCOL: &str = "/fai/kad/1.0.0";
+
+/// How long a DHT query may run
+const DHT_QUERY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Provider records a node keeps announcing (one per commit and file)
+const MAX_PROVIDED_KEYS: usize = 100_000;
+
+/// Content announced in the DHT
+///
+/// Peers publish provider records for the commits and files they host, so
+/// a fetch can find peers with the content beyond the local network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Content<'a> {
+    /// A commit, by hash
+    Commit(&'a str),
+    /// A stored file (or a manifest of a chunked file), by hash
+    Object(&'a str),
+}
+
+impl Content<'_> {
+    /// DHT key of the content
+    pub fn key(&self) -> kad::RecordKey {
+        match self {
+            Content::Commit(hash) => kad::RecordKey::new(&format!("/fai/commit/{}", hash)),
+            Content::Object(hash) => kad::RecordKey::new(&format!("/fai/object/{}", hash)),
+        }
+    }
+}
+
+/// A branch update requested by a pushing peer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefUpdate {
+    /// Branch to update on the receiver
+    pub branch: String,
+    /// Commit the branch should point to
+    pub new: String,
+    /// Allow updates that are not fast-forwards
+    pub force: bool,
+}
+
+/// Request to update branches of the receiving peer
+///
+/// The receiver fetches objects it lacks from the pusher over the chunk
+/// protocol before it touches any branch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PushRequest {
+    /// Branch updates to apply
+    pub updates: Vec<RefUpdate>,
+    /// Commits reachable from the new branch heads
+    pub commits: Vec<crate::storage::CommitInfo>,
+    /// File snapshot of each commit (commit_hash -> (file_path, file_hash, file_size))
+    pub files: HashMap<String, Vec<(String, String, u64)>>,
+    /// Tags pointing at the pushed commits
+    pub tags: Vec<crate::database::Tag>,
+}
+
+/// Outcome of a single ref update
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RefStatus {
+    /// The ref did not exist and was created
+    Created,
+    /// The ref was moved forward
+    FastForward,
+    /// The ref was moved to a commit that does not contain its old value
+    Forced,
+    /// The ref already pointed to the pushed commit
+    UpToDate,
+    /// The ref was left unchanged, with the reason
+    Rejected(String),
+}
+
+/// Per-ref result of a push
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefResult {
+    /// Updated ref (e.g., "refs/heads/main" or "refs/tags/v1.0")
+    pub ref_name: String,
+    /// Value of the ref on the receiver before the push
+    pub old: Option<String>,
+    /// Value the pusher asked for
+    pub new: String,
+    /// What happened to the ref
+    pub status: RefStatus,
+}
+
+impl RefResult {
+    /// Whether the receiver now has the pushed value
+    pub fn is_accepted(&self) -> bool {
+        !matches!(self.status, RefStatus::Rejected(_))
+    }
+}
+
+/// Status report for a push
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PushResponse {
+    /// Result of every requested ref update and pushed tag
+    pub results: Vec<RefResult>,
+    /// Set if the whole push failed (e.g., objects could not be fetched)
+    pub error: Option<String>,
+}
+
+/// Request for a peer's branches and tags
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RefsRequest {}
+
+/// A ref advertised by a peer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdvertisedRef {
+    /// Full ref name (`refs/heads/<branch>` or `refs/tags/<tag>`)
+    pub name: String,
+    /// Commit the ref points to
+    pub target: String,
+}
+
+/// A peer's branches and tags
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RefsResponse {
+    /// Branch the peer has checked out
+    pub head: Option<String>,
+    /// Branches with commits, then tags
+    pub refs: Vec<AdvertisedRef>,
+}
+
+impl RefsResponse {
+    /// Commit a branch points to, if the peer has that branch
+    pub fn branch(&self, name: &str) -> Option<&str> {
+        let ref_name = format!("refs/heads/{}", name);
+        self.refs
+            .iter()
+            .find(|advertised| advertised.name == ref_name)
+            .map(|advertised| advertised.target.as_str())
+    }
+
+    /// Commit the peer's checked out branch points to
+    pub fn head_target(&self) -> Option<&str> {
+        self.head.as_deref().and_then(|head| self.branch(head))
+    }
+
+    /// Branch a clone should check out, with its commit
+    ///
+    /// The peer's checked out branch if it has commits, otherwise its first
+    /// branch.
+    pub fn default_branch(&self) -> Option<(&str, &str)> {
+        if let Some(head) = self.head.as_deref() {
+            if let Some(target) = self.branch(head) {
+                return Some((head, target));
+            }
+        }
+        self.refs.iter().find_map(|advertised| {
+            advertised
+                .name
+                .strip_prefix("refs/heads/")
+                .map(|branch| (branch, advertised.target.as_str()))
+        })
+    }
 }
 
-/// Network behaviour combining mDNS and request-response
+/// A push being received while its missing objects are fetched
+struct IncomingPush {
+    /// Pushing peer
+    peer: PeerId,
+    /// Channel to answer the push on
+    channel: libp2p::request_response::ResponseChannel<PushResponse>,
+    /// The push itself
+    request: PushRequest,
+    /// Object fetches that have not been answered yet
+    outstanding: usize,
+    /// Objects that could not be fetched or failed verification
+    failed: Vec<String>,
+}
+
+/// Network behaviour combining mDNS, identify, Kademlia, NAT traversal and request-response
 #[derive(NetworkBehaviour)]
 pub struct FAIBehaviour {
     /// mDNS for peer discovery
     pub mdns: mdns::tokio::Behaviour,
+    /// Identify for learning the addresses peers listen on
+    pub identify: identify::Behaviour,
+    /// Kademlia DHT for finding which peers provide commits and files
+    pub kad: kad::Behaviour<kad::store::MemoryStore>,
+    /// AutoNAT for learning whether we are reachable from outside
+    pub autonat: autonat::Behaviour,
+    /// Circuit relay client for listening and dialing through relays
+    pub relay_client: relay::client::Behaviour,
+    /// Circuit relay server, only enabled on relay nodes (see [`NetworkManager::new_relay`])
+    pub relay: Toggle<relay::Behaviour>,
+    /// DCUtR for upgrading relayed connections to direct ones by hole punching
+    pub dcutr: dcutr::Behaviour,
     /// Request-response protocol for chunks
     pub request_response: libp2p::request_response::cbor::Behaviour<ChunkRequest, ChunkResponse>,
     /// Request-response protocol for commits
     pub commit_response: libp2p::request_response::cbor::Behaviour<CommitRequest, CommitResponse>,
+    /// Request-response protocol for pushing branch updates
+    pub push: libp2p::request_response::cbor::Behaviour<PushRequest, PushResponse>,
+    /// Request-response protocol for advertising branches and tags
+    pub refs: libp2p::request_response::cbor::Behaviour<RefsRequest, RefsResponse>,
 }
 
 /// Events from the network behaviour
@@ -72,7 +312,15 @@ pub struct FAIBehaviour {
 pub enum FAIEvent {
     RequestResponse(libp2p::request_response::Event<ChunkRequest, ChunkResponse>),
     CommitResponse(libp2p::request_response::Event<CommitRequest, CommitResponse>),
+    Push(libp2p::request_response::Event<PushRequest, PushResponse>),
+    Refs(libp2p::request_response::Event<RefsRequest, RefsResponse>),
     Mdns(mdns::Event),
+    Identify(identify::Event),
+    Kad(kad::Event),
+    Autonat(autonat::Event),
+    RelayClient(relay::client::Event),
+    Relay(relay::Event),
+    Dcutr(dcutr::Event),
 }
 
 impl From<libp2p::request_response::Event<ChunkRequest, ChunkResponse>> for FAIEvent {
@@ -87,6 +335,54 @@ impl From<libp2p::request_response::Event<CommitRequest, CommitResponse>> for FA
     }
 }
 
+impl From<identify::Event> for FAIEvent {
+    fn from(event: identify::Event) -> Self {
+        FAIEvent::Identify(event)
+    }
+}
+
+impl From<kad::Event> for FAIEvent {
+    fn from(event: kad::Event) -> Self {
+        FAIEvent::Kad(event)
+    }
+}
+
+impl From<autonat::Event> for FAIEvent {
+    fn from(event: autonat::Event) -> Self {
+        FAIEvent::Autonat(event)
+    }
+}
+
+impl From<relay::client::Event> for FAIEvent {
+    fn from(event: relay::client::Event) -> Self {
+        FAIEvent::RelayClient(event)
+    }
+}
+
+impl From<relay::Event> for FAIEvent {
+    fn from(event: relay::Event) -> Self {
+        FAIEvent::Relay(event)
+    }
+}
+
+impl From<dcutr::Event> for FAIEvent {
+    fn from(event: dcutr::Event) -> Self {
+        FAIEvent::Dcutr(event)
+    }
+}
+
+impl From<libp2p::request_response::Event<PushRequest, PushResponse>> for FAIEvent {
+    fn from(event: libp2p::request_response::Event<PushRequest, PushResponse>) -> Self {
+        FAIEvent::Push(event)
+    }
+}
+
+impl From<libp2p::request_response::Event<RefsRequest, RefsResponse>> for FAIEvent {
+    fn from(event: libp2p::request_response::Event<RefsRequest, RefsResponse>) -> Self {
+        FAIEvent::Refs(event)
+    }
+}
+
 /// Network manager for FAI Protocol
 pub struct NetworkManager {
     /// libp2p swarm for network operations
@@ -97,8 +393,40 @@ pub struct NetworkManager {
     storage: Arc<StorageManager>,
     /// Database manager for commit operations
     database: crate::database::DatabaseManager,
-    /// Pending commit responses (request_id -> commits)
-    pending_commit_responses: std::collections::HashMap<libp2p::request_response::OutboundRequestId, Vec<crate::storage::CommitInfo>>,
+    /// Pending commit responses (request_id -> response)
+    pending_commit_responses: std::collections::HashMap<libp2p::request_response::OutboundRequestId, CommitResponse>,
+    /// Pending push responses (request_id -> response)
+    pending_push_responses: HashMap<libp2p::request_response::OutboundRequestId, PushResponse>,
+    /// Pending ref advertisements (request_id -> response, None if the request failed)
+    pending_refs_responses: HashMap<libp2p::request_response::OutboundRequestId, Option<RefsResponse>>,
+    /// Download window and chunk selection
+    transfer: TransferConfig,
+    /// Addresses to listen on
+    network: NetworkConfig,
+    /// Non-QUIC addresses to dial if dialing a peer over QUIC fails
+    fallback_addresses: HashMap<PeerId, Vec<Multiaddr>>,
+    /// Download progress, shared with whoever reports it (see [`Self::transfer_progress`])
+    progress: Arc<Mutex<TransferProgress>>,
+    /// Inbound chunk requests waiting to be answered
+    uploads: bandwidth::UploadQueue<
+        libp2p::request_response::InboundRequestId,
+        (ChunkRequest, libp2p::request_response::ResponseChannel<ChunkResponse>),
+    >,
+    /// Rate limits for answering chunk requests
+    upload_limits: bandwidth::BandwidthLimiter,
+    /// Rate limits for downloading chunks
+    download_limits: bandwidth::BandwidthLimiter,
+    /// Running DHT provider lookups (query -> providers found, whether the query finished)
+    provider_queries: HashMap<kad::QueryId, (Vec<PeerId>, bool)>,
+    /// Pushes being received (inbound request_id -> push)
+    incoming_pushes: HashMap<libp2p::request_response::InboundRequestId, IncomingPush>,
+    /// Object fetches made for incoming pushes (request_id -> (push, object hash))
+    push_object_requests: HashMap<
+        libp2p::request_response::OutboundRequestId,
+        (libp2p::request_response::InboundRequestId, String),
+    >,
+    /// Rules for pushes from peers
+    receive: ReceiveConfig,
 }
 
 impl NetworkManager {
@@ -107,30 +435,80 @@ impl NetworkManager {
     /// # Arguments
     /// * `storage` - Storage manager for retrieving chunks
     /// * `database` - Database manager for commit operations
+    /// * `local_key` - Node identity (see [`identity::repo_identity`])
     ///
     /// # Returns
     /// A new NetworkManager instance with configured libp2p stack
-    pub fn new(storage: Arc<StorageManager>, database: crate::database::DatabaseManager) -> Result<Self> {
-        // Generate identity
-        let local_key = Keypair::generate_ed25519();
+    pub fn new(
+        storage: Arc<StorageManager>,
+        database: crate::database::DatabaseManager,
+        local_key: Keypair,
+    ) -> Result<Self> {
+        Self::build(storage, database, local_key, false)
+    }
+
+    /// Create a network manager that also relays connections between
+    /// peers that can't reach each other directly
+    ///
+    /// Takes the same arguments as [`Self::new`].
+    pub fn new_relay(
+        storage: Arc<StorageManager>,
+        database: crate::database::DatabaseManager,
+        local_key: Keypair,
+    ) -> Result<Self> {
+        Self::build(storage, database, local_key, true)
+    }
+
+    fn build(
+        storage: Arc<StorageManager>,
+        database: crate::database::DatabaseManager,
+        local_key: Keypair,
+        relay_server: bool,
+    ) -> Result<Self> {
         let local_peer_id = PeerId::from(local_key.public());
 
-        // Create behaviour with mDNS and chunk/commit request/response
-        let behaviour = FAIBehaviour {
-            mdns: mdns::tokio::Behaviour::new(
-                mdns::Config {
-                    query_interval: std::time::Duration::from_secs(5),
-                    ttl: std::time::Duration::from_secs(60),
-                    ..Default::default()
-                },
-                local_peer_id,
-            )?,
+        // Kademlia in server mode, so peers on a LAN answer queries without
+        // first confirming an external address
+        let mut kad_config = kad::Config::new(libp2p::StreamProtocol::new(KAD_PROTOCOL));
+        kad_config.set_query_timeout(DHT_QUERY_TIMEOUT);
+        let kad_store = kad::store::MemoryStore::with_config(
+            local_peer_id,
+            kad::store::MemoryStoreConfig {
+                max_provided_keys: MAX_PROVIDED_KEYS,
+                ..Default::default()
+            },
+        );
+        let mut kad = kad::Behaviour::with_config(local_peer_id, kad_store, kad_config);
+        kad.set_mode(Some(kad::Mode::Server));
+
+        // Create behaviour with mDNS, identify, Kademlia, NAT traversal and
+        // chunk/commit request/response; the relay client comes from the swarm builder
+        let mdns = mdns::tokio::Behaviour::new(
+            mdns::Config {
+                query_interval: std::time::Duration::from_secs(5),
+                ttl: std::time::Duration::from_secs(60),
+                ..Default::default()
+            },
+            local_peer_id,
+        )?;
+        let relay = relay_server.then(|| relay::Behaviour::new(local_peer_id, relay::Config::default()));
+        let make_behaviour = |key: &Keypair, relay_client| FAIBehaviour {
+            mdns,
+            identify: identify::Behaviour::new(identify::Config::new(
+                IDENTIFY_PROTOCOL.to_string(),
+                key.public(),
+            )),
+            kad,
+            autonat: autonat::Behaviour::new(local_peer_id, autonat::Config::default()),
+            relay_client,
+            relay: relay.into(),
+            dcutr: dcutr::Behaviour::new(local_peer_id),
             request_response: libp2p::request_response::cbor::Behaviour::new(
                 [(
                     libp2p::StreamProtocol::new("/fai/chunk/1.0.0"),
                     ProtocolSupport::Full,
                 )],
-                libp2p::request_response::Config::default(),
+                libp2p::request_response::Config::default().with_request_timeout(CHUNK_REQUEST_TIMEOUT),
             ),
             commit_response: libp2p::request_response::cbor::Behaviour::new(
                 [(
@@ -139,9 +517,23 @@ impl NetworkManager {
                 )],
                 libp2p::request_response::Config::default(),
             ),
+            push: libp2p::request_response::cbor::Behaviour::new(
+                [(
+                    libp2p::StreamProtocol::new("/fai/push/1.0.0"),
+                    ProtocolSupport::Full,
+                )],
+                libp2p::request_response::Config::default().with_request_timeout(PUSH_TIMEOUT),
+            ),
+            refs: libp2p::request_response::cbor::Behaviour::new(
+                [(
+                    libp2p::StreamProtocol::new("/fai/refs/1.0.0"),
+                    ProtocolSupport::Full,
+                )],
+                libp2p::request_response::Config::default(),
+            ),
         };
 
-        // Create swarm using the new builder pattern with TCP transport
+        // Create swarm with TCP, QUIC and relayed transports
         let swarm = SwarmBuilder::with_existing_identity(local_key)
             .with_tokio()
             .with_tcp(
@@ -149,7 +541,9 @@ impl NetworkManager {
                 libp2p::noise::Config::new,
                 yamux::Config::default,
             )?
-            .with_behaviour(|_| behaviour)?
+            .with_quic()
+            .with_relay_client(libp2p::noise::Config::new, yamux::Config::default)?
+            .with_behaviour(make_behaviour)?
             .with_swarm_config(|c| {
                 c.with_idle_connection_timeout(std::time::Duration::from_secs(60))
             })
@@ -161,37 +555,85 @@ impl NetworkManager {
             storage,
             database,
             pending_commit_responses: std::collections::HashMap::new(),
+            pending_push_responses: HashMap::new(),
+            pending_refs_responses: HashMap::new(),
+            transfer: TransferConfig::default(),
+            network: NetworkConfig::default(),
+            fallback_addresses: HashMap::new(),
+            progress: Arc::default(),
+            uploads: bandwidth::UploadQueue::new(
+                TransferConfig::default().max_inbound_requests,
+                MAX_QUEUED_REQUESTS,
+                MAX_QUEUED_REQUESTS_PER_PEER,
+            ),
+            upload_limits: bandwidth::BandwidthLimiter::default(),
+            download_limits: bandwidth::BandwidthLimiter::default(),
+            provider_queries: HashMap::new(),
+            incoming_pushes: HashMap::new(),
+            push_object_requests: HashMap::new(),
+            receive: ReceiveConfig::default(),
         })
     }
 
     /// Start the network manager and begin listening
     ///
+    /// Listens on the addresses of the network configuration (ephemeral
+    /// TCP and QUIC ports unless [`Self::set_network_config`] was called),
+    /// and through each of its relays once the relay accepts a reservation.
+    /// Its external addresses are announced to peers through identify.
+    ///
     /// # Returns
-    /// Ok(()) if successfully started
+    /// Ok(()) once every listener has an address
     pub async fn start(&mut self) -> Result<()> {
         use futures::stream::StreamExt;
 
-        // Listen on all interfaces
-        self.swarm.listen_on("/ip4/0.0.0.0/tcp/0".parse()?)?;
+        let mut pending = std::collections::HashSet::new();
+        let mut relay_listeners = std::collections::HashSet::new();
+        for addr in &self.network.listen {
+            let addr: Multiaddr = addr
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid listen address '{}': {}", addr, e))?;
+            pending.insert(self.swarm.listen_on(addr)?);
+        }
+        for addr in &self.network.external {
+            let addr: Multiaddr = addr
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid external address '{}': {}", addr, e))?;
+            self.swarm.add_external_address(addr);
+        }
+        for relay in &self.network.relays {
+            let relay: Multiaddr = relay
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid relay address '{}': {}", relay, e))?;
+            if !matches!(relay.iter().last(), Some(Protocol::P2p(_))) {
+                return Err(anyhow::anyhow!("Relay address '{}' must end with /p2p/<peer id>", relay));
+            }
+            let listener = self.swarm.listen_on(relay.with(Protocol::P2pCircuit))?;
+            pending.insert(listener);
+            relay_listeners.insert(listener);
+        }
 
         // Process initial events to get listening addresses
-        let mut listening_addr = None;
-        while let Some(event) = self.swarm.next().await {
-            match event {
-                SwarmEvent::NewListenAddr { address, .. } => {
+        while !pending.is_empty() {
+            match self.swarm.next().await {
+                Some(SwarmEvent::NewListenAddr { listener_id, address }) => {
                     println!("Listening on {}", address);
-                    listening_addr = Some(address.clone());
-                    break;
+                    self.advertise_relay_address(&address);
+                    pending.remove(&listener_id);
                 }
-                _ => {}
+                // An unreachable relay shouldn't keep the node from starting
+                Some(SwarmEvent::ListenerClosed { listener_id, reason, .. }) if relay_listeners.contains(&listener_id) => {
+                    println!("Warning: Could not listen through relay: {:?}", reason);
+                    pending.remove(&listener_id);
+                }
+                Some(SwarmEvent::ListenerClosed { listener_id, addresses, reason }) if pending.contains(&listener_id) => {
+                    return Err(anyhow::anyhow!("Listener on {:?} closed: {:?}", addresses, reason));
+                }
+                Some(_) => {}
+                None => break,
             }
         }
 
-        // Write peer info to shared location for test discovery
-        if let Some(addr) = listening_addr {
-            self.write_peer_info_file(&addr).await?;
-        }
-
         Ok(())
     }
 
@@ -200,10 +642,8 @@ impl NetworkManager {
     /// # Returns
     /// Ok(()) if events processed successfully
     pub async fn poll_events(&mut self) -> Result<()> {
-        use futures::stream::StreamExt;
-
         // Use a timeout to avoid hanging indefinitely
-        match tokio::time::timeout(std::time::Duration::from_millis(100), self.swarm.next()).await {
+        match tokio::time::timeout(std::time::Duration::from_millis(100), self.next_event()).await {
             Ok(Some(event)) => {
                 self.handle_swarm_event(event).await?;
             }
@@ -222,12 +662,9 @@ impl NetworkManager {
     /// # Returns
     /// Ok(()) if events processed successfully
     async fn poll_events_for_connection(&mut self) -> Result<()> {
-        use futures::stream::StreamExt;
-
         // Use a longer timeout for connection establishment
-        match tokio::time::timeout(std::time::Duration::from_secs(2), self.swarm.next()).await {
+        match tokio::time::timeout(std::time::Duration::from_secs(2), self.next_event()).await {
             Ok(Some(event)) => {
-                println!("DEBUG: Connection event: {:?}", std::mem::discriminant(&event));
                 self.handle_swarm_event(event).await?;
             }
             Ok(None) => {
@@ -240,6 +677,83 @@ impl NetworkManager {
         Ok(())
     }
 
+    /// Wait for the next swarm event, answering queued chunk requests as the
+    /// upload limits allow in the meantime
+    async fn next_event(&mut self) -> Option<SwarmEvent<FAIBehaviourEvent>> {
+        loop {
+            let Some(ready_at) = self.upload_ready_at() else {
+                return self.swarm.next().await;
+            };
+            tokio::select! {
+                event = self.swarm.next() => return event,
+                _ = tokio::time::sleep_until(ready_at.into()) => self.serve_queued_requests(),
+            }
+        }
+    }
+
+    /// When the next queued chunk request may be answered
+    ///
+    /// # Returns
+    /// None if nothing is queued or as many requests as allowed are being answered
+    fn upload_ready_at(&self) -> Option<Instant> {
+        if self.uploads.is_full() {
+            return None;
+        }
+        let now = Instant::now();
+        self.uploads
+            .waiting_peers()
+            .map(|peer| self.upload_limits.ready_at(peer, now))
+            .min()
+    }
+
+    /// Answer queued chunk requests, taking turns between peers, until the
+    /// concurrency cap or the upload limits stop us
+    fn serve_queued_requests(&mut self) {
+        loop {
+            let now = Instant::now();
+            let limits = &self.upload_limits;
+            let Some((peer, request_id, (request, channel))) = self.uploads.pop(|peer| limits.is_ready(peer, now))
+            else {
+                return;
+            };
+
+            // A whole multi-chunk file would go out in one response, far past
+            // the limits; limited peers must ask for the manifest and chunks
+            let stored = if request.raw {
+                self.storage.read_object(&request.hash)
+            } else if self.upload_limits.is_limited()
+                && matches!(self.storage.read_manifest(&request.hash), Ok(Some(_)))
+            {
+                Err(anyhow::anyhow!("whole files larger than a chunk are not served under an upload limit"))
+            } else {
+                self.storage.retrieve(&request.hash)
+            };
+            let data = match stored {
+                Ok(data) => {
+                    println!("Successfully retrieved chunk {} ({} bytes)", request.hash, data.len());
+                    self.upload_limits.consume(peer, data.len(), now);
+                    Some(data)
+                }
+                Err(e) => {
+                    println!("Failed to retrieve chunk {}: {}", request.hash, e);
+                    None
+                }
+            };
+
+            let response = ChunkResponse {
+                hash: request.hash.clone(),
+                data,
+            };
+
+            if let Err(e) = self.swarm.behaviour_mut().request_response.send_response(channel, response) {
+                eprintln!("Failed to send response: {:?}", e);
+                self.uploads.finished(&request_id);
+            } else {
+                println!("Sent chunk {} to peer {}", request.hash, peer);
+            }
+        }
+    }
+
     /// Handle swarm events
     async fn handle_swarm_event(&mut self, event: SwarmEvent<FAIBehaviourEvent>) -> Result<()> {
         match event {
@@ -248,6 +762,8 @@ impl NetworkManager {
                     FAIBehaviourEvent::Mdns(mdns::Event::Discovered(list)) => {
                         for (peer_id, addr) in list {
                             println!("Discovered peer {} at {}", peer_id, addr);
+                            self.remember_address(peer_id, &addr);
+                            self.swarm.behaviour_mut().kad.add_address(&peer_id, addr.clone());
 
                             // Update peer info
                             let peer_info =
@@ -275,6 +791,24 @@ impl NetworkManager {
                             }
                         }
                     }
+                    FAIBehaviourEvent::Identify(identify::Event::Received { peer_id, info, .. }) => {
+                        let speaks_kad = info.protocols.iter().any(|protocol| protocol.as_ref() == KAD_PROTOCOL);
+                        for addr in info.listen_addrs {
+                            self.remember_address(peer_id, &addr);
+                            if speaks_kad {
+                                self.swarm.behaviour_mut().kad.add_address(&peer_id, addr.clone());
+                            }
+                            let peer_info = self.discovered_peers.entry(peer_id).or_insert_with(|| PeerInfo {
+                                peer_id,
+                                addresses: Vec::new(),
+                                last_seen: SystemTime::now(),
+                            });
+                            if !peer_info.addresses.contains(&addr) {
+                                peer_info.addresses.push(addr);
+                            }
+                            peer_info.last_seen = SystemTime::now();
+                        }
+                    }
                     FAIBehaviourEvent::Mdns(mdns::Event::Expired(list)) => {
                         for (peer_id, _addr) in list {
                             println!("Peer {} expired", peer_id);
@@ -286,50 +820,31 @@ impl NetworkManager {
                     ) => {
                         match message {
                             libp2p::request_response::Message::Request {
-                                request, channel, ..
+                                request_id,
+                                request,
+                                channel,
                             } => {
                                 println!("Received chunk request {} from {}", request.hash, peer);
-
-                                // Try to retrieve the data from storage
-                                let data = match self.storage.retrieve(&request.hash) {
-                                    Ok(data) => {
-                                        println!(
-                                            "Successfully retrieved chunk {} ({} bytes)",
-                                            request.hash,
-                                            data.len()
-                                        );
-                                        Some(data)
+                                if let Err((request, channel)) = self.uploads.push(peer, request_id, (request, channel)) {
+                                    println!("Too many queued requests, refusing chunk {} for {}", request.hash, peer);
+                                    let response = ChunkResponse { hash: request.hash, data: None };
+                                    if self.swarm.behaviour_mut().request_response.send_response(channel, response).is_err() {
+                                        eprintln!("Failed to refuse chunk request from {}", peer);
                                     }
-                                    Err(e) => {
-                                        println!(
-                                            "Failed to retrieve chunk {}: {}",
-                                            request.hash, e
-                                        );
-                                        None
-                                    }
-                                };
-
-                                let response = ChunkResponse {
-                                    hash: request.hash.clone(),
-                                    data,
-                                };
-
-                                if let Err(e) = self
-                                    .swarm
-                                    .behaviour_mut()
-                                    .request_response
-                                    .send_response(channel, response)
-                                {
-                                    eprintln!("Failed to send response: {:?}", e);
-                                } else {
-                                    println!("Sent chunk {} to peer {}", request.hash, peer);
                                 }
+                                self.serve_queued_requests();
                             }
                             libp2p::request_response::Message::Response {
                                 request_id,
                                 response,
                                 ..
                             } => {
+                                // Objects fetched for a push we are receiving
+                                if let Some((push_id, hash)) = self.push_object_requests.remove(&request_id) {
+                                    self.receive_push_object(push_id, &hash, response.data);
+                                    return Ok(());
+                                }
+
                                 let data_len = response.data.as_ref().map(|d| d.len()).unwrap_or(0);
                                 println!(
                                     "Received response for request {:?}: hash={}, data_len={}",
@@ -350,51 +865,66 @@ impl NetworkManager {
                                     peer, request.commit_hash
                                 );
 
-                                // Get commits from database and convert to storage::CommitInfo
-                                let commits: Vec<crate::CommitInfo> = if let Some(hash) = &request.commit_hash {
-                                    // Get specific commit
-                                    match self.database.get_commit(hash) {
-                                        Ok(Some(db_commit)) => {
-                                            vec![crate::CommitInfo {
+                                // Send what the requester lacks: commits reachable from the
+                                // wanted commit (or every branch and tag) but not from its haves
+                                let wants = match &request.commit_hash {
+                                    Some(hash) => vec![hash.clone()],
+                                    None => self.database.ref_tips(false).unwrap_or_default(),
+                                };
+                                let commits: Vec<crate::CommitInfo> =
+                                    match self.database.commits_between(&wants, &request.haves) {
+                                        Ok(db_commits) => db_commits
+                                            .into_iter()
+                                            .map(|db_commit| crate::CommitInfo {
                                                 hash: db_commit.hash,
                                                 message: db_commit.message,
                                                 timestamp: db_commit.timestamp,
                                                 parents: db_commit.parents,
                                                 is_merge: db_commit.is_merge,
-                                            }]
-                                        }
-                                        Ok(None) => {
-                                            println!("Commit {} not found", hash);
-                                            vec![]
-                                        }
+                                            })
+                                            .collect(),
                                         Err(e) => {
-                                            eprintln!("Error getting commit {}: {}", hash, e);
+                                            eprintln!("Error computing commits to send: {}", e);
                                             vec![]
                                         }
-                                    }
-                                } else {
-                                    // Get all commits
-                                    match self.database.get_commit_history(None) {
-                                        Ok(db_commits) => {
-                                            // Convert database::Commit to CommitInfo
-                                            db_commits.into_iter().map(|db_commit| {
-                                                crate::CommitInfo {
-                                                    hash: db_commit.hash,
-                                                    message: db_commit.message,
-                                                    timestamp: db_commit.timestamp,
-                                                    parents: db_commit.parents,
-                                                    is_merge: db_commit.is_merge,
-                                                }
-                                            }).collect()
-                                        },
-                                        Err(e) => {
-                                            eprintln!("Error getting all commits: {}", e);
-                                            vec![]
-                                        }
-                                    }
-                                };
+                                    };
+                                println!(
+                                    "Peer {} sent {} have(s), sending {} commit(s)",
+                                    peer,
+                                    request.haves.len(),
+                                    commits.len()
+                                );
 
-                                let response = CommitResponse { commits };
+                                // Attach the file snapshot of every commit we send
+                                let files = commits
+                                    .iter()
+                                    .filter_map(|commit| {
+                                        self.database
+                                            .get_commit_files(&commit.hash)
+                                            .ok()
+                                            .map(|files| (commit.hash.clone(), files))
+                                    })
+                                    .collect();
+
+                                // Share the tags that point at any of these commits
+                                let tags = self
+                                    .database
+                                    .list_tags()
+                                    .unwrap_or_default()
+                                    .into_iter()
+                                    .filter(|tag| commits.iter().any(|c| c.hash == tag.target))
+                                    .collect();
+
+                                // Advertise branch heads so the requester can track them
+                                let branches = self
+                                    .database
+                                    .list_branches()
+                                    .unwrap_or_default()
+                                    .into_iter()
+                                    .filter(|(_, head)| head != crate::database::EMPTY_COMMIT_HASH)
+                                    .collect();
+
+                                let response = CommitResponse { commits, files, tags, branches };
 
                                 println!(
                                     "Sending {} commits to peer {}",
@@ -416,22 +946,8 @@ impl NetworkManager {
                                 response,
                                 ..
                             } => {
-                                println!(
-                                    "DEBUG: Received commit response for request {:?}: {} commits",
-                                    request_id,
-                                    response.commits.len()
-                                );
-                                for (i, commit) in response.commits.iter().enumerate() {
-                                    println!(
-                                        "DEBUG: Commit {}: {} - {}",
-                                        i,
-                                        &commit.hash[..8],
-                                        commit.message
-                                    );
-                                }
-
                                 // Store the response for the request_commits method to retrieve
-                                self.pending_commit_responses.insert(request_id, response.commits);
+                                self.pending_commit_responses.insert(request_id, response);
                             }
                         }
                     }
@@ -447,6 +963,106 @@ impl NetworkManager {
                             request_id, error
                         );
                     }
+                    FAIBehaviourEvent::Push(libp2p::request_response::Event::Message { peer, message }) => {
+                        match message {
+                            libp2p::request_response::Message::Request {
+                                request_id,
+                                request,
+                                channel,
+                            } => {
+                                println!(
+                                    "Received push of {} ref(s) and {} commit(s) from {}",
+                                    request.updates.len(),
+                                    request.commits.len(),
+                                    peer
+                                );
+                                self.start_incoming_push(peer, request_id, request, channel);
+                            }
+                            libp2p::request_response::Message::Response { request_id, response } => {
+                                self.pending_push_responses.insert(request_id, response);
+                            }
+                        }
+                    }
+                    FAIBehaviourEvent::Push(libp2p::request_response::Event::OutboundFailure {
+                        request_id,
+                        error,
+                        ..
+                    }) => {
+                        self.pending_push_responses.insert(
+                            request_id,
+                            PushResponse {
+                                results: Vec::new(),
+                                error: Some(format!("Push request failed: {}", error)),
+                            },
+                        );
+                    }
+                    FAIBehaviourEvent::Refs(libp2p::request_response::Event::Message { peer, message }) => {
+                        match message {
+                            libp2p::request_response::Message::Request { channel, .. } => {
+                                println!("Received ref advertisement request from {}", peer);
+                                let response = self.advertised_refs();
+                                if let Err(e) = self.swarm.behaviour_mut().refs.send_response(channel, response) {
+                                    eprintln!("Failed to send refs response: {:?}", e);
+                                }
+                            }
+                            libp2p::request_response::Message::Response { request_id, response } => {
+                                self.pending_refs_responses.insert(request_id, Some(response));
+                            }
+                        }
+                    }
+                    FAIBehaviourEvent::Kad(kad::Event::OutboundQueryProgressed {
+                        id,
+                        result: kad::QueryResult::GetProviders(result),
+                        step,
+                        ..
+                    }) => {
+                        let local_peer_id = *self.swarm.local_peer_id();
+                        let mut found = Vec::new();
+                        let mut finished = step.last;
+                        match result {
+                            Ok(kad::GetProvidersOk::FoundProviders { providers, .. }) => {
+                                found.extend(
+                                    providers
+                                        .into_iter()
+                                        .filter(|peer| *peer != local_peer_id && !self.is_banned(peer)),
+                                );
+                            }
+                            Ok(kad::GetProvidersOk::FinishedWithNoAdditionalRecord { .. }) => finished = true,
+                            Err(e) => {
+                                println!("Provider lookup failed: {}", e);
+                                finished = true;
+                            }
+                        }
+
+                        // Provider addresses are only known to Kademlia while the query
+                        // runs, so dial the providers now
+                        for peer in &found {
+                            if !self.swarm.is_connected(peer) {
+                                let opts = DialOpts::peer_id(*peer)
+                                    .condition(libp2p::swarm::dial_opts::PeerCondition::DisconnectedAndNotDialing)
+                                    .build();
+                                if let Err(e) = self.swarm.dial(opts) {
+                                    println!("Failed to dial provider {}: {}", peer, e);
+                                }
+                            }
+                        }
+                        if let Some((providers, done)) = self.provider_queries.get_mut(&id) {
+                            for peer in found {
+                                if !providers.contains(&peer) {
+                                    providers.push(peer);
+                                }
+                            }
+                            *done |= finished;
+                        }
+                    }
+                    FAIBehaviourEvent::Refs(libp2p::request_response::Event::OutboundFailure {
+                        request_id,
+                        error,
+                        ..
+                    }) => {
+                        println!("Refs request failed: request_id={:?}, error={:?}", request_id, error);
+                        self.pending_refs_responses.insert(request_id, None);
+                    }
                     FAIBehaviourEvent::RequestResponse(
                         libp2p::request_response::Event::OutboundFailure {
                             request_id,
@@ -454,19 +1070,83 @@ impl NetworkManager {
                             error,
                         },
                     ) => {
+                        if let Some((push_id, hash)) = self.push_object_requests.remove(&request_id) {
+                            self.receive_push_object(push_id, &hash, None);
+                            return Ok(());
+                        }
                         println!(
                             "Chunk request failed: request_id={:?}, error={:?}",
                             request_id, error
                         );
                     }
+                    FAIBehaviourEvent::RequestResponse(libp2p::request_response::Event::ResponseSent {
+                        request_id,
+                        ..
+                    }) => {
+                        self.uploads.finished(&request_id);
+                        self.serve_queued_requests();
+                    }
+                    FAIBehaviourEvent::RequestResponse(libp2p::request_response::Event::InboundFailure {
+                        peer,
+                        request_id,
+                        error,
+                    }) => {
+                        if self.uploads.finished(&request_id) {
+                            println!("Failed to send chunk to {}: {}", peer, error);
+                        }
+                        self.serve_queued_requests();
+                    }
+                    FAIBehaviourEvent::Autonat(autonat::Event::StatusChanged { new, .. }) => {
+                        println!("NAT status: {:?}", new);
+                    }
+                    FAIBehaviourEvent::RelayClient(relay::client::Event::ReservationReqAccepted {
+                        relay_peer_id,
+                        renewal: false,
+                        ..
+                    }) => {
+                        println!("Reachable through relay {}", relay_peer_id);
+                    }
+                    FAIBehaviourEvent::RelayClient(relay::client::Event::InboundCircuitEstablished {
+                        src_peer_id,
+                        ..
+                    }) => {
+                        println!("Relayed connection from {}", src_peer_id);
+                    }
+                    FAIBehaviourEvent::Relay(relay::Event::ReservationReqAccepted {
+                        src_peer_id,
+                        renewed: false,
+                    }) => {
+                        println!("Relaying connections for {}", src_peer_id);
+                    }
+                    FAIBehaviourEvent::Relay(relay::Event::CircuitReqAccepted { src_peer_id, dst_peer_id }) => {
+                        println!("Relaying {} -> {}", src_peer_id, dst_peer_id);
+                    }
+                    FAIBehaviourEvent::Dcutr(dcutr::Event { remote_peer_id, result }) => match result {
+                        Ok(_) => println!("Upgraded relayed connection to {} to a direct one", remote_peer_id),
+                        Err(e) => println!("Hole punching to {} failed: {}", remote_peer_id, e),
+                    },
                     _ => {}
                 }
             }
-            SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+            SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
                 println!("✅ Connection established to {}", peer_id);
+                self.fallback_addresses.remove(&peer_id);
+                if self.is_banned(&peer_id) {
+                    println!("Disconnecting banned peer {}", peer_id);
+                    let _ = self.swarm.disconnect_peer_id(peer_id);
+                    return Ok(());
+                }
+                if endpoint.is_dialer() {
+                    self.record_dial(peer_id, endpoint.get_remote_address(), true);
+                }
             }
-            SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+            SwarmEvent::ConnectionClosed { peer_id, cause, num_established, .. } => {
                 println!("❌ Connection closed to {} (cause: {:?})", peer_id, cause);
+                if num_established == 0 {
+                    self.uploads.remove_peer(&peer_id);
+                    self.upload_limits.forget(&peer_id, Instant::now());
+                    self.download_limits.forget(&peer_id, Instant::now());
+                }
             }
             SwarmEvent::IncomingConnection { local_addr, send_back_addr, .. } => {
                 println!("🔗 Incoming connection from {} to {}", send_back_addr, local_addr);
@@ -476,9 +1156,26 @@ impl NetworkManager {
             }
             SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
                 println!("❌ Outgoing connection error to {:?}: {:?}", peer_id, error);
+                if let (Some(peer_id), DialError::Transport(attempts)) = (peer_id, &error) {
+                    for (addr, _) in attempts {
+                        self.record_dial(peer_id, addr, false);
+                    }
+                }
+                if let Some(peer_id) = peer_id {
+                    if let Some(addresses) = self.fallback_addresses.remove(&peer_id) {
+                        println!("QUIC dial to {} failed, trying {:?}", peer_id, addresses);
+                        if let Err(e) = self.swarm.dial(DialOpts::peer_id(peer_id).addresses(addresses).build()) {
+                            println!("Failed to dial {}: {:?}", peer_id, e);
+                        }
+                    }
+                }
+            }
+            SwarmEvent::ExternalAddrConfirmed { address } => {
+                println!("🌐 Reachable at {}", address);
             }
             SwarmEvent::NewListenAddr { address, .. } => {
                 println!("🎯 Listening on {}", address);
+                self.advertise_relay_address(&address);
             }
             _ => {}
         }
@@ -493,6 +1190,22 @@ impl NetworkManager {
         self.discovered_peers.values().cloned().collect()
     }
 
+    /// Get connected peers
+    ///
+    /// # Returns
+    /// Peers with at least one open connection
+    pub fn connected_peers(&self) -> Vec<PeerId> {
+        self.swarm.connected_peers().copied().collect()
+    }
+
+    /// Get a handle on the download progress
+    ///
+    /// The counters keep going up as objects are requested and stored, so
+    /// they can be read while a download runs.
+    pub fn transfer_progress(&self) -> Arc<Mutex<TransferProgress>> {
+        self.progress.clone()
+    }
+
     /// Get local peer ID
     ///
     /// # Returns
@@ -501,6 +1214,15 @@ impl NetworkManager {
         *self.swarm.local_peer_id()
     }
 
+    /// Get external addresses
+    ///
+    /// # Returns
+    /// Addresses announced to peers as reachable from outside: configured
+    /// external addresses and those AutoNAT confirmed
+    pub fn external_addresses(&self) -> Vec<Multiaddr> {
+        self.swarm.external_addresses().cloned().collect()
+    }
+
     /// Get listening addresses
     ///
     /// # Returns
@@ -509,6 +1231,11 @@ impl NetworkManager {
         self.swarm.listeners().cloned().collect()
     }
 
+    /// Use the push rules of a repository configuration
+    pub fn set_receive_config(&mut self, receive: ReceiveConfig) {
+        self.receive = receive;
+    }
+
     /// Connect to a peer by address
     ///
     /// # Arguments
@@ -522,84 +1249,131 @@ impl NetworkManager {
         Ok(())
     }
 
-    /// Write peer info to a shared file for test discovery
-    async fn write_peer_info_file(&self, addr: &Multiaddr) -> Result<()> {
-        use std::fs;
-        use std::io::Write;
-
-        let peer_id = *self.swarm.local_peer_id();
-
-        // Try to write to /tmp first (for tests), fallback to current directory
-        let locations = vec![
-            "/tmp/fai_test_peers.txt",  // Shared location for tests
-            "./fai_peers.txt",          // Local fallback
-        ];
+    /// Add the peers of the address book to the discovered peers
+    ///
+    /// Nothing is dialed; requests to a peer try its known addresses, most
+    /// promising first. The peers also seed the DHT routing table.
+    ///
+    /// # Returns
+    /// Number of peers loaded
+    pub fn load_address_book(&mut self) -> Result<usize> {
+        let local_peer_id = *self.swarm.local_peer_id();
+        let mut loaded = std::collections::HashSet::new();
+
+        for entry in self.database.list_peer_addresses()? {
+            let (Ok(peer_id), Ok(addr)) = (entry.peer_id.parse::<PeerId>(), entry.address.parse::<Multiaddr>()) else {
+                continue;
+            };
+            if peer_id == local_peer_id {
+                continue;
+            }
 
-        for location in locations {
-            if let Ok(mut file) = fs::OpenOptions::new().append(true).create(true).open(location) {
-                if let Err(e) = writeln!(file, "{} {}", peer_id, addr) {
-                    println!("Warning: Failed to write peer info to {}: {}", location, e);
-                } else {
-                    println!("DEBUG: Wrote peer info to {}: {} {}", location, peer_id, addr);
-                }
+            let last_seen = SystemTime::UNIX_EPOCH
+                + std::time::Duration::from_millis(entry.last_seen.timestamp_millis().max(0) as u64);
+            let peer_info = self.discovered_peers.entry(peer_id).or_insert_with(|| PeerInfo {
+                peer_id,
+                addresses: Vec::new(),
+                last_seen,
+            });
+            if !peer_info.addresses.contains(&addr) {
+                peer_info.addresses.push(addr.clone());
             }
+            peer_info.last_seen = peer_info.last_seen.max(last_seen);
+            self.swarm.behaviour_mut().kad.add_address(&peer_id, addr);
+            loaded.insert(peer_id);
         }
 
-        Ok(())
+        Ok(loaded.len())
     }
 
-    /// Load peer info from shared files (useful for testing)
-    pub fn load_peers_from_files(&mut self) -> Result<usize> {
-        use std::fs;
-        use std::io::BufRead;
-
-        let locations = vec![
-            "/tmp/fai_test_peers.txt",  // Shared location for tests
-            "./fai_peers.txt",          // Local fallback
-        ];
-
-        let mut loaded_count = 0;
-
-        for location in locations {
-            if let Ok(file) = fs::File::open(location) {
-                let reader = std::io::BufReader::new(file);
-                for line in reader.lines() {
-                    if let Ok(line) = line {
-                        let parts: Vec<&str> = line.trim().split_whitespace().collect();
-                        if parts.len() >= 2 {
-                            if let Ok(peer_id) = parts[0].parse::<PeerId>() {
-                                if let Ok(addr) = parts[1].parse::<Multiaddr>() {
-                                    // Skip if this is our own peer ID
-                                    if peer_id != *self.swarm.local_peer_id() {
-                                        let addr_clone = addr.clone();
-                                        if let Err(e) = self.add_peer_manually(peer_id, addr) {
-                                            println!("Warning: Failed to add peer {}: {}", peer_id, e);
-                                        } else {
-                                            loaded_count += 1;
-                                            println!("DEBUG: Loaded peer from file: {} {}", peer_id, addr_clone);
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
+    /// Addresses to dial a peer at, most promising first
+    ///
+    /// Address book entries come in the book's order, followed by addresses
+    /// seen in this session that are not in the book yet.
+    fn known_addresses(&self, peer: PeerId) -> Vec<Multiaddr> {
+        let mut addresses: Vec<Multiaddr> = self
+            .database
+            .peer_addresses(&peer.to_string())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|entry| entry.address.parse().ok())
+            .collect();
+        if let Some(peer_info) = self.discovered_peers.get(&peer) {
+            for addr in &peer_info.addresses {
+                let addr = without_peer_id(addr);
+                if !addresses.contains(&addr) {
+                    addresses.push(addr);
                 }
             }
         }
-
-        Ok(loaded_count)
+        addresses
     }
 
-    /// Add a peer to the discovered peers list manually
-    ///
-    /// # Arguments
-    /// * `peer_id` - The peer ID
-    /// * `addr` - The multiaddress of the peer
+    /// Start dialing a peer at its known addresses
     ///
     /// # Returns
-    /// Ok(()) if peer added successfully
-    pub fn add_peer_manually(&mut self, peer_id: PeerId, addr: Multiaddr) -> Result<()> {
-        println!("Manually adding peer {} at {}", peer_id, addr);
+    /// false if the peer has no known addresses or the dial could not start
+    fn dial_known_addresses(&mut self, peer: PeerId) -> bool {
+        let addresses = self.known_addresses(peer);
+        if addresses.is_empty() {
+            return false;
+        }
+
+        match self.dial_preferring_quic(peer, addresses) {
+            // Already being dialed, e.g. at a remote's configured address
+            Ok(()) | Err(DialError::DialPeerConditionFalse(_)) => true,
+            Err(e) => {
+                println!("Failed to dial {}: {:?}", peer, e);
+                false
+            }
+        }
+    }
+
+    /// Dial a peer at its QUIC addresses, if it has any, before the others
+    ///
+    /// The other addresses are kept in `fallback_addresses` and dialed if
+    /// every QUIC address fails.
+    fn dial_preferring_quic(&mut self, peer: PeerId, addresses: Vec<Multiaddr>) -> Result<(), DialError> {
+        let (quic, other): (Vec<_>, Vec<_>) = addresses.into_iter().partition(is_quic);
+        let (first, fallback) = if quic.is_empty() { (other, Vec::new()) } else { (quic, other) };
+
+        self.swarm.dial(DialOpts::peer_id(peer).addresses(first).build())?;
+        if !fallback.is_empty() {
+            self.fallback_addresses.insert(peer, fallback);
+        }
+        Ok(())
+    }
+
+    /// Add an address a peer was seen at to the address book
+    fn remember_address(&self, peer: PeerId, addr: &Multiaddr) {
+        if let Err(e) = self
+            .database
+            .record_peer_address(&peer.to_string(), &without_peer_id(addr).to_string())
+        {
+            println!("Warning: Failed to update address book for {}: {}", peer, e);
+        }
+    }
+
+    /// Record the outcome of a dial in the address book
+    fn record_dial(&self, peer: PeerId, addr: &Multiaddr, success: bool) {
+        if let Err(e) = self
+            .database
+            .record_dial_result(&peer.to_string(), &without_peer_id(addr).to_string(), success)
+        {
+            println!("Warning: Failed to update address book for {}: {}", peer, e);
+        }
+    }
+
+    /// Add a peer to the discovered peers list manually
+    ///
+    /// # Arguments
+    /// * `peer_id` - The peer ID
+    /// * `addr` - The multiaddress of the peer
+    ///
+    /// # Returns
+    /// Ok(()) if peer added successfully
+    pub fn add_peer_manually(&mut self, peer_id: PeerId, addr: Multiaddr) -> Result<()> {
+        println!("Manually adding peer {} at {}", peer_id, addr);
 
         let peer_info = self
             .discovered_peers
@@ -620,6 +1394,32 @@ impl NetworkManager {
         self.connect_to_peer(addr)
     }
 
+    /// Dial the configured addresses of a remote
+    ///
+    /// QUIC addresses are tried before the others. Remotes without
+    /// addresses are left to peer discovery.
+    pub fn add_remote(&mut self, remote: &remote::Remote) {
+        if remote.addresses.is_empty() {
+            return;
+        }
+
+        let peer_info = self.discovered_peers.entry(remote.peer_id).or_insert_with(|| PeerInfo {
+            peer_id: remote.peer_id,
+            addresses: Vec::new(),
+            last_seen: SystemTime::now(),
+        });
+        for addr in &remote.addresses {
+            if !peer_info.addresses.contains(addr) {
+                peer_info.addresses.push(addr.clone());
+            }
+        }
+
+        println!("Dialing {} at {:?}", remote.name, remote.addresses);
+        if let Err(e) = self.dial_preferring_quic(remote.peer_id, remote.addresses.clone()) {
+            println!("Warning: Failed to dial {}: {}", remote.name, e);
+        }
+    }
+
     /// Connect to multiple known peers (useful for testing)
     ///
     /// # Arguments
@@ -648,85 +1448,317 @@ impl NetworkManager {
     /// # Returns
     /// The data if found, None if not found
     pub async fn request_chunk(&mut self, peer: PeerId, hash: &str) -> Result<Option<Vec<u8>>> {
-        // Always check if we need to establish a connection
-        let is_connected = self.swarm.is_connected(&peer);
-        println!("DEBUG: Peer {} is_connected: {}", peer, is_connected);
+        self.send_chunk_request(peer, ChunkRequest { hash: hash.to_string(), raw: false }).await
+    }
+
+    /// Announce in the DHT that this node provides some content
+    pub fn provide(&mut self, content: Content<'_>) -> Result<()> {
+        self.swarm
+            .behaviour_mut()
+            .kad
+            .start_providing(content.key())
+            .map_err(|e| anyhow::anyhow!("Failed to announce {:?}: {:?}", content, e))?;
+        Ok(())
+    }
+
+    /// Announce every commit in the database and every file they include
+    ///
+    /// # Returns
+    /// Number of provider records published
+    pub fn provide_local_content(&mut self) -> Result<usize> {
+        let mut files = std::collections::HashSet::new();
+        let mut announced = 0;
+        for commit in self.database.get_all_commits()? {
+            self.provide(Content::Commit(&commit.hash))?;
+            announced += 1;
+            for (_, file_hash, _) in self.database.get_commit_files(&commit.hash)? {
+                if self.storage.exists(&file_hash) && files.insert(file_hash.clone()) {
+                    self.provide(Content::Object(&file_hash))?;
+                    announced += 1;
+                }
+            }
+        }
+        Ok(announced)
+    }
 
-        if !is_connected {
-            println!("DEBUG: Peer {} not connected, attempting to establish connection", peer);
+    /// Look up the peers that provide some content in the DHT
+    ///
+    /// Providers are dialed as they are found; only those we end up
+    /// connected to are returned.
+    ///
+    /// # Returns
+    /// Connected providers, excluding this node
+    pub async fn find_providers(&mut self, content: Content<'_>) -> Result<Vec<PeerId>> {
+        let query_id = self.swarm.behaviour_mut().kad.get_providers(content.key());
+        self.provider_queries.insert(query_id, (Vec::new(), false));
+
+        let start_time = std::time::Instant::now();
+        while start_time.elapsed() < DHT_QUERY_TIMEOUT {
+            let done = self.provider_queries.get(&query_id).is_none_or(|(providers, finished)| {
+                *finished && providers.iter().all(|peer| self.swarm.is_connected(peer))
+            });
+            if done {
+                break;
+            }
+            self.poll_events().await?;
+        }
 
-            // Try to find addresses for this peer, prioritize localhost
-            if let Some(peer_info) = self.discovered_peers.get(&peer) {
-                let mut addrs = peer_info.addresses.clone();
+        let (providers, _) = self.provider_queries.remove(&query_id).unwrap_or_default();
+        Ok(providers
+            .into_iter()
+            .filter(|peer| self.swarm.is_connected(peer))
+            .collect())
+    }
 
-                // Prioritize localhost addresses for local testing
-                addrs.sort_by(|a, b| {
-                    let a_is_localhost = a.to_string().contains("127.0.0.1");
-                    let b_is_localhost = b.to_string().contains("127.0.0.1");
-                    b_is_localhost.cmp(&a_is_localhost) // localhost first
-                });
+    /// Use the transfer settings of a repository configuration
+    ///
+    /// Resets the bandwidth limits and drops queued chunk requests, so call
+    /// it before serving.
+    pub fn set_transfer_config(&mut self, transfer: TransferConfig) {
+        self.uploads = bandwidth::UploadQueue::new(
+            transfer.max_inbound_requests,
+            MAX_QUEUED_REQUESTS,
+            MAX_QUEUED_REQUESTS_PER_PEER,
+        );
+        self.upload_limits = bandwidth::BandwidthLimiter::new(transfer.upload_limit, transfer.peer_upload_limit);
+        self.download_limits =
+            bandwidth::BandwidthLimiter::new(transfer.download_limit, transfer.peer_download_limit);
+        self.transfer = transfer;
+    }
 
-                println!("DEBUG: Available addresses for {}: {:?}", peer, addrs);
+    /// Use the network settings of a repository configuration
+    ///
+    /// Must be called before [`Self::start`] for the listen addresses to apply.
+    pub fn set_network_config(&mut self, network: NetworkConfig) {
+        self.network = network;
+    }
 
-                for addr in &addrs {
-                    println!("DEBUG: Attempting to dial {} at {}", peer, addr);
-                    if let Err(e) = self.swarm.dial(addr.clone()) {
-                        println!("Failed to dial {} at {}: {:?}", peer, addr, e);
-                    } else {
-                        println!("DEBUG: Dialing {} at {} initiated", peer, addr);
-                        // Try one address at a time for now
-                        break;
-                    }
-                }
+    /// Peers to download from: `preferred` first, then every other connected peer
+    ///
+    /// Banned peers are left out.
+    pub fn download_peers(&self, preferred: PeerId) -> Vec<PeerId> {
+        std::iter::once(preferred)
+            .chain(self.swarm.connected_peers().copied().filter(|peer| *peer != preferred))
+            .filter(|peer| !self.is_banned(peer))
+            .collect()
+    }
 
-                // Wait a bit for connection to establish while processing events
-                println!("DEBUG: Starting connection waiting loop...");
-                for attempt in 0..50 {
-                    // Process any pending swarm events with longer timeout for connections
-                    self.poll_events_for_connection().await?;
+    /// On relay nodes, treat a listen address as reachable from outside
+    ///
+    /// Relays hand their external addresses to the peers that reserve a
+    /// slot, so a relay needs some before any reservation succeeds.
+    fn advertise_relay_address(&mut self, address: &Multiaddr) {
+        if self.swarm.behaviour().relay.is_enabled() && !address.iter().any(|p| p == Protocol::P2pCircuit) {
+            self.swarm.add_external_address(address.clone());
+        }
+    }
 
-                    let current_peers = self.swarm.connected_peers().collect::<Vec<_>>();
-                    if current_peers.iter().any(|p| **p == peer) {
-                        println!("✅ Successfully connected to peer {}", peer);
-                        break;
-                    }
+    /// Whether a peer is banned for sending data that failed verification
+    fn is_banned(&self, peer: &PeerId) -> bool {
+        self.database.is_peer_banned(&peer.to_string()).unwrap_or_else(|e| {
+            println!("Warning: Failed to look up reputation of {}: {}", peer, e);
+            false
+        })
+    }
 
-                    if attempt % 5 == 0 {  // Print more frequently
-                        println!("🔄 Waiting for connection to {} (attempt {}/50, connected peers: {})",
-                                peer, attempt + 1, current_peers.len());
-                    }
-                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    /// Count an object that failed verification against the peer that sent it
+    ///
+    /// A peer that gets banned by this is disconnected.
+    fn report_bad_object(&mut self, peer: PeerId, hash: &str) {
+        println!("Warning: Peer {} sent data for {} that doesn't match its hash", peer, hash);
+        match self.database.record_bad_object(&peer.to_string()) {
+            Ok(Some(banned_until)) => {
+                println!(
+                    "Banning peer {} until {} for sending bad data",
+                    peer,
+                    banned_until.format("%Y-%m-%d %H:%M:%S UTC")
+                );
+                let _ = self.swarm.disconnect_peer_id(peer);
+            }
+            Ok(None) => {}
+            Err(e) => println!("Warning: Failed to record bad object from {}: {}", peer, e),
+        }
+    }
+
+    /// Fetch a committed file, requesting only the objects we don't have
+    ///
+    /// A multi-chunk file is fetched as its manifest followed by the chunks
+    /// missing locally, so a partially present file only transfers the rest.
+    /// Chunks are requested in parallel from all `peers` and the file's
+    /// providers in the DHT (see [`download::DownloadScheduler`]), and every
+    /// object is verified against its hash before it is stored. A peer that
+    /// sends a bad object is dropped from the download and counted towards
+    /// a ban.
+    ///
+    /// # Arguments
+    /// * `peers` - Peers to fetch from, preferred peers first
+    /// * `hash` - Hash of the file (as recorded in a commit)
+    ///
+    /// # Returns
+    /// Number of objects downloaded
+    pub async fn fetch_file(&mut self, peers: &[PeerId], hash: &str) -> Result<usize> {
+        if self.storage.exists(hash) && self.storage.missing_chunks(hash)?.is_empty() {
+            return Ok(0);
+        }
+
+        let mut peers = peers.to_vec();
+        for provider in self.find_providers(Content::Object(hash)).await? {
+            if !peers.contains(&provider) {
+                peers.push(provider);
+            }
+        }
+        let peers = peers.as_slice();
+
+        let mut downloaded = 0;
+        if !self.storage.exists(hash) {
+            downloaded += self.download_objects(peers, vec![hash.to_string()]).await?;
+        }
+        let missing = self.storage.missing_chunks(hash)?;
+        downloaded += self.download_objects(peers, missing).await?;
+        Ok(downloaded)
+    }
+
+    /// Download stored objects from several peers at once
+    ///
+    /// # Returns
+    /// Number of objects downloaded
+    async fn download_objects(&mut self, peers: &[PeerId], objects: Vec<String>) -> Result<usize> {
+        if objects.is_empty() {
+            return Ok(0);
+        }
+
+        let mut connected = Vec::new();
+        for &peer in peers {
+            if self.ensure_connected(peer).await? {
+                connected.push(peer);
+            }
+        }
+        if connected.is_empty() {
+            return Err(anyhow::anyhow!("Not connected to any peer to download from"));
+        }
+
+        if let Ok(mut progress) = self.progress.lock() {
+            progress.objects_total += objects.len();
+        }
+        let mut scheduler = download::DownloadScheduler::new(
+            objects,
+            connected,
+            self.transfer.max_in_flight,
+            self.transfer.chunk_selection,
+        );
+        let mut requests: HashMap<libp2p::request_response::OutboundRequestId, (PeerId, String)> =
+            HashMap::new();
+
+        while !scheduler.is_done() {
+            let now = Instant::now();
+            let limits = &self.download_limits;
+            for (peer, hash) in scheduler.next_requests_where(|peer| limits.is_ready(peer, now)) {
+                let request_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .request_response
+                    .send_request(&peer, ChunkRequest { hash: hash.clone(), raw: true });
+                requests.insert(request_id, (peer, hash));
+            }
+
+            // Peers held back by the download limits get more requests once they catch up
+            let resume_at = scheduler
+                .peers()
+                .iter()
+                .map(|peer| self.download_limits.ready_at(peer, now))
+                .filter(|at| *at > now)
+                .min();
+            if requests.is_empty() && resume_at.is_none() {
+                let hash = scheduler.unavailable_chunk().unwrap_or_default();
+                return Err(anyhow::anyhow!("Object {} not available from any peer", hash));
+            }
+
+            let wait = resume_at.map_or(DOWNLOAD_STALL_TIMEOUT, |at| (at - now).min(DOWNLOAD_STALL_TIMEOUT));
+            let event = match tokio::time::timeout(wait, self.next_event()).await {
+                Ok(Some(event)) => event,
+                Ok(None) => return Err(anyhow::anyhow!("Network stopped during download")),
+                Err(_) if resume_at.is_some() => continue,
+                Err(_) => {
+                    return Err(anyhow::anyhow!(
+                        "Download stalled: no response from {} peer(s) in {}s",
+                        scheduler.peers().len(),
+                        DOWNLOAD_STALL_TIMEOUT.as_secs()
+                    ))
                 }
+            };
 
-                // Final check
-                let final_connected_peers = self.swarm.connected_peers().collect::<Vec<_>>();
-                if !final_connected_peers.iter().any(|p| **p == peer) {
-                    println!("❌ Failed to establish connection to {} after 50 attempts", peer);
-                    println!("DEBUG: Final connected peers: {:?}", final_connected_peers);
+            match event {
+                SwarmEvent::Behaviour(FAIBehaviourEvent::RequestResponse(
+                    libp2p::request_response::Event::Message {
+                        message: libp2p::request_response::Message::Response { request_id, response },
+                        ..
+                    },
+                )) if requests.contains_key(&request_id) => {
+                    let Some((peer, hash)) = requests.remove(&request_id) else {
+                        continue;
+                    };
+                    match response.data {
+                        Some(data) if matches_hash(&hash, &data, true)? => {
+                            self.download_limits.consume(peer, data.len(), Instant::now());
+                            self.storage.store_object(&hash, &data)?;
+                            scheduler.completed(peer, &hash);
+                            self.database.record_transfer_object(&hash)?;
+                            if let Ok(mut progress) = self.progress.lock() {
+                                progress.objects_done += 1;
+                            }
+                        }
+                        Some(_) => {
+                            eprintln!("Dropping peer {} from download: object {} failed verification", peer, hash);
+                            self.report_bad_object(peer, &hash);
+                            scheduler.peer_failed(peer);
+                            requests.retain(|_, (p, _)| *p != peer);
+                        }
+                        None => scheduler.not_found(peer, &hash),
+                    }
                 }
-            } else {
-                println!("DEBUG: No address information found for peer {}", peer);
+                SwarmEvent::Behaviour(FAIBehaviourEvent::RequestResponse(
+                    libp2p::request_response::Event::OutboundFailure { request_id, peer, error },
+                )) if requests.contains_key(&request_id) => {
+                    eprintln!("Dropping peer {} from download: {}", peer, error);
+                    scheduler.peer_failed(peer);
+                    requests.retain(|_, (p, _)| *p != peer);
+                }
+                event => self.handle_swarm_event(event).await?,
             }
         }
 
+        Ok(scheduler.completed_count())
+    }
+
+    /// Send a chunk request, connecting first if needed, and wait for the data
+    ///
+    /// # Returns
+    /// The data, verified against the requested hash; an error if it
+    /// doesn't match or the peer is banned
+    async fn send_chunk_request(&mut self, peer: PeerId, request: ChunkRequest) -> Result<Option<Vec<u8>>> {
+        if self.is_banned(&peer) {
+            return Err(anyhow::anyhow!("Peer {} is banned for sending bad data", peer));
+        }
+
+        // Always check if we need to establish a connection
+        if !self.swarm.is_connected(&peer) && !self.ensure_connected(peer).await? {
+            println!("❌ Failed to establish connection to {}", peer);
+        }
+
         // Ensure we're connected before sending request
         if !self.swarm.is_connected(&peer) {
             return Ok(None);
         }
 
-        let request_id = self.swarm.behaviour_mut().request_response.send_request(
-            &peer,
-            ChunkRequest {
-                hash: hash.to_string(),
-            },
-        );
+        let (hash, raw) = (request.hash.clone(), request.raw);
+        let request_id = self.swarm.behaviour_mut().request_response.send_request(&peer, request);
 
         // Wait for response with timeout
         let timeout_duration = std::time::Duration::from_secs(10);
         let start_time = std::time::Instant::now();
 
         while start_time.elapsed() < timeout_duration {
-            if let Some(event) = self.swarm.next().await {
+            if let Some(event) = self.next_event().await {
                 match event {
                     SwarmEvent::Behaviour(FAIBehaviourEvent::RequestResponse(
                         libp2p::request_response::Event::Message {
@@ -739,7 +1771,17 @@ impl NetworkManager {
                         },
                     )) => {
                         if response_id == request_id {
-                            return Ok(response.data);
+                            return match response.data {
+                                Some(data) if !matches_hash(&hash, &data, raw)? => {
+                                    self.report_bad_object(peer, &hash);
+                                    Err(anyhow::anyhow!("Data from peer {} doesn't match hash {}", peer, hash))
+                                }
+                                Some(data) => {
+                                    self.download_limits.consume(peer, data.len(), Instant::now());
+                                    Ok(Some(data))
+                                }
+                                None => Ok(None),
+                            };
                         }
                     }
                     SwarmEvent::Behaviour(FAIBehaviourEvent::RequestResponse(
@@ -767,6 +1809,8 @@ impl NetworkManager {
     ///
     /// # Arguments
     /// * `peer` - The peer to request from
+    /// * `remote` - Remote name the peer's branches are tracked under
+    ///   (`refs/remotes/<remote>/<branch>`)
     /// * `commit_hash` - Optional specific commit hash to request
     ///
     /// # Returns
@@ -774,77 +1818,16 @@ impl NetworkManager {
     pub async fn request_commits(
         &mut self,
         peer: PeerId,
+        remote: &str,
         commit_hash: Option<String>,
     ) -> Result<Vec<crate::storage::CommitInfo>> {
-        println!(
-            "DEBUG: request_commits called with peer={}, commit_hash={:?}",
-            peer, commit_hash
-        );
-
         // Always check if we need to establish a connection
-        let is_connected = self.swarm.is_connected(&peer);
-        println!("DEBUG: Peer {} is_connected: {}", peer, is_connected);
-        let connected_peers = self.swarm.connected_peers().collect::<Vec<_>>();
-        println!(
-            "DEBUG: Currently connected to {} peers: {:?}",
-            connected_peers.len(),
-            connected_peers
-        );
-
-        if !is_connected {
-            println!("DEBUG: Peer {} is not connected, attempting to dial", peer);
-            // Try to find addresses for this peer
-            if let Some(peer_info) = self.discovered_peers.get(&peer) {
-                println!(
-                    "DEBUG: Found {} addresses for peer {}",
-                    peer_info.addresses.len(),
-                    peer
-                );
-                for addr in &peer_info.addresses {
-                    println!("DEBUG: Attempting to dial {} at {}", peer, addr);
-                    if let Err(e) = self.swarm.dial(addr.clone()) {
-                        println!("DEBUG: Failed to dial {} at {}: {:?}", peer, addr, e);
-                    } else {
-                        println!("DEBUG: Dialing {} at {} initiated", peer, addr);
-                    }
-                }
-
-                // Wait a bit for connection to establish while processing events
-                println!("DEBUG: Starting connection waiting loop for commits...");
-                for attempt in 0..50 {
-                    // Process any pending swarm events with longer timeout for connections
-                    self.poll_events_for_connection().await?;
-
-                    let current_peers = self.swarm.connected_peers().collect::<Vec<_>>();
-                    if current_peers.iter().any(|p| **p == peer) {
-                        println!("✅ Successfully connected to peer {}", peer);
-                        break;
-                    }
-
-                    if attempt % 5 == 0 {  // Print more frequently
-                        println!("🔄 Waiting for connection to {} (attempt {}/50, connected peers: {})",
-                                peer, attempt + 1, current_peers.len());
-                    }
-                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-                }
-
-                // Final check
-                let final_connected_peers = self.swarm.connected_peers().collect::<Vec<_>>();
-                if !final_connected_peers.iter().any(|p| **p == peer) {
-                    println!("❌ Failed to establish connection to {} after 50 attempts", peer);
-                    println!("DEBUG: Final connected peers: {:?}", final_connected_peers);
-                }
-            } else {
-                println!("DEBUG: No addresses found for peer {}", peer);
-            }
+        if !self.swarm.is_connected(&peer) && !self.ensure_connected(peer).await? {
+            println!("❌ Failed to establish connection to {}", peer);
         }
 
         // Ensure we're connected before sending request
         if !self.swarm.is_connected(&peer) {
-            println!(
-                "DEBUG: Not connected to peer {}, cannot send commit request",
-                peer
-            );
             return Ok(vec![]);
         }
 
@@ -852,14 +1835,10 @@ impl NetworkManager {
             &peer,
             CommitRequest {
                 commit_hash: commit_hash.clone(),
+                haves: self.database.fetch_haves()?,
             },
         );
 
-        println!(
-            "DEBUG: Sent commit request to peer {}, request_id={:?}",
-            peer, request_id
-        );
-
         // Wait for response with timeout
         let timeout_duration = std::time::Duration::from_secs(10);
         let start_time = std::time::Instant::now();
@@ -871,21 +1850,16 @@ impl NetworkManager {
             }
 
             // Check if we've received a response for this request
-            if let Some(commits) = self.pending_commit_responses.get(&request_id) {
-                println!("DEBUG: Received {} commits from peer", commits.len());
-
-                // Store commits locally
-                for commit in commits {
-                    // Use the timestamp from CommitInfo directly (it's already DateTime<Utc>)
-                    let _timestamp = commit.timestamp;
-
-                    // For create_commit, we need (hash, message, parents, files)
-                    // Since CommitInfo doesn't include file information, we need to get it separately
-                    let files = if let Ok(db_files) = self.database.get_commit_files(&commit.hash) {
-                        db_files
-                    } else {
-                        vec![] // Empty file list if we can't get files
-                    };
+            if let Some(response) = self.pending_commit_responses.remove(&request_id) {
+                // Refuse the whole response if any snapshot could escape the working tree
+                for (path, _, _) in response.files.values().flatten() {
+                    crate::database::check_file_path(path)
+                        .map_err(|e| anyhow::anyhow!("Peer {} sent a bad snapshot: {}", peer, e))?;
+                }
+
+                // Store commits locally together with their file snapshots
+                for commit in &response.commits {
+                    let files = response.files.get(&commit.hash).cloned().unwrap_or_default();
 
                     if let Err(e) = self.database.create_commit(
                         &commit.hash,
@@ -898,35 +1872,766 @@ impl NetworkManager {
                     }
                 }
 
-                // Return a copy of the commits and remove from pending
-                let result = commits.clone();
-                self.pending_commit_responses.remove(&request_id);
-                return Ok(result);
+                // Store tags we don't have yet; never move an existing local tag
+                for tag in &response.tags {
+                    match self.database.get_tag(&tag.name) {
+                        Ok(Some(existing)) if existing.target != tag.target => {
+                            println!(
+                                "Warning: Tag '{}' differs from the peer's version, keeping local tag",
+                                tag.name
+                            );
+                        }
+                        Ok(Some(_)) => {}
+                        _ => match self.database.save_tag(tag) {
+                            Ok(()) => println!("Fetched tag {}", tag.name),
+                            Err(e) => println!("Warning: Failed to store tag {}: {}", tag.name, e),
+                        },
+                    }
+                }
+
+                // Record where the peer's branches are; skip heads we did not receive
+                for (branch, head) in &response.branches {
+                    if !matches!(self.database.get_commit(head), Ok(Some(_))) {
+                        continue;
+                    }
+                    let operation = format!("pull: from {}", remote);
+                    if let Err(e) = self.database.update_remote_ref(remote, branch, head, &operation) {
+                        println!("Warning: Failed to update {}/{}: {}", remote, branch, e);
+                    }
+                }
+
+                return Ok(response.commits);
             }
 
             tokio::time::sleep(std::time::Duration::from_millis(100)).await;
         }
 
-        println!("DEBUG: Timeout waiting for commit response");
+        println!("Warning: Timed out waiting for commits from {}", peer);
         Ok(vec![])
     }
 
-    /// Send commits to a peer (主动推送)
+    /// Ask a peer which branches and tags it has
     ///
     /// # Arguments
-    /// * `peer` - The peer to send commits to
-    /// * `commits` - The commits to send
+    /// * `peer` - The peer to ask
     ///
     /// # Returns
-    /// Ok(()) if commits were sent successfully
-    pub async fn send_commits(
+    /// The peer's checked out branch and its branch and tag refs
+    pub async fn list_remote_refs(&mut self, peer: PeerId) -> Result<RefsResponse> {
+        if !self.ensure_connected(peer).await? {
+            return Err(anyhow::anyhow!("Could not connect to peer {}", peer));
+        }
+
+        let request_id = self.swarm.behaviour_mut().refs.send_request(&peer, RefsRequest {});
+
+        let timeout_duration = std::time::Duration::from_secs(10);
+        let start_time = std::time::Instant::now();
+        while start_time.elapsed() < timeout_duration {
+            if let Err(e) = self.poll_events().await {
+                println!("Warning: Error during response polling: {}", e);
+            }
+            if let Some(response) = self.pending_refs_responses.remove(&request_id) {
+                return response.ok_or_else(|| anyhow::anyhow!("Peer {} did not answer the refs request", peer));
+            }
+        }
+
+        Err(anyhow::anyhow!("Timed out waiting for refs from peer {}", peer))
+    }
+
+    /// Our branches (with commits) and tags, as advertised to peers
+    fn advertised_refs(&self) -> RefsResponse {
+        let mut refs: Vec<AdvertisedRef> = self
+            .database
+            .list_branches()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(_, head)| head != crate::database::EMPTY_COMMIT_HASH)
+            .map(|(branch, head)| AdvertisedRef {
+                name: format!("refs/heads/{}", branch),
+                target: head,
+            })
+            .collect();
+        refs.extend(self.database.list_tags().unwrap_or_default().into_iter().map(|tag| AdvertisedRef {
+            name: format!("refs/tags/{}", tag.name),
+            target: tag.target,
+        }));
+
+        RefsResponse {
+            head: self.database.get_current_branch().ok(),
+            refs,
+        }
+    }
+
+    /// Push branch updates to a peer
+    ///
+    /// Asks the peer for its refs first and sends only the commits reachable
+    /// from each new head but not from a peer ref we know; the peer fetches
+    /// the objects it lacks from us while we wait for its status report.
+    /// Accepted branch updates move the matching remote-tracking refs.
+    ///
+    /// # Arguments
+    /// * `peer` - The peer to push to
+    /// * `remote` - Remote name the peer's branches are tracked under
+    /// * `updates` - Branch updates to request
+    ///
+    /// # Returns
+    /// The peer's per-ref status report
+    pub async fn push(&mut self, peer: PeerId, remote: &str, updates: Vec<RefUpdate>) -> Result<PushResponse> {
+        if !self.ensure_connected(peer).await? {
+            return Err(anyhow::anyhow!("Could not connect to peer {}", peer));
+        }
+
+        // The peer's refs we know are the haves: it has everything behind them
+        let mut haves = Vec::new();
+        for advertised in self.list_remote_refs(peer).await?.refs {
+            if self.database.get_commit(&advertised.target)?.is_some() {
+                haves.push(advertised.target);
+            }
+        }
+
+        let heads: Vec<String> = updates.iter().map(|update| update.new.clone()).collect();
+        let reachable = self.database.reachable_commits(&heads)?;
+
+        let mut commits = Vec::new();
+        let mut files = HashMap::new();
+        for commit in self.database.commits_between(&heads, &haves)? {
+            files.insert(commit.hash.clone(), self.database.get_commit_files(&commit.hash)?);
+            commits.push(crate::storage::CommitInfo {
+                hash: commit.hash,
+                message: commit.message,
+                timestamp: commit.timestamp,
+                parents: commit.parents,
+                is_merge: commit.is_merge,
+            });
+        }
+        let tags = self
+            .database
+            .list_tags()?
+            .into_iter()
+            .filter(|tag| reachable.contains(&tag.target))
+            .collect();
+
+        let request_id = self.swarm.behaviour_mut().push.send_request(
+            &peer,
+            PushRequest {
+                updates,
+                commits,
+                files,
+                tags,
+            },
+        );
+
+        let start_time = std::time::Instant::now();
+        while start_time.elapsed() < PUSH_TIMEOUT {
+            // Keep serving events: the peer fetches objects from us meanwhile
+            if let Err(e) = self.poll_events().await {
+                println!("Warning: Error during response polling: {}", e);
+            }
+
+            if let Some(response) = self.pending_push_responses.remove(&request_id) {
+                if let Some(error) = &response.error {
+                    return Err(anyhow::anyhow!("Push rejected by {}: {}", peer, error));
+                }
+
+                for result in response.results.iter().filter(|result| result.is_accepted()) {
+                    if let Some(branch) = result.ref_name.strip_prefix("refs/heads/") {
+                        self.database
+                            .update_remote_ref(remote, branch, &result.new, &format!("push: to {}", remote))?;
+                    }
+                }
+
+                return Ok(response);
+            }
+        }
+
+        Err(anyhow::anyhow!("Timed out waiting for push status from {}", peer))
+    }
+
+    /// Dial a known peer unless we are connected already
+    ///
+    /// # Returns
+    /// Whether we are connected to the peer; never true for banned peers
+    async fn ensure_connected(&mut self, peer: PeerId) -> Result<bool> {
+        if self.is_banned(&peer) {
+            println!("Not connecting to banned peer {}", peer);
+            return Ok(false);
+        }
+        if self.swarm.is_connected(&peer) {
+            return Ok(true);
+        }
+
+        if !self.dial_known_addresses(peer) {
+            return Ok(false);
+        }
+
+        for _ in 0..50 {
+            if self.swarm.is_connected(&peer) {
+                return Ok(true);
+            }
+            self.poll_events_for_connection().await?;
+        }
+
+        Ok(self.swarm.is_connected(&peer))
+    }
+
+    /// Begin receiving a push by fetching the objects we lack from the pusher
+    fn start_incoming_push(
         &mut self,
         peer: PeerId,
-        commits: Vec<crate::storage::CommitInfo>,
-    ) -> Result<()> {
-        // Simplified version - just return success without hanging
-        println!("DEBUG: send_commits called with {} commits", commits.len());
-        println!("DEBUG: Connected to peer {}, push completed", peer);
-        Ok(())
+        request_id: libp2p::request_response::InboundRequestId,
+        request: PushRequest,
+        channel: libp2p::request_response::ResponseChannel<PushResponse>,
+    ) {
+        // Refuse snapshots that could escape the working tree before fetching anything
+        let bad_path = request
+            .files
+            .values()
+            .flatten()
+            .find_map(|(path, _, _)| crate::database::check_file_path(path).err());
+        if let Some(e) = bad_path {
+            println!("Rejected push from {}: {}", peer, e);
+            let response = PushResponse {
+                results: Vec::new(),
+                error: Some(e.to_string()),
+            };
+            if self.swarm.behaviour_mut().push.send_response(channel, response).is_err() {
+                eprintln!("Failed to send push status to {}", peer);
+            }
+            return;
+        }
+
+        let mut missing = std::collections::BTreeSet::new();
+        for commit in &request.commits {
+            if matches!(self.database.get_commit(&commit.hash), Ok(Some(_))) {
+                continue;
+            }
+            for (_, hash, _) in request.files.get(&commit.hash).into_iter().flatten() {
+                if !self.storage.exists(hash) {
+                    missing.insert(hash.clone());
+                } else {
+                    missing.extend(self.storage.missing_chunks(hash).unwrap_or_default());
+                }
+            }
+        }
+
+        for hash in &missing {
+            self.request_push_object(peer, request_id, hash);
+        }
+
+        self.incoming_pushes.insert(
+            request_id,
+            IncomingPush {
+                peer,
+                channel,
+                request,
+                outstanding: missing.len(),
+                failed: Vec::new(),
+            },
+        );
+        if missing.is_empty() {
+            self.finish_incoming_push(request_id);
+        }
+    }
+
+    /// Ask the pusher for a stored object (a file, manifest or chunk) as-is
+    fn request_push_object(&mut self, peer: PeerId, push_id: libp2p::request_response::InboundRequestId, hash: &str) {
+        let object_request = self
+            .swarm
+            .behaviour_mut()
+            .request_response
+            .send_request(&peer, ChunkRequest { hash: hash.to_string(), raw: true });
+        self.push_object_requests.insert(object_request, (push_id, hash.to_string()));
+    }
+
+    /// Verify and store an object fetched for an incoming push
+    ///
+    /// # Arguments
+    /// * `push_id` - The push the object belongs to
+    /// * `hash` - Hash the pusher advertised for the object
+    /// * `data` - Object content, None if the pusher did not send it
+    fn receive_push_object(
+        &mut self,
+        push_id: libp2p::request_response::InboundRequestId,
+        hash: &str,
+        data: Option<Vec<u8>>,
+    ) {
+        let pusher = self.incoming_pushes.get(&push_id).map(|push| push.peer);
+        let verified = match data {
+            Some(data) => match matches_hash(hash, &data, true) {
+                Ok(true) => match self.storage.store_object(hash, &data) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        println!("Warning: Failed to store object {}: {}", hash, e);
+                        false
+                    }
+                },
+                Ok(false) => {
+                    if let Some(pusher) = pusher {
+                        self.report_bad_object(pusher, hash);
+                    }
+                    false
+                }
+                Err(e) => {
+                    println!("Warning: Failed to verify object {}: {}", hash, e);
+                    false
+                }
+            },
+            None => false,
+        };
+
+        // A manifest's chunks are fetched as part of the same push
+        let mut chunks = Vec::new();
+        if let (Some(pusher), true) = (pusher, verified) {
+            chunks = self.storage.missing_chunks(hash).unwrap_or_default();
+            for chunk in &chunks {
+                self.request_push_object(pusher, push_id, chunk);
+            }
+        }
+
+        let Some(push) = self.incoming_pushes.get_mut(&push_id) else {
+            return;
+        };
+        push.outstanding = push.outstanding.saturating_sub(1) + chunks.len();
+        if !verified {
+            push.failed.push(hash.to_string());
+        }
+        if push.outstanding == 0 {
+            self.finish_incoming_push(push_id);
+        }
+    }
+
+    /// Apply an incoming push once all its objects are present and answer the pusher
+    fn finish_incoming_push(&mut self, push_id: libp2p::request_response::InboundRequestId) {
+        let Some(push) = self.incoming_pushes.remove(&push_id) else {
+            return;
+        };
+
+        let result = if push.failed.is_empty() {
+            self.apply_push(&push.peer, &push.request)
+        } else {
+            Err(anyhow::anyhow!(
+                "Missing or corrupt objects: {}",
+                push.failed.join(", ")
+            ))
+        };
+        let response = match result {
+            Ok(results) => {
+                for result in &results {
+                    println!("Push from {}: {} {:?}", push.peer, result.ref_name, result.status);
+                }
+                // Announce the pushed commits and files we now host
+                if results.iter().any(RefResult::is_accepted) {
+                    for commit in &push.request.commits {
+                        let files = push.request.files.get(&commit.hash).into_iter().flatten();
+                        let announced = std::iter::once(Content::Commit(&commit.hash))
+                            .chain(files.map(|(_, hash, _)| Content::Object(hash)))
+                            .try_for_each(|content| self.provide(content));
+                        if let Err(e) = announced {
+                            println!("Failed to announce pushed content: {}", e);
+                        }
+                    }
+                }
+                PushResponse { results, error: None }
+            }
+            Err(e) => {
+                println!("Rejected push from {}: {}", push.peer, e);
+                PushResponse {
+                    results: Vec::new(),
+                    error: Some(e.to_string()),
+                }
+            }
+        };
+
+        if self
+            .swarm
+            .behaviour_mut()
+            .push
+            .send_response(push.channel, response)
+            .is_err()
+        {
+            eprintln!("Failed to send push status to {}", push.peer);
+        }
+    }
+
+    /// Store pushed commits and tags and move branches in one transaction
+    ///
+    /// Branches only move forward unless the update is forced and
+    /// `receive.allow_forced_push` is set; the checked out branch and
+    /// existing tags are never moved.
+    fn apply_push(&self, peer: &PeerId, request: &PushRequest) -> Result<Vec<RefResult>> {
+        use crate::database::EMPTY_COMMIT_HASH;
+
+        let allow_forced = self.receive.allow_forced_push;
+        self.database.transaction(|db| {
+            // Moving the checked out branch would leave the working tree and
+            // staging area describing a different commit
+            let current = db.get_current_branch().ok();
+
+            for commit in &request.commits {
+                if db.get_commit(&commit.hash)?.is_some() {
+                    continue;
+                }
+                let files = request.files.get(&commit.hash).cloned().unwrap_or_default();
+                db.create_commit(&commit.hash, &commit.message, &commit.parents, &files, commit.is_merge)?;
+            }
+
+            let operation = format!("push: from {}", peer);
+            let mut results = Vec::new();
+
+            for update in &request.updates {
+                let old = db.get_branch_head(&update.branch)?;
+                let exists = old.is_some();
+                let old = old.filter(|hash| hash != EMPTY_COMMIT_HASH);
+
+                let status = if crate::database::check_ref_name("branch", &update.branch).is_err() {
+                    RefStatus::Rejected("invalid branch name".to_string())
+                } else if current.as_deref() == Some(update.branch.as_str()) {
+                    RefStatus::Rejected("branch is checked out".to_string())
+                } else if db.get_commit(&update.new)?.is_none() {
+                    RefStatus::Rejected("unknown commit".to_string())
+                } else {
+                    match &old {
+                        None => RefStatus::Created,
+                        Some(old) if *old == update.new => RefStatus::UpToDate,
+                        Some(old) if db.is_ancestor(old, &update.new)? => RefStatus::FastForward,
+                        Some(_) if update.force && allow_forced => RefStatus::Forced,
+                        Some(_) if update.force => RefStatus::Rejected("forced updates are not allowed".to_string()),
+                        Some(_) => RefStatus::Rejected("non-fast-forward".to_string()),
+                    }
+                };
+
+                if matches!(status, RefStatus::Created | RefStatus::FastForward | RefStatus::Forced) {
+                    if !exists {
+                        db.create_branch(&update.branch, EMPTY_COMMIT_HASH)?;
+                    }
+                    db.update_branch_head_with_reason(&update.branch, &update.new, &operation)?;
+                }
+
+                results.push(RefResult {
+                    ref_name: format!("refs/heads/{}", update.branch),
+                    old,
+                    new: update.new.clone(),
+                    status,
+                });
+            }
+
+            for tag in &request.tags {
+                let old = db.get_tag(&tag.name)?.map(|existing| existing.target);
+                let status = match &old {
+                    Some(target) if *target == tag.target => RefStatus::UpToDate,
+                    Some(_) => RefStatus::Rejected("already exists".to_string()),
+                    None => {
+                        db.save_tag(tag)?;
+                        RefStatus::Created
+                    }
+                };
+                results.push(RefResult {
+                    ref_name: format!("refs/tags/{}", tag.name),
+                    old,
+                    new: tag.target.clone(),
+                    status,
+                });
+            }
+
+            Ok(results)
+        })
+    }
+}
+
+/// Whether an address is a QUIC address
+fn is_quic(addr: &Multiaddr) -> bool {
+    addr.iter().any(|protocol| matches!(protocol, Protocol::QuicV1))
+}
+
+/// Check received data against the hash it was requested by
+///
+/// Raw objects hash directly; whole files hash the way
+/// [`StorageManager::store`] would store them.
+fn matches_hash(hash: &str, data: &[u8], raw: bool) -> Result<bool> {
+    let actual = if raw {
+        blake3::hash(data).to_hex().to_string()
+    } else {
+        StorageManager::content_hash(data)?
+    };
+    Ok(actual == hash)
+}
+
+/// An address without its trailing `/p2p/<peer id>`, as stored in the address book
+fn without_peer_id(addr: &Multiaddr) -> Multiaddr {
+    let mut addr = addr.clone();
+    if matches!(addr.iter().last(), Some(Protocol::P2p(_))) {
+        addr.pop();
+    }
+    addr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    async fn start_node() -> (NetworkManager, Multiaddr, TempDir) {
+        start_configured_node(false, NetworkConfig::default()).await
+    }
+
+    async fn start_configured_node(relay_server: bool, network: NetworkConfig) -> (NetworkManager, Multiaddr, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let fai_path = temp_dir.path().join(".fai");
+        std::fs::create_dir_all(&fai_path).unwrap();
+        let storage = Arc::new(StorageManager::new(fai_path.clone()).unwrap());
+        let database = crate::database::DatabaseManager::new(&fai_path.join("db.sqlite")).unwrap();
+        let key = Keypair::generate_ed25519();
+        let mut node = if relay_server {
+            NetworkManager::new_relay(storage, database, key).unwrap()
+        } else {
+            NetworkManager::new(storage, database, key).unwrap()
+        };
+        node.set_network_config(network);
+        node.start().await.unwrap();
+
+        // Nodes that only listen through a relay have no TCP port
+        let port = node
+            .listeners()
+            .iter()
+            .flat_map(|addr| addr.iter())
+            .find_map(|protocol| match protocol {
+                Protocol::Tcp(port) => Some(port),
+                _ => None,
+            });
+        let addr = match port {
+            Some(port) => format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap(),
+            None => node.listeners()[0].clone(),
+        };
+        (node, addr, temp_dir)
+    }
+
+    #[tokio::test]
+    async fn test_dht_finds_providers_across_nodes() {
+        let (mut a, a_addr, _a_dir) = start_node().await;
+        let (mut b, b_addr, _b_dir) = start_node().await;
+        let (mut c, _, _c_dir) = start_node().await;
+        let (a_id, b_id) = (a.local_peer_id(), b.local_peer_id());
+
+        let hash = a.storage.store(b"model weights").unwrap();
+        a.provide(Content::Object(&hash)).unwrap();
+
+        // A and B know each other; C only knows B
+        b.add_peer_manually(a_id, a_addr).unwrap();
+        c.add_peer_manually(b_id, b_addr).unwrap();
+        for mut node in [a, b] {
+            tokio::spawn(async move {
+                loop {
+                    let _ = node.poll_events().await;
+                }
+            });
+        }
+
+        // C finds A through B's routing table once identify has run
+        let mut providers = Vec::new();
+        for _ in 0..20 {
+            providers = c.find_providers(Content::Object(&hash)).await.unwrap();
+            if providers.contains(&a_id) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+        }
+        assert_eq!(providers, vec![a_id]);
+        assert!(c.find_providers(Content::Commit(&hash)).await.unwrap().is_empty());
+
+        // B doesn't have the file, so the fetch falls back to the provider
+        assert_eq!(c.fetch_file(&[b_id], &hash).await.unwrap(), 1);
+        assert_eq!(c.storage.retrieve(&hash).unwrap(), b"model weights");
+    }
+
+    #[tokio::test]
+    async fn test_connect_through_relay() {
+        let (relay, relay_addr, _relay_dir) = start_configured_node(true, NetworkConfig::default()).await;
+        let relay_addr = relay_addr.with(Protocol::P2p(relay.local_peer_id()));
+        let mut relay = relay;
+        tokio::spawn(async move {
+            loop {
+                let _ = relay.poll_events().await;
+            }
+        });
+
+        // A is only reachable through the relay; start() returns once the
+        // reservation is accepted
+        let network = NetworkConfig {
+            listen: Vec::new(),
+            relays: vec![relay_addr.to_string()],
+            ..Default::default()
+        };
+        let (mut a, circuit, _a_dir) = start_configured_node(false, network).await;
+        let a_id = a.local_peer_id();
+        assert!(circuit.iter().any(|protocol| protocol == Protocol::P2pCircuit));
+        a.storage.store(b"model weights").unwrap();
+        tokio::spawn(async move {
+            loop {
+                let _ = a.poll_events().await;
+            }
+        });
+
+        // B only knows A's relayed address
+        let (mut b, _, _b_dir) = start_node().await;
+        b.add_peer_manually(a_id, circuit).unwrap();
+        let hash = blake3::hash(b"model weights").to_hex().to_string();
+        assert_eq!(b.fetch_file(&[a_id], &hash).await.unwrap(), 1);
+        assert_eq!(b.storage.retrieve(&hash).unwrap(), b"model weights");
+    }
+
+    #[tokio::test]
+    async fn test_upload_limit() {
+        let (mut a, a_addr, _a_dir) = start_node().await;
+        let a_id = a.local_peer_id();
+        a.set_transfer_config(TransferConfig {
+            upload_limit: Some(512 * 1024),
+            max_inbound_requests: 1,
+            ..Default::default()
+        });
+        // Three chunks of 1 MiB, 1 MiB and 512 KiB
+        let data: Vec<u8> = (0..5 * 512 * 1024).map(|i| (i % 251) as u8).collect();
+        let hash = a.storage.store(&data).unwrap();
+        tokio::spawn(async move {
+            loop {
+                let _ = a.poll_events().await;
+            }
+        });
+
+        // The first chunk uses up the burst; the others wait for the bucket to refill
+        let (mut b, _, _b_dir) = start_node().await;
+        b.add_peer_manually(a_id, a_addr).unwrap();
+        let started = std::time::Instant::now();
+        assert_eq!(b.fetch_file(&[a_id], &hash).await.unwrap(), 4, "manifest and three chunks");
+        assert!(started.elapsed() >= std::time::Duration::from_secs(2), "took {:?}", started.elapsed());
+        assert_eq!(b.storage.retrieve(&hash).unwrap(), data);
+
+        // The whole file in one response would bypass the limit
+        assert_eq!(b.request_chunk(a_id, &hash).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_listen_and_external_addresses() {
+        let network = NetworkConfig {
+            listen: vec!["/ip4/127.0.0.1/tcp/0".to_string(), "/ip6/::1/tcp/0".to_string()],
+            external: vec!["/dns4/models.example.com/tcp/4001".to_string()],
+            relays: Vec::new(),
+        };
+        let (mut a, a_addr, _a_dir) = start_configured_node(false, network).await;
+        let a_id = a.local_peer_id();
+        assert!(a.listeners().iter().any(|addr| addr.to_string().starts_with("/ip6/::1/tcp/")));
+        let external: Multiaddr = "/dns4/models.example.com/tcp/4001".parse().unwrap();
+        assert_eq!(a.external_addresses(), vec![external.clone()]);
+        tokio::spawn(async move {
+            loop {
+                let _ = a.poll_events().await;
+            }
+        });
+
+        // B learns the external address from identify once connected
+        let (mut b, _, _b_dir) = start_node().await;
+        b.add_peer_manually(a_id, a_addr).unwrap();
+        for _ in 0..50 {
+            if b.list_peers().iter().any(|peer| peer.peer_id == a_id && peer.addresses.contains(&external)) {
+                break;
+            }
+            b.poll_events().await.unwrap();
+        }
+        let addresses = b.known_addresses(a_id);
+        assert!(addresses.contains(&external));
+        assert!(addresses.iter().any(|addr| addr.to_string().starts_with("/ip6/::1/tcp/")));
+    }
+
+    #[tokio::test]
+    async fn test_push_rules() {
+        let (mut a, a_addr, _a_dir) = start_node().await;
+        let a_id = a.local_peer_id();
+        let (mut b, _, _b_dir) = start_node().await;
+
+        // Both sides share c1; B has a fast-forward c2 with a multi-chunk
+        // file and a diverging c3
+        let large: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let v1 = b.storage.store(b"weights v1").unwrap();
+        let v2 = b.storage.store(&large).unwrap();
+        let model = |hash: &str| vec![("model.bin".to_string(), hash.to_string(), 10)];
+        for node in [&a, &b] {
+            node.database.create_commit("c1", "First", &[], &model(&v1), false).unwrap();
+            node.database.update_branch_head("main", "c1").unwrap();
+            node.database.create_branch("dev", "c1").unwrap();
+        }
+        a.storage.store(b"weights v1").unwrap();
+        b.database.create_commit("c2", "Second", &["c1".to_string()], &model(&v2), false).unwrap();
+        b.database.create_commit("c3", "Other", &["c1".to_string()], &model(&v1), false).unwrap();
+        let escape = vec![("../evil".to_string(), v1.clone(), 10)];
+        b.database.create_commit("c4", "Escape", &["c2".to_string()], &escape, false).unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let _ = a.poll_events().await;
+            }
+        });
+        b.add_peer_manually(a_id, a_addr.clone()).unwrap();
+
+        let update = |branch: &str, new: &str, force: bool| RefUpdate {
+            branch: branch.to_string(),
+            new: new.to_string(),
+            force,
+        };
+        let response = b
+            .push(a_id, "origin", vec![update("main", "c2", false), update("dev", "c2", false)])
+            .await
+            .unwrap();
+        assert_eq!(response.results[0].status, RefStatus::Rejected("branch is checked out".to_string()));
+        assert_eq!(response.results[1].status, RefStatus::FastForward);
+
+        // A fetched the manifest and every chunk of the pushed file
+        let (mut c, _, _c_dir) = start_node().await;
+        c.add_peer_manually(a_id, a_addr).unwrap();
+        assert_eq!(c.fetch_file(&[a_id], &v2).await.unwrap(), 4);
+        assert_eq!(c.storage.retrieve(&v2).unwrap(), large);
+
+        let response = b
+            .push(a_id, "origin", vec![update("dev", "c3", true), update("-f", "c2", false)])
+            .await
+            .unwrap();
+        assert_eq!(
+            response.results[0].status,
+            RefStatus::Rejected("forced updates are not allowed".to_string())
+        );
+        assert_eq!(response.results[1].status, RefStatus::Rejected("invalid branch name".to_string()));
+
+        let error = b.push(a_id, "origin", vec![update("dev", "c4", false)]).await.unwrap_err();
+        assert!(error.to_string().contains("Unsafe file path"), "{}", error);
+        assert_eq!(b.list_remote_refs(a_id).await.unwrap().branch("dev"), Some("c2"));
+    }
+
+    fn advertised(name: &str, target: &str) -> AdvertisedRef {
+        AdvertisedRef {
+            name: name.to_string(),
+            target: target.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_refs_response() {
+        let mut response = RefsResponse {
+            head: Some("dev".to_string()),
+            refs: vec![
+                advertised("refs/heads/main", "c1"),
+                advertised("refs/heads/dev", "c2"),
+                advertised("refs/tags/v1", "c1"),
+            ],
+        };
+        assert_eq!(response.branch("main"), Some("c1"));
+        assert_eq!(response.branch("v1"), None);
+        assert_eq!(response.head_target(), Some("c2"));
+        assert_eq!(response.default_branch(), Some(("dev", "c2")));
+
+        // A checked out branch without commits falls back to the first branch
+        response.head = Some("empty".to_string());
+        assert_eq!(response.head_target(), None);
+        assert_eq!(response.default_branch(), Some(("main", "c1")));
+
+        response.refs.retain(|advertised| advertised.name.starts_with("refs/tags/"));
+        assert_eq!(response.default_branch(), None);
     }
 }
@@ -0,0 +1,146 @@
+//! Named remotes for FAI Protocol
+//!
+//! A remote is a peer ID plus the addresses to dial it at, stored under
+//! `[remotes.<name>]` in `.fai/config.toml`. Commands that talk to a peer
+//! accept a remote name, a bare peer ID (found through discovery) or a
+//! multiaddr ending in `/p2p/<peer id>`.
+
+use anyhow::Result;
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+
+use crate::config::{RemoteConfig, RepoConfig};
+
+/// Default name of the remote a repository was cloned from
+pub const DEFAULT_REMOTE: &str = "origin";
+
+/// A peer resolved from a remote name, peer ID or multiaddr
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    /// Name the peer's branches are tracked under (`refs/remotes/<name>/...`)
+    pub name: String,
+    /// Peer ID of the remote
+    pub peer_id: PeerId,
+    /// Addresses to dial; empty if the peer has to be discovered
+    pub addresses: Vec<Multiaddr>,
+}
+
+impl Remote {
+    /// Build a remote from its configuration entry
+    ///
+    /// # Arguments
+    /// * `name` - Remote name (must not be empty or contain '/')
+    /// * `config` - Peer ID and addresses of the remote
+    pub fn from_config(name: &str, config: &RemoteConfig) -> Result<Self> {
+        if name.is_empty() || name.contains('/') || name.chars().any(char::is_whitespace) {
+            return Err(anyhow::anyhow!("Invalid remote name '{}'", name));
+        }
+        let peer_id = config
+            .peer_id
+            .parse::<PeerId>()
+            .map_err(|_| anyhow::anyhow!("Invalid peer ID for remote '{}': {}", name, config.peer_id))?;
+        let addresses = config
+            .addresses
+            .iter()
+            .map(|addr| {
+                addr.parse::<Multiaddr>()
+                    .map_err(|e| anyhow::anyhow!("Invalid address for remote '{}': {} ({})", name, addr, e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            name: name.to_string(),
+            peer_id,
+            addresses,
+        })
+    }
+
+    /// Resolve a remote name, peer ID or `/.../p2p/<peer id>` multiaddr
+    ///
+    /// A peer ID that belongs to a configured remote resolves to that remote,
+    /// so its branches are tracked under the remote's name either way.
+    /// Otherwise the peer ID itself is used as the name.
+    pub fn resolve(config: &RepoConfig, spec: &str) -> Result<Self> {
+        if let Some(remote_config) = config.remotes.get(spec) {
+            return Self::from_config(spec, remote_config);
+        }
+
+        let (peer_id, addresses) = if spec.starts_with('/') {
+            let addr = spec
+                .parse::<Multiaddr>()
+                .map_err(|e| anyhow::anyhow!("Invalid address '{}': {}", spec, e))?;
+            match addr.iter().last() {
+                Some(Protocol::P2p(peer_id)) => (peer_id, vec![addr]),
+                _ => return Err(anyhow::anyhow!("Address '{}' does not end in /p2p/<peer id>", spec)),
+            }
+        } else {
+            let peer_id = spec
+                .parse::<PeerId>()
+                .map_err(|_| anyhow::anyhow!("'{}' is not a configured remote or a peer ID", spec))?;
+            (peer_id, Vec::new())
+        };
+
+        for (name, remote_config) in &config.remotes {
+            if remote_config.peer_id == peer_id.to_string() {
+                let mut remote = Self::from_config(name, remote_config)?;
+                for addr in addresses {
+                    if !remote.addresses.contains(&addr) {
+                        remote.addresses.push(addr);
+                    }
+                }
+                return Ok(remote);
+            }
+        }
+
+        Ok(Self {
+            name: peer_id.to_string(),
+            peer_id,
+            addresses,
+        })
+    }
+
+    /// Configuration entry for this remote
+    pub fn to_config(&self) -> RemoteConfig {
+        RemoteConfig {
+            peer_id: self.peer_id.to_string(),
+            addresses: self.addresses.iter().map(Multiaddr::to_string).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_remote() {
+        let peer_id = PeerId::random();
+        let mut config = RepoConfig::default();
+        config.remotes.insert(
+            "origin".to_string(),
+            RemoteConfig {
+                peer_id: peer_id.to_string(),
+                addresses: vec!["/ip4/10.0.0.2/tcp/4001".to_string()],
+            },
+        );
+
+        let remote = Remote::resolve(&config, "origin").unwrap();
+        assert_eq!(remote.peer_id, peer_id);
+        assert_eq!(remote.addresses.len(), 1);
+
+        // A known peer ID or address maps back to the configured name
+        assert_eq!(Remote::resolve(&config, &peer_id.to_string()).unwrap().name, "origin");
+        let remote = Remote::resolve(&config, &format!("/ip4/10.0.0.3/tcp/4001/p2p/{}", peer_id)).unwrap();
+        assert_eq!(remote.name, "origin");
+        assert_eq!(remote.addresses.len(), 2);
+
+        let other = PeerId::random();
+        let remote = Remote::resolve(&config, &other.to_string()).unwrap();
+        assert_eq!(remote.name, other.to_string());
+        assert!(remote.addresses.is_empty());
+
+        assert!(Remote::resolve(&config, "upstream").is_err());
+        assert!(Remote::resolve(&config, "/ip4/10.0.0.3/tcp/4001").is_err());
+        assert!(Remote::from_config("a/b", &remote.to_config()).is_err());
+    }
+}
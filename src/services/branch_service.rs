@@ -1,29 +1,44 @@
 //! Branch management service for FAI Protocol
 //!
 //! Provides Git-like branching functionality including:
-//! - Creating and deleting branches
+//! - Creating, deleting and renaming branches
+//! - Protecting configured branches from deletion and renaming
 //! - Switching between branches
 //! - Listing branches
 //! - Managing branch references
+//! - Tracking upstream branches of a remote
 
 use anyhow::Result;
 use std::path::Path;
 
+use crate::config::{BranchConfig, RepoConfig, Upstream};
+
 /// Branch management service
 pub struct BranchService {
     pub database: crate::database::DatabaseManager,
+    config: BranchConfig,
 }
 
 impl BranchService {
     /// Create a new branch service instance
     pub fn new(database: crate::database::DatabaseManager) -> Self {
-        Self { database }
+        Self {
+            database,
+            config: BranchConfig::default(),
+        }
+    }
+
+    /// Use the given branch settings (e.g. protection rules)
+    pub fn with_config(mut self, config: BranchConfig) -> Self {
+        self.config = config;
+        self
     }
 
     /// Initialize the branch service from repository path
     pub fn from_repo_path(repo_path: &Path) -> Result<Self> {
         let database = crate::database::DatabaseManager::new(&repo_path.join("db.sqlite"))?;
-        Ok(Self::new(database))
+        let config = RepoConfig::load(repo_path)?;
+        Ok(Self::new(database).with_config(config.branches))
     }
 
     /// Create a new branch
@@ -53,37 +68,197 @@ impl BranchService {
     ///
     /// # Arguments
     /// * `name` - Branch name to delete
+    /// * `force` - Delete even if the branch has commits not merged into the current branch
     ///
-    /// Note: Cannot delete the current branch
-    pub fn delete_branch(&self, name: &str) -> Result<()> {
+    /// Note: The current branch and protected branches can never be deleted.
+    /// Its upstream is dropped from the in-memory settings; persist them
+    /// with [`Self::config`].
+    pub fn delete_branch(&mut self, name: &str, force: bool) -> Result<()> {
+        self.check_not_protected(name, "delete")?;
+
+        let head = self
+            .database
+            .get_branch_head(name)?
+            .ok_or_else(|| anyhow::anyhow!("Branch '{}' does not exist", name))?;
+        if self.get_current_branch().ok().as_deref() == Some(name) {
+            return Err(anyhow::anyhow!("Cannot delete current branch '{}'", name));
+        }
+
+        if !force && head != crate::database::EMPTY_COMMIT_HASH {
+            let current_head = self
+                .database
+                .get_branch_head(&self.get_current_branch()?)?
+                .filter(|hash| hash != crate::database::EMPTY_COMMIT_HASH);
+            let merged = match current_head {
+                Some(current_head) => self.database.is_ancestor(&head, &current_head)?,
+                None => false,
+            };
+            if !merged {
+                return Err(anyhow::anyhow!(
+                    "Branch '{}' is not fully merged. Use --force to delete it anyway.",
+                    name
+                ));
+            }
+        }
+
         self.database.delete_branch(name)?;
+        self.config.upstream.remove(name);
+        Ok(())
+    }
+
+    /// Rename a branch
+    ///
+    /// # Arguments
+    /// * `old_name` - Existing branch name
+    /// * `new_name` - New branch name
+    ///
+    /// Note: Protected branches cannot be renamed, and a branch cannot be
+    /// renamed to a protected name. Its upstream moves to the new name in the
+    /// in-memory settings; persist them with [`Self::config`].
+    pub fn rename_branch(&mut self, old_name: &str, new_name: &str) -> Result<()> {
+        self.check_not_protected(old_name, "rename")?;
+        self.check_not_protected(new_name, "rename to")?;
+        self.database.rename_branch(old_name, new_name)?;
+        if let Some(upstream) = self.config.upstream.remove(old_name) {
+            self.config.upstream.insert(new_name.to_string(), upstream);
+        }
+        Ok(())
+    }
+
+    /// Fail if the branch matches a configured protection rule
+    fn check_not_protected(&self, name: &str, action: &str) -> Result<()> {
+        if self.config.is_protected(name) {
+            return Err(anyhow::anyhow!(
+                "Cannot {} protected branch '{}'",
+                action,
+                name
+            ));
+        }
         Ok(())
     }
 
     /// List all branches
     ///
     /// # Returns
-    /// Vector of branch information
+    /// Vector of branch information, with divergence relative to the current branch
     pub fn list_branches(&self) -> Result<Vec<BranchInfo>> {
         let branches = self.database.list_branches()?;
         let current_branch = self.get_current_branch().unwrap_or_else(|_| "detached".to_string());
+        let current_head = branches
+            .iter()
+            .find(|(name, head)| *name == current_branch && head != crate::database::EMPTY_COMMIT_HASH)
+            .map(|(_, head)| head.clone());
 
         let mut branch_infos = Vec::new();
         for (name, head_commit) in branches {
             let is_current = name == current_branch;
-            let is_empty = head_commit == "0000000000000000000000000000000000000000";
+            let is_empty = head_commit == crate::database::EMPTY_COMMIT_HASH;
 
+            let divergence = match &current_head {
+                Some(current_head) if !is_current && !is_empty => {
+                    let (ahead, behind) = self.database.ahead_behind(&head_commit, current_head)?;
+                    let merge_base = self.database.find_merge_base(&head_commit, current_head)?;
+                    Some(BranchDivergence { ahead, behind, merge_base })
+                }
+                _ => None,
+            };
+
+            let tracking = self.tracking_status(&name)?;
             branch_infos.push(BranchInfo {
                 name,
                 head_commit,
                 is_current,
                 is_empty,
+                divergence,
+                tracking,
             });
         }
 
         Ok(branch_infos)
     }
 
+    /// Branch settings, including upstream configuration
+    pub fn config(&self) -> &BranchConfig {
+        &self.config
+    }
+
+    /// Get the upstream configured for a branch
+    pub fn upstream(&self, branch: &str) -> Option<&Upstream> {
+        self.config.upstream.get(branch)
+    }
+
+    /// Set the upstream of a branch
+    ///
+    /// Only changes the in-memory settings; persist them with [`Self::config`].
+    ///
+    /// # Arguments
+    /// * `branch` - Local branch name
+    /// * `upstream` - Remote branch to track (its remote-tracking ref must exist)
+    pub fn set_upstream(&mut self, branch: &str, upstream: Upstream) -> Result<()> {
+        if !self.database.branch_exists(branch)? {
+            return Err(anyhow::anyhow!("Branch '{}' does not exist", branch));
+        }
+        if self
+            .database
+            .get_remote_ref(&upstream.remote, &upstream.branch)?
+            .is_none()
+        {
+            return Err(anyhow::anyhow!(
+                "Remote-tracking ref '{}' does not exist. Pull from the remote first.",
+                upstream
+            ));
+        }
+
+        self.config.upstream.insert(branch.to_string(), upstream);
+        Ok(())
+    }
+
+    /// Remove the upstream of a branch
+    ///
+    /// # Returns
+    /// The upstream that was configured
+    pub fn unset_upstream(&mut self, branch: &str) -> Result<Upstream> {
+        self.config
+            .upstream
+            .remove(branch)
+            .ok_or_else(|| anyhow::anyhow!("Branch '{}' has no upstream configured", branch))
+    }
+
+    /// Compare a branch with its upstream
+    ///
+    /// # Returns
+    /// None if no upstream is configured
+    pub fn tracking_status(&self, branch: &str) -> Result<Option<TrackingStatus>> {
+        let upstream = match self.upstream(branch) {
+            Some(upstream) => upstream.clone(),
+            None => return Ok(None),
+        };
+
+        let upstream_head = self
+            .database
+            .get_remote_ref(&upstream.remote, &upstream.branch)?;
+        let local_head = self
+            .database
+            .get_branch_head(branch)?
+            .filter(|hash| hash != crate::database::EMPTY_COMMIT_HASH);
+
+        let divergence = match (upstream_head, local_head) {
+            (Some(upstream_head), Some(local_head)) => {
+                let (ahead, behind) = self.database.ahead_behind(&local_head, &upstream_head)?;
+                let merge_base = self.database.find_merge_base(&local_head, &upstream_head)?;
+                Some(BranchDivergence { ahead, behind, merge_base })
+            }
+            (Some(upstream_head), None) => Some(BranchDivergence {
+                ahead: 0,
+                behind: self.database.reachable_commits(&[upstream_head])?.len(),
+                merge_base: None,
+            }),
+            (None, _) => None,
+        };
+
+        Ok(Some(TrackingStatus { upstream, divergence }))
+    }
+
     /// Switch to a branch
     ///
     /// # Arguments
@@ -141,6 +316,43 @@ pub struct BranchInfo {
     pub is_current: bool,
     /// Whether the branch has no commits
     pub is_empty: bool,
+    /// Divergence from the current branch (None for the current or an empty branch)
+    pub divergence: Option<BranchDivergence>,
+    /// Comparison with the upstream branch (None if no upstream is configured)
+    pub tracking: Option<TrackingStatus>,
+}
+
+/// How far a branch has diverged from another branch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BranchDivergence {
+    /// Commits on this branch that the other branch does not have
+    pub ahead: usize,
+    /// Commits on the other branch that this branch does not have
+    pub behind: usize,
+    /// Nearest common ancestor of both branches
+    pub merge_base: Option<String>,
+}
+
+/// How a branch compares to its upstream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackingStatus {
+    /// Configured upstream
+    pub upstream: Upstream,
+    /// Divergence from the upstream (None if its remote-tracking ref is gone)
+    pub divergence: Option<BranchDivergence>,
+}
+
+impl TrackingStatus {
+    /// Get a short summary (e.g. "ahead 2, behind 1", "up to date" or "gone")
+    pub fn summary(&self) -> String {
+        match &self.divergence {
+            None => "gone".to_string(),
+            Some(d) if d.ahead > 0 && d.behind > 0 => format!("ahead {}, behind {}", d.ahead, d.behind),
+            Some(d) if d.ahead > 0 => format!("ahead {}", d.ahead),
+            Some(d) if d.behind > 0 => format!("behind {}", d.behind),
+            Some(_) => "up to date".to_string(),
+        }
+    }
 }
 
 impl BranchInfo {
@@ -162,6 +374,24 @@ impl BranchInfo {
     pub fn status_text(&self) -> &'static str {
         if self.is_empty { "(no commits)" } else { "" }
     }
+
+    /// Get ahead/behind text relative to the current branch (e.g. "[ahead 2, behind 1]")
+    pub fn divergence_text(&self) -> String {
+        match &self.divergence {
+            Some(d) if d.ahead > 0 && d.behind > 0 => format!("[ahead {}, behind {}]", d.ahead, d.behind),
+            Some(d) if d.ahead > 0 => format!("[ahead {}]", d.ahead),
+            Some(d) if d.behind > 0 => format!("[behind {}]", d.behind),
+            _ => String::new(),
+        }
+    }
+
+    /// Get upstream text (e.g. "[origin/main: ahead 2, behind 1]")
+    pub fn tracking_text(&self) -> String {
+        match &self.tracking {
+            Some(tracking) => format!("[{}: {}]", tracking.upstream, tracking.summary()),
+            None => String::new(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -185,5 +415,107 @@ mod tests {
         // This test would need a commit to be created first
         // For now, we'll just test the branch existence check
         assert!(!service.database.branch_exists("test").unwrap());
+
+        service.database.create_commit("c1", "First", &[], &[], false).unwrap();
+        service.create_branch("test", Some("c1")).unwrap();
+        assert!(service.create_branch("test", Some("c1")).is_err());
+        assert!(service.database.create_branch("test", "c2").is_err());
+        assert_eq!(service.database.get_branch_head("test").unwrap().as_deref(), Some("c1"));
+        for name in ["a b", "HEAD", "../x", "-f"] {
+            assert!(service.create_branch(name, Some("c1")).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_delete_branch_safety() {
+        let (mut service, _temp_dir) = create_test_branch_service();
+        let db = &service.database;
+
+        db.create_commit("c1", "First", &[], &[], false).unwrap();
+        db.create_commit("c2", "Experiment", &["c1".to_string()], &[], false).unwrap();
+        db.update_branch_head("main", "c1").unwrap();
+        db.create_branch("merged", "c1").unwrap();
+        db.create_branch("unmerged", "c2").unwrap();
+
+        assert!(service.delete_branch("main", true).is_err()); // current branch
+        assert!(service.delete_branch("unmerged", false).is_err());
+        service.delete_branch("merged", false).unwrap();
+        service.delete_branch("unmerged", true).unwrap();
+        assert!(service.delete_branch("missing", true).is_err());
+    }
+
+    #[test]
+    fn test_rename_and_protected_branches() {
+        let (service, _temp_dir) = create_test_branch_service();
+        let mut service = service.with_config(BranchConfig {
+            protected: vec!["release/*".to_string()],
+            ..Default::default()
+        });
+        let db = &service.database;
+
+        db.create_commit("c1", "First", &[], &[], false).unwrap();
+        db.update_branch_head("main", "c1").unwrap();
+        db.create_branch("release/1.0", "c1").unwrap();
+
+        // Renaming the current branch moves HEAD along
+        service.rename_branch("main", "trunk").unwrap();
+        let db = &service.database;
+        assert_eq!(service.get_current_branch().unwrap(), "trunk");
+        assert_eq!(db.get_branch_head("trunk").unwrap().as_deref(), Some("c1"));
+        assert!(!db.branch_exists("main").unwrap());
+        assert_eq!(db.reflog(Some("trunk"), None).unwrap().len(), 2);
+
+        for invalid in ["", "HEAD", "-f", "a..b", "has space", "back~1"] {
+            assert!(service.rename_branch("trunk", invalid).is_err(), "renamed to '{}'", invalid);
+        }
+        assert_eq!(service.get_current_branch().unwrap(), "trunk");
+
+        assert!(service.rename_branch("release/1.0", "old").is_err());
+        assert!(service.rename_branch("trunk", "release/2.0").is_err());
+        assert!(service.delete_branch("release/1.0", true).is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_upstream_tracking_status() {
+        let (mut service, _temp_dir) = create_test_branch_service();
+        let db = &service.database;
+
+        db.create_commit("c1", "First", &[], &[], false).unwrap();
+        db.create_commit("c2", "Local", &["c1".to_string()], &[], false).unwrap();
+        db.create_commit("c3", "Remote", &["c1".to_string()], &[], false).unwrap();
+        db.create_commit("c4", "Remote again", &["c3".to_string()], &[], false).unwrap();
+        db.update_branch_head("main", "c2").unwrap();
+        db.update_remote_ref("origin", "main", "c4", "pull").unwrap();
+
+        let upstream = Upstream::parse("origin/main").unwrap();
+        assert!(service.set_upstream("missing", upstream.clone()).is_err());
+        assert!(service.set_upstream("main", Upstream::parse("origin/dev").unwrap()).is_err());
+        assert_eq!(service.tracking_status("main").unwrap(), None);
+
+        service.set_upstream("main", upstream.clone()).unwrap();
+        let status = service.tracking_status("main").unwrap().unwrap();
+        assert_eq!(status.summary(), "ahead 1, behind 2");
+        assert_eq!(status.divergence.unwrap().merge_base.as_deref(), Some("c1"));
+        assert_eq!(
+            service.list_branches().unwrap()[0].tracking_text(),
+            "[origin/main: ahead 1, behind 2]"
+        );
+
+        service.database.update_branch_head("main", "c4").unwrap();
+        assert_eq!(service.tracking_status("main").unwrap().unwrap().summary(), "up to date");
+
+        assert_eq!(service.unset_upstream("main").unwrap(), upstream);
+        assert!(service.unset_upstream("main").is_err());
+
+        // The upstream follows a rename and goes away with the branch
+        service.set_upstream("main", upstream.clone()).unwrap();
+        service.rename_branch("main", "trunk").unwrap();
+        assert_eq!(service.upstream("main"), None);
+        assert_eq!(service.upstream("trunk"), Some(&upstream));
+
+        service.database.create_branch("old", "c1").unwrap();
+        service.set_upstream("old", upstream.clone()).unwrap();
+        service.delete_branch("old", true).unwrap();
+        assert_eq!(service.upstream("old"), None);
+    }
+}
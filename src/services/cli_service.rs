@@ -5,6 +5,12 @@
 use anyhow::Result;
 use std::path::Path;
 use super::branch_service::BranchService;
+use crate::config::{RemoteConfig, RepoConfig, Upstream};
+use crate::network::remote::Remote;
+use super::gc_service::GcService;
+use super::history_service::{HistoryService, ResetMode};
+use super::merge_service::{ConflictResolution, MergeOutcome, MergeService};
+use super::stash_service::StashService;
 
 /// CLI service for handling user commands
 pub struct CliService {
@@ -46,21 +52,69 @@ impl CliService {
         Ok(())
     }
 
+    /// Print the peer ID of this repository, or change how its identity is stored
+    ///
+    /// # Arguments
+    /// * `encrypt` - Encrypt the identity with the passphrase from `FAI_IDENTITY_PASSPHRASE`
+    /// * `decrypt` - Store the identity unencrypted again
+    pub fn handle_id_command(&self, encrypt: bool, decrypt: bool) -> Result<()> {
+        use crate::network::identity;
+
+        self.check_repo_initialized()?;
+
+        let fai_path = self.repo_path.join(".fai");
+        let passphrase = identity::passphrase_from_env();
+
+        let encrypted = identity::identity_path(&fai_path).exists() && identity::is_encrypted(&fai_path)?;
+
+        if encrypt {
+            let passphrase = passphrase.ok_or_else(|| {
+                anyhow::anyhow!("Set {} to the passphrase to encrypt the identity with", identity::PASSPHRASE_ENV)
+            })?;
+            if encrypted {
+                return Err(anyhow::anyhow!("Identity is already encrypted"));
+            }
+            let keypair = identity::load_or_generate(&fai_path, None)?;
+            identity::save(&fai_path, &keypair, Some(&passphrase))?;
+            println!("Encrypted identity {}", identity::identity_path(&fai_path).display());
+            return Ok(());
+        }
+
+        if decrypt {
+            if !encrypted {
+                return Err(anyhow::anyhow!("Identity is not encrypted"));
+            }
+            let keypair = identity::repo_identity(&fai_path)?;
+            identity::save(&fai_path, &keypair, None)?;
+            println!("Decrypted identity {}", identity::identity_path(&fai_path).display());
+            return Ok(());
+        }
+
+        let keypair = identity::repo_identity(&fai_path)?;
+        println!("{}", libp2p::PeerId::from(keypair.public()));
+        Ok(())
+    }
+
     /// Handle branch operations
     pub fn handle_branch_command(
         &self,
         branch_name: Option<String>,
         delete: bool,
+        force: bool,
+        rename: Option<Vec<String>>,
         list: bool,
+        verbose: bool,
     ) -> Result<()> {
         self.check_repo_initialized()?;
 
-        let branch_service = BranchService::from_repo_path(&self.repo_path.join(".fai"))?;
+        let mut branch_service = BranchService::from_repo_path(&self.repo_path.join(".fai"))?;
 
-        if list {
-            self.list_branches(&branch_service)?;
+        if list || verbose {
+            self.list_branches(&branch_service, verbose)?;
+        } else if let Some(names) = rename {
+            self.rename_branch(&mut branch_service, &names[0], &names[1])?;
         } else if delete {
-            self.delete_branch(&branch_service, branch_name)?;
+            self.delete_branch(&mut branch_service, branch_name, force)?;
         } else if let Some(name) = branch_name {
             self.create_branch(&branch_service, &name)?;
         } else {
@@ -70,22 +124,475 @@ impl CliService {
         Ok(())
     }
 
+    /// List remote-tracking branches
+    pub fn handle_remote_branches_command(&self) -> Result<()> {
+        self.check_repo_initialized()?;
+
+        let database = crate::database::DatabaseManager::new(&self.repo_path.join(".fai").join("db.sqlite"))?;
+        let remote_refs = database.list_remote_refs(None)?;
+        if remote_refs.is_empty() {
+            println!("No remote-tracking branches");
+            return Ok(());
+        }
+
+        println!("Remote-tracking branches:");
+        for remote_ref in remote_refs {
+            println!("  {} {}", remote_ref.name(), &remote_ref.target[..8.min(remote_ref.target.len())]);
+        }
+        Ok(())
+    }
+
+    /// Set or remove the upstream of a branch
+    ///
+    /// # Arguments
+    /// * `branch_name` - Local branch (defaults to the current branch)
+    /// * `upstream` - "<remote>/<branch>" to track, or None to stop tracking
+    pub fn handle_set_upstream_command(&self, branch_name: Option<String>, upstream: Option<&str>) -> Result<()> {
+        self.check_repo_initialized()?;
+
+        let mut branch_service = BranchService::from_repo_path(&self.repo_path.join(".fai"))?;
+        let branch = match branch_name {
+            Some(name) => name,
+            None => branch_service.get_current_branch()?,
+        };
+
+        match upstream {
+            Some(upstream) => {
+                let upstream = Upstream::parse(upstream)?;
+                branch_service.set_upstream(&branch, upstream.clone())?;
+                self.save_branch_config(&branch_service)?;
+                println!("Branch '{}' set up to track '{}'", branch, upstream);
+            }
+            None => {
+                let upstream = branch_service.unset_upstream(&branch)?;
+                self.save_branch_config(&branch_service)?;
+                println!("Branch '{}' no longer tracks '{}'", branch, upstream);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Handle checkout operations
     pub fn handle_checkout_command(&self, branch_name: &str) -> Result<()> {
         self.check_repo_initialized()?;
 
         let branch_service = BranchService::from_repo_path(&self.repo_path.join(".fai"))?;
         branch_service.checkout_branch(branch_name)?;
+
+        // Keep the HEAD file in sync so new commits land on this branch
+        std::fs::write(
+            self.repo_path.join(".fai").join("HEAD"),
+            format!("ref: refs/heads/{}", branch_name),
+        )?;
         println!("Switched to branch '{}'", branch_name);
 
         Ok(())
     }
 
+    /// Handle merge operations
+    pub fn handle_merge_command(
+        &self,
+        branch_name: &str,
+        ours: bool,
+        theirs: bool,
+        message: Option<String>,
+    ) -> Result<()> {
+        self.check_repo_initialized()?;
+
+        let resolution = if ours {
+            ConflictResolution::Ours
+        } else if theirs {
+            ConflictResolution::Theirs
+        } else {
+            ConflictResolution::Abort
+        };
+
+        let merge_service = MergeService::from_repo_path(&self.repo_path.join(".fai"))?;
+        let current_branch = merge_service.database.get_current_branch()?;
+
+        match merge_service.merge_branch(branch_name, resolution, message.as_deref())? {
+            MergeOutcome::UpToDate => {
+                println!("Already up to date.");
+            }
+            MergeOutcome::FastForward { from, to } => {
+                match from {
+                    Some(from) => println!("Updating {}..{}", &from[..8], &to[..8]),
+                    None => println!("Updating to {}", &to[..8]),
+                }
+                println!("Fast-forward");
+            }
+            MergeOutcome::Merged { commit, resolved } => {
+                for conflict in &resolved {
+                    println!(
+                        "Resolved conflict in {} ({}) using {} version",
+                        conflict.path,
+                        conflict.describe(),
+                        if ours { "our" } else { "their" }
+                    );
+                }
+                println!(
+                    "Merged branch '{}' into {}: {}",
+                    branch_name, current_branch, &commit[..8]
+                );
+            }
+            MergeOutcome::Conflicts(conflicts) => {
+                for conflict in &conflicts {
+                    println!("CONFLICT ({}): {}", conflict.describe(), conflict.path);
+                }
+                return Err(anyhow::anyhow!(
+                    "Automatic merge failed with {} conflict(s). Re-run with --ours or --theirs to choose a version.",
+                    conflicts.len()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle cherry-pick operations
+    pub fn handle_cherry_pick_command(&self, rev: &str, ours: bool, theirs: bool) -> Result<()> {
+        self.check_repo_initialized()?;
+
+        let resolution = if ours {
+            ConflictResolution::Ours
+        } else if theirs {
+            ConflictResolution::Theirs
+        } else {
+            ConflictResolution::Abort
+        };
+
+        let merge_service = MergeService::from_repo_path(&self.repo_path.join(".fai"))?;
+        let current_branch = merge_service.database.get_current_branch()?;
+
+        match merge_service.cherry_pick(rev, resolution)? {
+            MergeOutcome::Merged { commit, resolved } => {
+                for conflict in &resolved {
+                    println!(
+                        "Resolved conflict in {} ({}) using {} version",
+                        conflict.path,
+                        conflict.describe(),
+                        if ours { "our" } else { "their" }
+                    );
+                }
+                println!("Cherry-picked {} onto {}: {}", rev, current_branch, &commit[..8]);
+            }
+            MergeOutcome::Conflicts(conflicts) => {
+                for conflict in &conflicts {
+                    println!("CONFLICT ({}): {}", conflict.describe(), conflict.path);
+                }
+                return Err(anyhow::anyhow!(
+                    "Cherry-pick failed with {} conflict(s). Re-run with --ours or --theirs to choose a version.",
+                    conflicts.len()
+                ));
+            }
+            MergeOutcome::UpToDate | MergeOutcome::FastForward { .. } => {
+                println!("Nothing to cherry-pick: changes already present on {}", current_branch);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle tag operations
+    pub fn handle_tag_command(
+        &self,
+        tag_name: Option<String>,
+        rev: Option<String>,
+        message: Option<String>,
+        list: bool,
+        delete: bool,
+    ) -> Result<()> {
+        self.check_repo_initialized()?;
+
+        let database = crate::database::DatabaseManager::new(&self.repo_path.join(".fai/db.sqlite"))?;
+
+        match tag_name {
+            Some(name) if delete => {
+                database.delete_tag(&name)?;
+                println!("Deleted tag '{}'", name);
+            }
+            Some(name) if !list => {
+                let target = database.resolve_revision(rev.as_deref().unwrap_or("HEAD"))?;
+                let tag = database.create_tag(&name, &target, message.as_deref())?;
+                println!(
+                    "Created {} tag '{}' at {}",
+                    if tag.is_annotated() { "annotated" } else { "lightweight" },
+                    name,
+                    &target[..8]
+                );
+            }
+            None if delete => {
+                return Err(anyhow::anyhow!("Tag name required for deletion"));
+            }
+            _ => {
+                let tags = database.list_tags()?;
+                if tags.is_empty() {
+                    println!("No tags");
+                }
+                for tag in tags {
+                    match &tag.message {
+                        Some(message) => println!("{:<16} {}  {}", tag.name, &tag.target[..8], message),
+                        None => println!("{:<16} {}", tag.name, &tag.target[..8]),
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle reflog display
+    pub fn handle_reflog_command(&self, ref_name: Option<&str>, limit: Option<usize>) -> Result<()> {
+        self.check_repo_initialized()?;
+
+        let database = crate::database::DatabaseManager::new(&self.repo_path.join(".fai/db.sqlite"))?;
+        let entries = database.reflog(ref_name, limit)?;
+
+        if entries.is_empty() {
+            println!("No reflog entries");
+        }
+        for (i, entry) in entries.iter().enumerate() {
+            let hash = entry.new_hash.as_deref().unwrap_or("(deleted)");
+            let selector = match ref_name {
+                Some(name) => format!("{}@{{{}}}", name, i),
+                None => entry.ref_name.clone(),
+            };
+            println!(
+                "{} {}: {} ({}, {})",
+                &hash[..8.min(hash.len())],
+                selector,
+                entry.operation,
+                entry.user,
+                entry.timestamp.format("%Y-%m-%d %H:%M:%S")
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Handle garbage collection
+    pub fn handle_gc_command(&self, dry_run: bool) -> Result<()> {
+        self.check_repo_initialized()?;
+
+        let gc_service = GcService::from_repo_path(&self.repo_path.join(".fai"))?;
+        let summary = gc_service.run(dry_run)?;
+
+        let verb = if dry_run { "Would remove" } else { "Removed" };
+        println!("{} {} unreachable commit(s)", verb, summary.commits_pruned);
+        println!(
+            "{} {} object(s), {:.2} MB",
+            verb,
+            summary.objects_removed,
+            summary.bytes_freed as f64 / 1_048_576.0
+        );
+        println!("{} {} expired reflog entries", verb, summary.reflog_entries_expired);
+
+        Ok(())
+    }
+
+    /// Handle `fai stash push`
+    pub fn handle_stash_push(&self, message: Option<String>) -> Result<()> {
+        self.check_repo_initialized()?;
+
+        let stash_service = StashService::from_repo_path(&self.repo_path.join(".fai"))?;
+        let entry = stash_service.push(message.as_deref())?;
+        println!("Saved working directory and staged changes: {}", entry.message);
+
+        Ok(())
+    }
+
+    /// Handle `fai stash pop`
+    pub fn handle_stash_pop(&self, index: usize) -> Result<()> {
+        self.check_repo_initialized()?;
+
+        let stash_service = StashService::from_repo_path(&self.repo_path.join(".fai"))?;
+        let (entry, files) = stash_service.pop(index)?;
+        for file in &files {
+            println!(
+                "  {}: {}",
+                if file.staged { "staged" } else { "modified" },
+                file.path
+            );
+        }
+        println!("Dropped stash@{{{}}} ({})", index, entry.message);
+
+        Ok(())
+    }
+
+    /// Handle `fai stash list`
+    pub fn handle_stash_list(&self) -> Result<()> {
+        self.check_repo_initialized()?;
+
+        let stash_service = StashService::from_repo_path(&self.repo_path.join(".fai"))?;
+        for (index, entry) in stash_service.list()?.iter().enumerate() {
+            println!("stash@{{{}}}: On {}: {}", index, entry.branch, entry.message);
+        }
+
+        Ok(())
+    }
+
+    /// Handle `fai stash drop`
+    pub fn handle_stash_drop(&self, index: usize) -> Result<()> {
+        self.check_repo_initialized()?;
+
+        let stash_service = StashService::from_repo_path(&self.repo_path.join(".fai"))?;
+        let entry = stash_service.drop(index)?;
+        println!("Dropped stash@{{{}}} ({})", index, entry.message);
+
+        Ok(())
+    }
+
+    /// Handle `fai remote add`
+    ///
+    /// # Arguments
+    /// * `name` - Remote name
+    /// * `peer_id` - Peer ID of the remote
+    /// * `addresses` - Multiaddrs to dial the remote at
+    pub fn handle_remote_add(&self, name: &str, peer_id: &str, addresses: Vec<String>) -> Result<()> {
+        self.check_repo_initialized()?;
+
+        let fai_path = self.repo_path.join(".fai");
+        let mut config = RepoConfig::load(&fai_path)?;
+        if config.remotes.contains_key(name) {
+            return Err(anyhow::anyhow!("Remote '{}' already exists", name));
+        }
+
+        let remote_config = RemoteConfig {
+            peer_id: peer_id.to_string(),
+            addresses,
+        };
+        // Validate the name, peer ID and addresses before saving
+        Remote::from_config(name, &remote_config)?;
+        config.remotes.insert(name.to_string(), remote_config);
+        config.save(&fai_path)?;
+
+        println!("Added remote '{}' ({})", name, peer_id);
+        Ok(())
+    }
+
+    /// Handle `fai remote list`
+    ///
+    /// # Arguments
+    /// * `verbose` - Also print peer IDs and addresses
+    pub fn handle_remote_list(&self, verbose: bool) -> Result<()> {
+        self.check_repo_initialized()?;
+
+        let config = RepoConfig::load(&self.repo_path.join(".fai"))?;
+        for (name, remote) in &config.remotes {
+            if !verbose {
+                println!("{}", name);
+                continue;
+            }
+            println!("{}\t{}", name, remote.peer_id);
+            for addr in &remote.addresses {
+                println!("\t{}", addr);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle `fai remote remove`
+    ///
+    /// Deletes the remote's remote-tracking refs and any upstream settings
+    /// that point at it.
+    pub fn handle_remote_remove(&self, name: &str) -> Result<()> {
+        self.check_repo_initialized()?;
+
+        let fai_path = self.repo_path.join(".fai");
+        let mut config = RepoConfig::load(&fai_path)?;
+        if config.remotes.remove(name).is_none() {
+            return Err(anyhow::anyhow!("No such remote: '{}'", name));
+        }
+        config.branches.upstream.retain(|_, upstream| upstream.remote != name);
+        config.save(&fai_path)?;
+
+        let database = crate::database::DatabaseManager::new(&fai_path.join("db.sqlite"))?;
+        let deleted = database.delete_remote_refs(name)?;
+
+        println!("Removed remote '{}' ({} remote-tracking branches deleted)", name, deleted);
+        Ok(())
+    }
+
+    /// Handle `fai pull --abort`
+    ///
+    /// Discards the journaled pull or clone: remote-tracking refs go back to
+    /// where they were, received commits nothing else reaches are removed,
+    /// and so are the objects downloaded for them.
+    pub fn handle_pull_abort(&self) -> Result<()> {
+        self.check_repo_initialized()?;
+
+        let fai_path = self.repo_path.join(".fai");
+        let gc_service = GcService::from_repo_path(&fai_path)?;
+        let database = &gc_service.database;
+        let storage = crate::storage::StorageManager::new(fai_path)?;
+        let transfer = database
+            .abort_transfer()?
+            .ok_or_else(|| anyhow::anyhow!("No interrupted pull or clone to abort"))?;
+
+        // Keep objects that the remaining commits, staging or stashes refer to
+        let commits: Vec<String> = database.get_all_commits()?.into_iter().map(|commit| commit.hash).collect();
+        let live_objects = gc_service.live_objects(&commits)?;
+        let mut removed = 0;
+        for hash in &transfer.verified_objects {
+            if !live_objects.contains(hash) && storage.exists(hash) {
+                storage.remove_object(hash)?;
+                removed += 1;
+            }
+        }
+
+        let discarded = transfer
+            .commits
+            .iter()
+            .filter(|hash| matches!(database.get_commit(hash), Ok(None)))
+            .count();
+        println!(
+            "Aborted transfer from '{}': discarded {} commit(s) and {} object(s)",
+            transfer.remote, discarded, removed
+        );
+        Ok(())
+    }
+
+    /// Handle reset operations
+    pub fn handle_reset_command(&self, rev: &str, mode: ResetMode) -> Result<()> {
+        self.check_repo_initialized()?;
+
+        let history_service = HistoryService::from_repo_path(&self.repo_path.join(".fai"))?;
+        let summary = history_service.reset(rev, mode)?;
+
+        match mode {
+            ResetMode::Soft if summary.files_updated > 0 => {
+                println!("Staged {} file(s) from the previous head", summary.files_updated);
+            }
+            ResetMode::Hard => {
+                println!(
+                    "Restored {} file(s), removed {} file(s)",
+                    summary.files_updated, summary.files_removed
+                );
+            }
+            _ => {}
+        }
+        println!("HEAD is now at {}", &summary.to[..8]);
+
+        Ok(())
+    }
+
+    /// Handle revert operations
+    pub fn handle_revert_command(&self, rev: &str) -> Result<()> {
+        self.check_repo_initialized()?;
+
+        let history_service = HistoryService::from_repo_path(&self.repo_path.join(".fai"))?;
+        let commit = history_service.revert(rev)?;
+        println!("Created revert commit {}", &commit[..8]);
+
+        Ok(())
+    }
+
     /// Handle commit amend operations
     pub fn handle_commit_amend(&self, message: Option<String>) -> Result<()> {
         self.check_repo_initialized()?;
 
-        let fai = crate::FaiProtocol::new_at(&self.repo_path)?;
         let database = crate::database::DatabaseManager::new(&self.repo_path.join(".fai/db.sqlite"))?;
 
         // Get current HEAD commit
@@ -107,18 +614,12 @@ impl CliService {
         println!("Old message: {}", last_commit.message);
         println!("New message: {}", commit_message);
 
-        // Get staged files (they should be the same as the last commit)
-        let staged_files = database.get_staged_files()?;
-        let has_staged_files = !staged_files.is_empty();
-        let files_to_commit = if has_staged_files {
-            staged_files
-        } else {
-            // If no staged files, get files from the last commit
-            database.get_commit_files(&current_head)?
-        };
+        // Keep the last commit's snapshot and apply any staged changes on top
+        let has_staged_files = !database.get_staged_files()?.is_empty();
+        let files_to_commit = database.snapshot_with_staged(Some(&current_head))?;
 
         // Calculate new commit hash
-        let new_hash = self.calculate_amended_hash(&commit_message, &current_branch, &files_to_commit, &last_commit)?;
+        let new_hash = self.calculate_amended_hash(&commit_message, &current_branch, &files_to_commit, last_commit)?;
 
         // Create new commit with same parents
         database.create_commit(
@@ -129,9 +630,12 @@ impl CliService {
             last_commit.is_merge,
         )?;
 
-        // Update current branch HEAD
-        database.update_branch_head(&current_branch, &new_hash)?;
-        database.update_head(&new_hash)?;
+        // Update current branch HEAD (the HEAD file follows the branch ref)
+        database.update_branch_head_with_reason(
+            &current_branch,
+            &new_hash,
+            &format!("commit (amend): {}", commit_message),
+        )?;
 
         println!("Amended commit: {}", &new_hash[..8]);
 
@@ -169,11 +673,35 @@ impl CliService {
     }
 
     /// List branches with nice formatting
-    fn list_branches(&self, branch_service: &BranchService) -> Result<()> {
+    ///
+    /// In verbose mode each branch shows its head commit, its ahead/behind
+    /// counts relative to the current branch and the head commit message.
+    fn list_branches(&self, branch_service: &BranchService, verbose: bool) -> Result<()> {
         let branches = branch_service.list_branches()?;
 
         println!("Branches:");
         for branch in branches {
+            if verbose {
+                let message = branch_service
+                    .database
+                    .get_commit(&branch.head_commit)?
+                    .map(|commit| commit.message)
+                    .unwrap_or_default();
+                let divergence = branch.divergence_text();
+                let tracking = branch.tracking_text();
+                println!("{}{} {}{}{}{}{} {}",
+                    branch.status_marker(),
+                    branch.name,
+                    if branch.is_empty { branch.status_text() } else { branch.short_hash() },
+                    if tracking.is_empty() { "" } else { " " },
+                    tracking,
+                    if divergence.is_empty() { "" } else { " " },
+                    divergence,
+                    message
+                );
+                continue;
+            }
+
             println!("{}{}{} {}",
                 branch.status_marker(),
                 branch.name,
@@ -188,6 +716,14 @@ impl CliService {
         Ok(())
     }
 
+    /// Write the branch settings (protection rules, upstreams) back to the repo config
+    fn save_branch_config(&self, branch_service: &BranchService) -> Result<()> {
+        let fai_path = self.repo_path.join(".fai");
+        let mut config = RepoConfig::load(&fai_path)?;
+        config.branches = branch_service.config().clone();
+        config.save(&fai_path)
+    }
+
     /// Create a new branch
     fn create_branch(&self, branch_service: &BranchService, name: &str) -> Result<()> {
         let current_head = branch_service.database.get_head_commit()?
@@ -199,27 +735,56 @@ impl CliService {
     }
 
     /// Delete a branch
-    fn delete_branch(&self, branch_service: &BranchService, branch_name: Option<String>) -> Result<()> {
+    fn delete_branch(&self, branch_service: &mut BranchService, branch_name: Option<String>, force: bool) -> Result<()> {
         let name = branch_name.ok_or_else(|| anyhow::anyhow!("Branch name required for deletion"))?;
-        branch_service.delete_branch(&name)?;
-        println!("Deleted branch '{}'", name);
+        let head = branch_service.get_branch_head(&name)?;
+        branch_service.delete_branch(&name, force)?;
+        self.save_branch_config(branch_service)?;
+        match head.filter(|hash| hash != crate::database::EMPTY_COMMIT_HASH) {
+            Some(head) => println!("Deleted branch '{}' (was {})", name, &head[..8]),
+            None => println!("Deleted branch '{}'", name),
+        }
+        Ok(())
+    }
+
+    /// Rename a branch
+    fn rename_branch(&self, branch_service: &mut BranchService, old_name: &str, new_name: &str) -> Result<()> {
+        branch_service.rename_branch(old_name, new_name)?;
+        self.save_branch_config(branch_service)?;
+
+        // Keep the HEAD file pointing at the renamed branch
+        let head_path = self.repo_path.join(".fai").join("HEAD");
+        if std::fs::read_to_string(&head_path)?.trim() == format!("ref: refs/heads/{}", old_name) {
+            std::fs::write(&head_path, format!("ref: refs/heads/{}", new_name))?;
+        }
+
+        println!("Renamed branch '{}' to '{}'", old_name, new_name);
         Ok(())
     }
 
     /// Show branch command help
     fn show_branch_help(&self) {
         println!("Usage: fai branch [OPTIONS] [BRANCH_NAME]");
-        println!("");
+        println!();
         println!("Options:");
         println!("  -l, --list     List all branches");
+        println!("  -v, --verbose  List branches with head commit and ahead/behind counts");
         println!("  -d, --delete    Delete a branch");
-        println!("");
+        println!("  -f, --force    Delete a branch even if it is not fully merged");
+        println!("  -m, --move <OLD> <NEW>  Rename a branch");
+        println!("  -r, --remotes  List remote-tracking branches");
+        println!("  -u, --set-upstream-to <REMOTE>/<BRANCH>  Track a remote branch");
+        println!("      --unset-upstream  Stop tracking the upstream branch");
+        println!();
         println!("Arguments:");
         println!("  <BRANCH_NAME>  Name of the branch to create");
-        println!("");
+        println!();
         println!("Examples:");
         println!("  fai branch feature-xyz    # Create a new branch");
         println!("  fai branch --list         # List all branches");
+        println!("  fai branch -v             # Show how branches diverge from the current one");
         println!("  fai branch --delete old   # Delete a branch");
+        println!("  fai branch -m old new     # Rename a branch");
+        println!("  fai branch -u origin/main # Track origin/main from the current branch");
     }
 }
\ No newline at end of file
@@ -0,0 +1,195 @@
+//! Garbage collection service for FAI Protocol
+//!
+//! Removes commits and stored objects that nothing refers to any more.
+//! Branch heads, tags, remote-tracking refs, a detached HEAD, the staging
+//! area, stashes and recent reflog entries are the roots; reflog entries stay
+//! roots for `gc.reflog_expire_days` so recent resets, amends and branch
+//! deletions can still be undone.
+
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::config::RepoConfig;
+use crate::database::{DatabaseManager, EMPTY_COMMIT_HASH};
+use crate::storage::StorageManager;
+
+/// Result of a garbage collection run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcSummary {
+    /// Unreachable commits removed from the database
+    pub commits_pruned: usize,
+    /// Unreferenced objects removed from `.fai/objects`
+    pub objects_removed: usize,
+    /// Bytes freed by removing objects
+    pub bytes_freed: u64,
+    /// Reflog entries older than the expiry period
+    pub reflog_entries_expired: usize,
+}
+
+/// Garbage collection service
+pub struct GcService {
+    pub database: DatabaseManager,
+    storage: StorageManager,
+    config: RepoConfig,
+    fai_path: PathBuf,
+}
+
+impl GcService {
+    /// Create a new garbage collection service instance
+    pub fn new(database: DatabaseManager, storage: StorageManager, config: RepoConfig, fai_path: PathBuf) -> Self {
+        Self {
+            database,
+            storage,
+            config,
+            fai_path,
+        }
+    }
+
+    /// Initialize the garbage collection service from repository path (the `.fai` directory)
+    pub fn from_repo_path(repo_path: &Path) -> Result<Self> {
+        let database = DatabaseManager::new(&repo_path.join("db.sqlite"))?;
+        let storage = StorageManager::new(repo_path.to_path_buf())?;
+        let config = RepoConfig::load(repo_path)?;
+        Ok(Self::new(database, storage, config, repo_path.to_path_buf()))
+    }
+
+    /// Remove unreachable commits and unreferenced objects
+    ///
+    /// # Arguments
+    /// * `dry_run` - Only report what would be removed
+    pub fn run(&self, dry_run: bool) -> Result<GcSummary> {
+        let cutoff = Utc::now() - Duration::days(self.config.gc.reflog_expire_days as i64);
+
+        // Commits that must survive
+        let mut roots: Vec<String> = self
+            .database
+            .list_branches()?
+            .into_iter()
+            .map(|(_, head)| head)
+            .filter(|head| head != EMPTY_COMMIT_HASH)
+            .collect();
+        roots.extend(self.database.list_tags()?.into_iter().map(|tag| tag.target));
+        roots.extend(self.database.list_remote_refs(None)?.into_iter().map(|remote_ref| remote_ref.target));
+        roots.extend(self.database.reflog_commits_since(cutoff)?);
+        roots.extend(self.database.list_stashes()?.into_iter().filter_map(|stash| stash.base_commit));
+        if let Ok(head) = std::fs::read_to_string(self.fai_path.join("HEAD")) {
+            if !head.starts_with("ref:") {
+                roots.push(head.trim().to_string());
+            }
+        }
+        let reachable = self.database.reachable_commits(&roots)?;
+
+        let live_objects = self.live_objects(&reachable)?;
+
+        let mut summary = GcSummary::default();
+
+        for commit in self.database.get_all_commits()? {
+            if !reachable.contains(&commit.hash) {
+                if !dry_run {
+                    self.database.delete_commit(&commit.hash)?;
+                }
+                summary.commits_pruned += 1;
+            }
+        }
+
+        for hash in self.storage.list_objects()? {
+            if live_objects.contains(&hash) {
+                continue;
+            }
+            summary.bytes_freed += if dry_run {
+                let object_path = self.fai_path.join("objects").join(&hash[..2]).join(&hash[2..]);
+                std::fs::metadata(object_path).map(|m| m.len()).unwrap_or(0)
+            } else {
+                self.storage.remove_object(&hash)?
+            };
+            summary.objects_removed += 1;
+        }
+
+        summary.reflog_entries_expired = if dry_run {
+            self.database
+                .reflog(None, None)?
+                .iter()
+                .filter(|entry| entry.timestamp < cutoff)
+                .count()
+        } else {
+            self.database.expire_reflog(cutoff)?
+        };
+
+        Ok(summary)
+    }
+
+    /// Objects that `commits`, the staging area and stashes refer to
+    ///
+    /// Includes the chunks of multi-chunk files, which no snapshot lists.
+    pub fn live_objects<'a>(&self, commits: impl IntoIterator<Item = &'a String>) -> Result<HashSet<String>> {
+        let mut file_hashes: HashSet<String> = self
+            .database
+            .get_staged_files()?
+            .into_iter()
+            .map(|(_, hash, _)| hash)
+            .collect();
+        file_hashes.extend(self.database.stashed_file_hashes()?);
+        for commit in commits {
+            file_hashes.extend(self.database.get_commit_files(commit)?.into_iter().map(|(_, hash, _)| hash));
+        }
+        let mut live_objects = file_hashes.clone();
+        for hash in &file_hashes {
+            if let Ok(Some(manifest)) = self.storage.read_manifest(hash) {
+                live_objects.extend(manifest.chunks);
+            }
+        }
+        Ok(live_objects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn create_test_gc_service() -> (GcService, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let fai_path = temp_dir.path().join(".fai");
+        std::fs::create_dir_all(&fai_path).unwrap();
+        let service = GcService::from_repo_path(&fai_path).unwrap();
+        (service, temp_dir)
+    }
+
+    #[test]
+    fn test_reflog_keeps_reset_commits_alive() {
+        let (service, _temp_dir) = create_test_gc_service();
+        let db = &service.database;
+
+        let kept = service.storage.store(b"kept weights").unwrap();
+        let dropped = service.storage.store(b"dropped weights").unwrap();
+        let orphan = service.storage.store(b"never committed").unwrap();
+
+        db.create_commit("c1", "Keep", &[], &[("model.bin".to_string(), kept.clone(), 12)], false)
+            .unwrap();
+        db.create_commit("c2", "Drop", &["c1".to_string()], &[("model.bin".to_string(), dropped.clone(), 15)], false)
+            .unwrap();
+        db.update_branch_head("main", "c2").unwrap();
+        db.update_branch_head("main", "c1").unwrap();
+
+        // c2 is only referenced by the reflog, which keeps it alive
+        let summary = service.run(false).unwrap();
+        assert_eq!(summary.commits_pruned, 0);
+        assert_eq!(summary.objects_removed, 1);
+        assert!(!service.storage.exists(&orphan));
+        assert!(service.storage.exists(&dropped));
+
+        // Once the reflog entries expire, c2 and its object go away
+        assert_eq!(db.expire_reflog(Utc::now() + Duration::seconds(1)).unwrap(), 4);
+        let summary = service.run(true).unwrap();
+        assert_eq!(summary.commits_pruned, 1);
+        assert_eq!(summary.objects_removed, 1);
+        assert!(service.storage.exists(&dropped)); // dry run
+
+        service.run(false).unwrap();
+        assert!(db.get_commit("c2").unwrap().is_none());
+        assert!(!service.storage.exists(&dropped));
+        assert!(service.storage.exists(&kept));
+    }
+}
@@ -0,0 +1,397 @@
+//! History service for FAI Protocol
+//!
+//! Provides commands that rewrite or undo history on the current branch:
+//! - `reset` moves the branch head, optionally resetting staging and the working tree
+//! - `revert` records a new commit that undoes the file changes of an earlier one
+
+use anyhow::Result;
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::database::{DatabaseManager, EMPTY_COMMIT_HASH};
+use crate::storage::StorageManager;
+
+use super::merge_service::FileEntry;
+use super::worktree;
+
+/// How much of the repository state `reset` rewrites
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetMode {
+    /// Move the branch head only; the undone changes are left staged
+    Soft,
+    /// Move the branch head and clear the staging area
+    Mixed,
+    /// Move the branch head, clear staging and restore the working tree
+    Hard,
+}
+
+/// Result of a reset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResetSummary {
+    /// Previous head of the branch (None if it had no commits)
+    pub from: Option<String>,
+    /// New head of the branch
+    pub to: String,
+    /// Number of files staged (soft) or written to the working tree (hard)
+    pub files_updated: usize,
+    /// Number of files removed from the working tree (hard only)
+    pub files_removed: usize,
+}
+
+/// History service for resetting and reverting commits
+pub struct HistoryService {
+    pub database: DatabaseManager,
+    storage: StorageManager,
+    work_dir: PathBuf,
+}
+
+impl HistoryService {
+    /// Create a new history service instance
+    ///
+    /// # Arguments
+    /// * `database` - Repository database
+    /// * `storage` - Object storage used to restore file contents
+    /// * `work_dir` - Working tree that file paths are relative to
+    pub fn new(database: DatabaseManager, storage: StorageManager, work_dir: PathBuf) -> Self {
+        Self {
+            database,
+            storage,
+            work_dir,
+        }
+    }
+
+    /// Initialize the history service from repository path (the `.fai` directory)
+    pub fn from_repo_path(repo_path: &Path) -> Result<Self> {
+        let database = DatabaseManager::new(&repo_path.join("db.sqlite"))?;
+        let storage = StorageManager::new(repo_path.to_path_buf())?;
+        let work_dir = repo_path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from("."));
+        Ok(Self::new(database, storage, work_dir))
+    }
+
+    /// Move the current branch to another commit
+    ///
+    /// # Arguments
+    /// * `rev` - Target revision (see [`DatabaseManager::resolve_revision`])
+    /// * `mode` - How much state to reset besides the branch head
+    pub fn reset(&self, rev: &str, mode: ResetMode) -> Result<ResetSummary> {
+        let branch = self.database.get_current_branch()?;
+        let target = self.database.resolve_revision(rev)?;
+        let current = self
+            .database
+            .get_branch_head(&branch)?
+            .filter(|hash| hash != EMPTY_COMMIT_HASH);
+
+        let current_files = match &current {
+            Some(hash) => self.database.snapshot_with_staged(Some(hash))?,
+            None => self.database.get_staged_files()?,
+        };
+        let target_files = self.database.get_commit_files(&target)?;
+
+        let mut files_updated = 0;
+        let mut files_removed = 0;
+
+        match mode {
+            ResetMode::Soft => {
+                // Keep everything that differs from the target staged, so
+                // committing again recreates the state before the reset
+                let target_map: BTreeMap<&str, &str> = target_files
+                    .iter()
+                    .map(|(path, hash, _)| (path.as_str(), hash.as_str()))
+                    .collect();
+                for (path, hash, size) in &current_files {
+                    if target_map.get(path.as_str()) != Some(&hash.as_str()) {
+                        self.database.add_to_staging(path, hash, *size)?;
+                        files_updated += 1;
+                    }
+                }
+            }
+            ResetMode::Mixed => {
+                self.database.clear_staging()?;
+            }
+            ResetMode::Hard => {
+                // Restore file contents before touching any refs, so a missing
+                // object or an unsafe path leaves the repository unchanged
+                (files_updated, files_removed) =
+                    worktree::check_out(&self.storage, &self.work_dir, &current_files, &target_files)?;
+                self.database.clear_staging()?;
+            }
+        }
+
+        self.database
+            .update_branch_head_with_reason(&branch, &target, &format!("reset: moving to {}", rev))?;
+
+        Ok(ResetSummary {
+            from: current,
+            to: target,
+            files_updated,
+            files_removed,
+        })
+    }
+
+    /// Record a new commit that undoes the file changes of an earlier commit
+    ///
+    /// Every path the commit changed relative to its parent is set back to
+    /// the parent's version. Paths that were modified again afterwards are
+    /// reported as conflicts instead of being overwritten. The working tree
+    /// is updated to the new snapshot, so it must not hold uncommitted changes.
+    ///
+    /// # Arguments
+    /// * `rev` - Revision of the commit to revert
+    ///
+    /// # Returns
+    /// Hash of the new revert commit
+    pub fn revert(&self, rev: &str) -> Result<String> {
+        let branch = self.database.get_current_branch()?;
+        let target = self.database.resolve_revision(rev)?;
+        let commit = self
+            .database
+            .get_commit(&target)?
+            .ok_or_else(|| anyhow::anyhow!("Commit not found: {}", target))?;
+
+        if commit.is_merge || commit.parents.len() > 1 {
+            return Err(anyhow::anyhow!(
+                "Commit {} is a merge commit; reverting merges is not supported",
+                &target[..8.min(target.len())]
+            ));
+        }
+
+        let head = self
+            .database
+            .get_branch_head(&branch)?
+            .filter(|hash| hash != EMPTY_COMMIT_HASH)
+            .ok_or_else(|| anyhow::anyhow!("Branch '{}' has no commits", branch))?;
+
+        if !self.database.is_ancestor(&target, &head)? {
+            return Err(anyhow::anyhow!(
+                "Commit {} is not part of branch '{}'",
+                &target[..8.min(target.len())],
+                branch
+            ));
+        }
+
+        let parent_files = match commit.parents.first() {
+            Some(parent) => self.database.get_commit_files(parent)?,
+            None => Vec::new(),
+        };
+        let commit_files = self.database.get_commit_files(&target)?;
+        let head_files = self.database.get_commit_files(&head)?;
+        worktree::check_clean(&self.database, &self.storage, &self.work_dir, &head_files, "reverting")?;
+
+        let reverted = reverse_changes(&parent_files, &commit_files, &head_files)
+            .map_err(|conflicts| {
+                anyhow::anyhow!(
+                    "Cannot revert {}: changed again since then: {}",
+                    &target[..8.min(target.len())],
+                    conflicts.join(", ")
+                )
+            })?;
+
+        if reverted == head_files {
+            return Err(anyhow::anyhow!("Nothing to revert"));
+        }
+
+        let message = format!("Revert \"{}\"", commit.message);
+        let parents = vec![head];
+        let commit_data = format!(
+            "{}{}{:?}{:?}",
+            Utc::now().timestamp_millis(),
+            message,
+            parents,
+            reverted
+        );
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(commit_data.as_bytes());
+        let commit_hash = hasher.finalize().to_hex().to_string();
+
+        // Update the working tree before touching any refs, so a missing
+        // object or an unsafe path leaves the repository unchanged
+        worktree::check_out(&self.storage, &self.work_dir, &head_files, &reverted)?;
+        self.database
+            .create_commit(&commit_hash, &message, &parents, &reverted, false)?;
+        self.database
+            .update_branch_head_with_reason(&branch, &commit_hash, &format!("revert: {}", message))?;
+
+        Ok(commit_hash)
+    }
+}
+
+/// Undo the changes between `parent` and `commit` on top of `head`
+///
+/// # Returns
+/// The resulting snapshot ordered by path, or the paths whose version in
+/// `head` no longer matches `commit`
+pub fn reverse_changes(
+    parent: &[FileEntry],
+    commit: &[FileEntry],
+    head: &[FileEntry],
+) -> std::result::Result<Vec<FileEntry>, Vec<String>> {
+    let index = |files: &[FileEntry]| -> BTreeMap<String, (String, u64)> {
+        files
+            .iter()
+            .map(|(path, hash, size)| (path.clone(), (hash.clone(), *size)))
+            .collect()
+    };
+    let parent = index(parent);
+    let commit = index(commit);
+    let mut result = index(head);
+
+    let mut paths: Vec<&String> = parent.keys().chain(commit.keys()).collect();
+    paths.sort();
+    paths.dedup();
+
+    let mut conflicts = Vec::new();
+    for path in paths {
+        let before = parent.get(path);
+        let after = commit.get(path);
+        if before == after {
+            continue;
+        }
+        if result.get(path).map(|(hash, _)| hash) != after.map(|(hash, _)| hash) {
+            conflicts.push(path.clone());
+            continue;
+        }
+        match before {
+            Some(entry) => result.insert(path.clone(), entry.clone()),
+            None => result.remove(path),
+        };
+    }
+
+    if !conflicts.is_empty() {
+        return Err(conflicts);
+    }
+
+    Ok(result
+        .into_iter()
+        .map(|(path, (hash, size))| (path, hash, size))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn file(path: &str, hash: &str) -> FileEntry {
+        (path.to_string(), hash.to_string(), 1)
+    }
+
+    fn stored(service: &HistoryService, path: &str, content: &str) -> FileEntry {
+        let hash = service.storage.store(content.as_bytes()).unwrap();
+        (path.to_string(), hash, content.len() as u64)
+    }
+
+    fn create_test_history_service() -> (HistoryService, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let fai_path = temp_dir.path().join(".fai");
+        std::fs::create_dir_all(&fai_path).unwrap();
+        let service = HistoryService::from_repo_path(&fai_path).unwrap();
+        (service, temp_dir)
+    }
+
+    #[test]
+    fn test_reverse_changes() {
+        let parent = vec![file("a", "a1"), file("b", "b1")];
+        let commit = vec![file("a", "a2"), file("c", "c1")];
+        let head = vec![file("a", "a2"), file("c", "c1"), file("d", "d1")];
+
+        let reverted = reverse_changes(&parent, &commit, &head).unwrap();
+        assert_eq!(reverted, vec![file("a", "a1"), file("b", "b1"), file("d", "d1")]);
+
+        let modified_again = vec![file("a", "a3"), file("c", "c1")];
+        assert_eq!(
+            reverse_changes(&parent, &commit, &modified_again),
+            Err(vec!["a".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_reset_modes() {
+        let (service, temp_dir) = create_test_history_service();
+        let db = &service.database;
+
+        let v1 = service.storage.store(b"weights v1").unwrap();
+        let v2 = service.storage.store(b"weights v2").unwrap();
+        let extra = service.storage.store(b"extra").unwrap();
+
+        db.create_commit("c1", "First", &[], &[(String::from("model.bin"), v1.clone(), 10)], false)
+            .unwrap();
+        db.create_commit(
+            "c2",
+            "Second",
+            &["c1".to_string()],
+            &[("extra.txt".to_string(), extra.clone(), 5), ("model.bin".to_string(), v2.clone(), 10)],
+            false,
+        )
+        .unwrap();
+        db.update_branch_head("main", "c2").unwrap();
+
+        // Soft: the undone changes stay staged
+        let summary = service.reset("HEAD~1", ResetMode::Soft).unwrap();
+        assert_eq!(summary.from.as_deref(), Some("c2"));
+        assert_eq!(summary.to, "c1");
+        assert_eq!(db.get_branch_head("main").unwrap().as_deref(), Some("c1"));
+        assert_eq!(db.get_staged_files().unwrap().len(), 2);
+
+        // Mixed: staging is cleared
+        db.update_branch_head("main", "c2").unwrap();
+        service.reset("c1", ResetMode::Mixed).unwrap();
+        assert!(db.get_staged_files().unwrap().is_empty());
+
+        // Hard: the working tree follows the target snapshot
+        db.update_branch_head("main", "c2").unwrap();
+        std::fs::write(temp_dir.path().join("model.bin"), b"weights v2").unwrap();
+        std::fs::write(temp_dir.path().join("extra.txt"), b"extra").unwrap();
+        let summary = service.reset("HEAD~1", ResetMode::Hard).unwrap();
+        assert_eq!(summary.files_updated, 1);
+        assert_eq!(summary.files_removed, 1);
+        assert_eq!(std::fs::read(temp_dir.path().join("model.bin")).unwrap(), b"weights v1");
+        assert!(!temp_dir.path().join("extra.txt").exists());
+
+        // Hard: a snapshot pointing outside the working tree is refused
+        let evil = service.storage.store(b"evil").unwrap();
+        db.create_commit("c3", "Escape", &["c1".to_string()], &[("../evil".to_string(), evil, 4)], false)
+            .unwrap();
+        assert!(service.reset("c3", ResetMode::Hard).is_err());
+        assert_eq!(db.get_branch_head("main").unwrap().as_deref(), Some("c1"));
+        assert!(!temp_dir.path().parent().unwrap().join("evil").exists());
+    }
+
+    #[test]
+    fn test_revert_creates_inverse_commit() {
+        let (service, temp_dir) = create_test_history_service();
+        let db = &service.database;
+        let file = |path: &str, content: &str| stored(&service, path, content);
+        let work_file = |path: &str| std::fs::read_to_string(temp_dir.path().join(path)).ok();
+
+        db.create_commit("c1", "First", &[], &[file("a", "a1")], false).unwrap();
+        db.create_commit("c2", "Add b", &["c1".to_string()], &[file("a", "a1"), file("b", "b1")], false)
+            .unwrap();
+        db.create_commit("c3", "Change a", &["c2".to_string()], &[file("a", "a2"), file("b", "b1")], false)
+            .unwrap();
+        db.update_branch_head("main", "c3").unwrap();
+        std::fs::write(temp_dir.path().join("a"), "a2").unwrap();
+        std::fs::write(temp_dir.path().join("b"), "b1").unwrap();
+
+        // A local edit to a tracked file blocks the revert
+        std::fs::write(temp_dir.path().join("b"), "edited").unwrap();
+        let err = service.revert("c2").unwrap_err().to_string();
+        assert!(err.contains("uncommitted changes to b"), "{}", err);
+        std::fs::write(temp_dir.path().join("b"), "b1").unwrap();
+
+        let revert = service.revert("c2").unwrap();
+        let commit = db.get_commit(&revert).unwrap().unwrap();
+        assert_eq!(commit.message, "Revert \"Add b\"");
+        assert_eq!(commit.parents, vec!["c3".to_string()]);
+        assert_eq!(db.get_commit_files(&revert).unwrap(), vec![file("a", "a2")]);
+        assert_eq!(db.get_branch_head("main").unwrap(), Some(revert));
+        assert_eq!(work_file("a").as_deref(), Some("a2"));
+        assert_eq!(work_file("b"), None);
+
+        // c1 introduced "a", which has been modified since
+        assert!(service.revert("c1").is_err());
+    }
+}
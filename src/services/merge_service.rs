@@ -0,0 +1,539 @@
+//! Merge service for FAI Protocol
+//!
+//! Provides branch merging on top of snapshot commits:
+//! - Merge base lookup and fast-forward detection
+//! - File-level three-way merges of commit snapshots
+//! - Conflict detection and whole-file resolution (`--ours` / `--theirs`)
+//! - Cherry-picking single commits onto the current branch
+//!
+//! Both check the result out into the working tree, and refuse to run while
+//! there are staged changes or modified tracked files.
+//!
+//! Model files are binary, so a path changed differently on both sides is
+//! never merged textually; one complete version has to be chosen.
+
+use anyhow::Result;
+use chrono::Utc;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use crate::database::{DatabaseManager, EMPTY_COMMIT_HASH};
+use crate::storage::StorageManager;
+
+use super::worktree;
+
+/// File entry as stored in a commit snapshot: (file_path, file_hash, file_size)
+pub type FileEntry = (String, String, u64);
+
+/// How to resolve paths that were changed differently on both sides
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictResolution {
+    /// Stop and report the conflicts without committing
+    Abort,
+    /// Keep the version from the current branch
+    Ours,
+    /// Take the version from the branch being merged
+    Theirs,
+}
+
+/// A path whose content diverged on both sides of a merge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeConflict {
+    /// File path
+    pub path: String,
+    /// File hash in the merge base (None if absent)
+    pub base: Option<String>,
+    /// File hash on the current branch (None if deleted)
+    pub ours: Option<String>,
+    /// File hash on the merged branch (None if deleted)
+    pub theirs: Option<String>,
+}
+
+impl MergeConflict {
+    /// Short description of the kind of conflict for display
+    pub fn describe(&self) -> &'static str {
+        match (&self.base, &self.ours, &self.theirs) {
+            (None, Some(_), Some(_)) => "both added",
+            (Some(_), None, Some(_)) => "deleted by us",
+            (Some(_), Some(_), None) => "deleted by them",
+            _ => "both modified",
+        }
+    }
+}
+
+/// Result of a merge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeOutcome {
+    /// The merged branch is already contained in the current branch
+    UpToDate,
+    /// The current branch was moved forward without a merge commit
+    FastForward {
+        /// Previous head of the current branch (None if it had no commits)
+        from: Option<String>,
+        /// New head of the current branch
+        to: String,
+    },
+    /// A two-parent merge commit was recorded
+    Merged {
+        /// Hash of the merge commit
+        commit: String,
+        /// Conflicts that were resolved with `--ours` / `--theirs`
+        resolved: Vec<MergeConflict>,
+    },
+    /// The merge stopped because of unresolved conflicts
+    Conflicts(Vec<MergeConflict>),
+}
+
+/// Merge three commit snapshots at file level
+///
+/// # Arguments
+/// * `base` - Files of the merge base
+/// * `ours` - Files of the current branch head
+/// * `theirs` - Files of the merged branch head
+/// * `resolution` - Which side wins for conflicting paths
+///
+/// # Returns
+/// The merged file set (ordered by path) and every conflict that was found.
+/// With `ConflictResolution::Abort` conflicting paths are left out of the result.
+pub fn three_way_merge(
+    base: &[FileEntry],
+    ours: &[FileEntry],
+    theirs: &[FileEntry],
+    resolution: ConflictResolution,
+) -> (Vec<FileEntry>, Vec<MergeConflict>) {
+    let index = |files: &[FileEntry]| -> BTreeMap<String, (String, u64)> {
+        files
+            .iter()
+            .map(|(path, hash, size)| (path.clone(), (hash.clone(), *size)))
+            .collect()
+    };
+    let base = index(base);
+    let ours = index(ours);
+    let theirs = index(theirs);
+
+    let paths: BTreeSet<&String> = base.keys().chain(ours.keys()).chain(theirs.keys()).collect();
+
+    let mut merged = Vec::new();
+    let mut conflicts = Vec::new();
+
+    for path in paths {
+        let b = base.get(path);
+        let o = ours.get(path);
+        let t = theirs.get(path);
+        let hash_of = |entry: Option<&(String, u64)>| entry.map(|(hash, _)| hash.clone());
+
+        let chosen = if hash_of(o) == hash_of(t) || hash_of(t) == hash_of(b) {
+            // Same on both sides, or only we changed it
+            o
+        } else if hash_of(o) == hash_of(b) {
+            // Only they changed it
+            t
+        } else {
+            conflicts.push(MergeConflict {
+                path: path.clone(),
+                base: hash_of(b),
+                ours: hash_of(o),
+                theirs: hash_of(t),
+            });
+            match resolution {
+                ConflictResolution::Abort => continue,
+                ConflictResolution::Ours => o,
+                ConflictResolution::Theirs => t,
+            }
+        };
+
+        if let Some((hash, size)) = chosen {
+            merged.push((path.clone(), hash.clone(), *size));
+        }
+    }
+
+    (merged, conflicts)
+}
+
+/// Merge service for combining branches
+pub struct MergeService {
+    pub database: DatabaseManager,
+    storage: StorageManager,
+    work_dir: PathBuf,
+}
+
+impl MergeService {
+    /// Create a new merge service instance
+    ///
+    /// # Arguments
+    /// * `database` - Repository database
+    /// * `storage` - Object storage used to check out merged files
+    /// * `work_dir` - Working tree that file paths are relative to
+    pub fn new(database: DatabaseManager, storage: StorageManager, work_dir: PathBuf) -> Self {
+        Self {
+            database,
+            storage,
+            work_dir,
+        }
+    }
+
+    /// Initialize the merge service from repository path (the `.fai` directory)
+    pub fn from_repo_path(repo_path: &Path) -> Result<Self> {
+        let database = DatabaseManager::new(&repo_path.join("db.sqlite"))?;
+        let storage = StorageManager::new(repo_path.to_path_buf())?;
+        let work_dir = repo_path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from("."));
+        Ok(Self::new(database, storage, work_dir))
+    }
+
+    /// Merge a branch into the current branch
+    ///
+    /// # Arguments
+    /// * `branch` - Name of the branch to merge
+    /// * `resolution` - How to resolve conflicting paths
+    /// * `message` - Merge commit message (defaults to "Merge branch '<branch>' into <current>")
+    pub fn merge_branch(
+        &self,
+        branch: &str,
+        resolution: ConflictResolution,
+        message: Option<&str>,
+    ) -> Result<MergeOutcome> {
+        let current = self.database.get_current_branch()?;
+        if current == branch {
+            return Err(anyhow::anyhow!("Cannot merge branch '{}' into itself", branch));
+        }
+
+        // A local branch, or a remote-tracking branch such as "origin/main"
+        let theirs = match self.database.get_branch_head(branch)? {
+            Some(head) => head,
+            None => match branch.split_once('/') {
+                Some((remote, remote_branch)) => self.database.get_remote_ref(remote, remote_branch)?,
+                None => None,
+            }
+            .ok_or_else(|| anyhow::anyhow!("Branch '{}' does not exist", branch))?,
+        };
+        if theirs == EMPTY_COMMIT_HASH {
+            return Err(anyhow::anyhow!("Branch '{}' has no commits", branch));
+        }
+
+        let ours = self
+            .database
+            .get_branch_head(&current)?
+            .filter(|hash| hash != EMPTY_COMMIT_HASH);
+        let our_files = match &ours {
+            Some(hash) => self.database.get_commit_files(hash)?,
+            None => Vec::new(),
+        };
+        worktree::check_clean(&self.database, &self.storage, &self.work_dir, &our_files, "merging")?;
+
+        let ours = match ours {
+            Some(hash) => hash,
+            None => {
+                // Nothing on this branch yet: just adopt the other branch
+                self.check_out_commit(&our_files, &theirs)?;
+                self.database.update_branch_head_with_reason(
+                    &current,
+                    &theirs,
+                    &format!("merge {}: Fast-forward", branch),
+                )?;
+                return Ok(MergeOutcome::FastForward { from: None, to: theirs });
+            }
+        };
+
+        let base = self.database.find_merge_base(&ours, &theirs)?;
+        if base.as_deref() == Some(theirs.as_str()) {
+            return Ok(MergeOutcome::UpToDate);
+        }
+        if base.as_deref() == Some(ours.as_str()) {
+            self.check_out_commit(&our_files, &theirs)?;
+            self.database.update_branch_head_with_reason(
+                &current,
+                &theirs,
+                &format!("merge {}: Fast-forward", branch),
+            )?;
+            return Ok(MergeOutcome::FastForward {
+                from: Some(ours),
+                to: theirs,
+            });
+        }
+
+        let base_files = match &base {
+            Some(hash) => self.database.get_commit_files(hash)?,
+            None => Vec::new(),
+        };
+        let their_files = self.database.get_commit_files(&theirs)?;
+
+        let (merged, conflicts) = three_way_merge(&base_files, &our_files, &their_files, resolution);
+        if !conflicts.is_empty() && resolution == ConflictResolution::Abort {
+            return Ok(MergeOutcome::Conflicts(conflicts));
+        }
+
+        let message = message
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("Merge branch '{}' into {}", branch, current));
+        let parents = vec![ours, theirs];
+        let commit_hash = Self::merge_commit_hash(&message, &parents, &merged);
+
+        worktree::check_out(&self.storage, &self.work_dir, &our_files, &merged)?;
+        self.database
+            .create_commit(&commit_hash, &message, &parents, &merged, true)?;
+        self.database.update_branch_head_with_reason(
+            &current,
+            &commit_hash,
+            &format!("merge {}: Merge made", branch),
+        )?;
+
+        Ok(MergeOutcome::Merged {
+            commit: commit_hash,
+            resolved: conflicts,
+        })
+    }
+
+    /// Apply the changes of a single commit onto the current branch
+    ///
+    /// The commit's parent serves as merge base, so only the files the commit
+    /// added, modified or removed are carried over. Conflicts are detected and
+    /// resolved exactly like in [`MergeService::merge_branch`].
+    ///
+    /// # Arguments
+    /// * `rev` - Revision of the commit to pick
+    /// * `resolution` - How to resolve conflicting paths
+    ///
+    /// # Returns
+    /// `Merged` with the new commit, `Conflicts`, or `UpToDate` if the
+    /// changes are already present on the current branch
+    pub fn cherry_pick(&self, rev: &str, resolution: ConflictResolution) -> Result<MergeOutcome> {
+        let current = self.database.get_current_branch()?;
+        let picked = self.database.resolve_revision(rev)?;
+        let commit = self
+            .database
+            .get_commit(&picked)?
+            .ok_or_else(|| anyhow::anyhow!("Commit not found: {}", picked))?;
+
+        if commit.is_merge || commit.parents.len() > 1 {
+            return Err(anyhow::anyhow!(
+                "Commit {} is a merge commit; cherry-picking merges is not supported",
+                &picked[..8.min(picked.len())]
+            ));
+        }
+
+        let ours = self
+            .database
+            .get_branch_head(&current)?
+            .filter(|hash| hash != EMPTY_COMMIT_HASH)
+            .ok_or_else(|| anyhow::anyhow!("Branch '{}' has no commits", current))?;
+        let our_files = self.database.get_commit_files(&ours)?;
+        worktree::check_clean(&self.database, &self.storage, &self.work_dir, &our_files, "cherry-picking")?;
+
+        let base_files = match commit.parents.first() {
+            Some(parent) => self.database.get_commit_files(parent)?,
+            None => Vec::new(),
+        };
+        let their_files = self.database.get_commit_files(&picked)?;
+
+        let (merged, conflicts) = three_way_merge(&base_files, &our_files, &their_files, resolution);
+        if !conflicts.is_empty() && resolution == ConflictResolution::Abort {
+            return Ok(MergeOutcome::Conflicts(conflicts));
+        }
+        if merged == our_files {
+            return Ok(MergeOutcome::UpToDate);
+        }
+
+        let message = format!(
+            "{}\n\n(cherry picked from commit {})",
+            commit.message, picked
+        );
+        let parents = vec![ours];
+        let commit_hash = Self::merge_commit_hash(&message, &parents, &merged);
+
+        worktree::check_out(&self.storage, &self.work_dir, &our_files, &merged)?;
+        self.database
+            .create_commit(&commit_hash, &message, &parents, &merged, false)?;
+        self.database.update_branch_head_with_reason(
+            &current,
+            &commit_hash,
+            &format!("cherry-pick: {}", commit.message),
+        )?;
+
+        Ok(MergeOutcome::Merged {
+            commit: commit_hash,
+            resolved: conflicts,
+        })
+    }
+
+    /// Check out the snapshot of a commit in place of the current head's files
+    fn check_out_commit(&self, head_files: &[FileEntry], commit: &str) -> Result<()> {
+        let files = self.database.get_commit_files(commit)?;
+        worktree::check_out(&self.storage, &self.work_dir, head_files, &files)?;
+        Ok(())
+    }
+
+    /// Calculate hash for a merge or cherry-picked commit
+    fn merge_commit_hash(message: &str, parents: &[String], files: &[FileEntry]) -> String {
+        let commit_data = format!(
+            "{}{}{:?}{:?}",
+            Utc::now().timestamp_millis(),
+            message,
+            parents,
+            files
+        );
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(commit_data.as_bytes());
+        hasher.finalize().to_hex().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn file(path: &str, hash: &str) -> FileEntry {
+        (path.to_string(), hash.to_string(), 1)
+    }
+
+    fn create_test_merge_service() -> (MergeService, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let fai_path = temp_dir.path().join(".fai");
+        std::fs::create_dir_all(&fai_path).unwrap();
+        let service = MergeService::from_repo_path(&fai_path).unwrap();
+        (service, temp_dir)
+    }
+
+    /// Store `content` and return its file entry
+    fn stored(service: &MergeService, path: &str, content: &str) -> FileEntry {
+        let hash = service.storage.store(content.as_bytes()).unwrap();
+        (path.to_string(), hash, content.len() as u64)
+    }
+
+    #[test]
+    fn test_three_way_merge_takes_one_sided_changes() {
+        let base = vec![file("a", "a1"), file("b", "b1"), file("c", "c1")];
+        let ours = vec![file("a", "a2"), file("b", "b1"), file("c", "c1")];
+        let theirs = vec![file("a", "a1"), file("c", "c1"), file("d", "d1")];
+
+        let (merged, conflicts) = three_way_merge(&base, &ours, &theirs, ConflictResolution::Abort);
+
+        assert!(conflicts.is_empty());
+        assert_eq!(merged, vec![file("a", "a2"), file("c", "c1"), file("d", "d1")]);
+    }
+
+    #[test]
+    fn test_three_way_merge_conflicts() {
+        let base = vec![file("model", "m1"), file("cfg", "c1")];
+        let ours = vec![file("model", "m2")];
+        let theirs = vec![file("model", "m3"), file("cfg", "c2")];
+
+        let (merged, conflicts) = three_way_merge(&base, &ours, &theirs, ConflictResolution::Abort);
+        assert!(merged.is_empty());
+        assert_eq!(conflicts.len(), 2);
+        assert_eq!(conflicts[0].path, "cfg");
+        assert_eq!(conflicts[0].describe(), "deleted by us");
+        assert_eq!(conflicts[1].describe(), "both modified");
+
+        let (merged, _) = three_way_merge(&base, &ours, &theirs, ConflictResolution::Theirs);
+        assert_eq!(merged, vec![file("cfg", "c2"), file("model", "m3")]);
+
+        let (merged, _) = three_way_merge(&base, &ours, &theirs, ConflictResolution::Ours);
+        assert_eq!(merged, vec![file("model", "m2")]);
+    }
+
+    #[test]
+    fn test_merge_branch_fast_forward_and_merge_commit() {
+        let (service, temp_dir) = create_test_merge_service();
+        let db = &service.database;
+        let file = |path: &str, content: &str| stored(&service, path, content);
+        let work_file = |path: &str| std::fs::read_to_string(temp_dir.path().join(path)).ok();
+
+        db.create_commit("root", "Root", &[], &[file("a", "a1")], false).unwrap();
+        db.update_branch_head("main", "root").unwrap();
+        db.create_branch("feature", "root").unwrap();
+
+        // Feature moves ahead: main can fast-forward
+        db.create_commit("f1", "Feature", &["root".to_string()], &[file("a", "a1"), file("b", "b1")], false)
+            .unwrap();
+        db.update_branch_head("feature", "f1").unwrap();
+        let outcome = service.merge_branch("feature", ConflictResolution::Abort, None).unwrap();
+        assert_eq!(
+            outcome,
+            MergeOutcome::FastForward { from: Some("root".to_string()), to: "f1".to_string() }
+        );
+        assert_eq!(work_file("b").as_deref(), Some("b1"), "a fast-forward checks out the new head");
+        assert_eq!(service.merge_branch("feature", ConflictResolution::Abort, None).unwrap(), MergeOutcome::UpToDate);
+
+        // Diverge: main changes a, feature adds c
+        db.create_commit("m2", "Main", &["f1".to_string()], &[file("a", "a2"), file("b", "b1")], false)
+            .unwrap();
+        db.update_branch_head("main", "m2").unwrap();
+        std::fs::write(temp_dir.path().join("a"), "a2").unwrap();
+        db.create_commit("f2", "Feature 2", &["f1".to_string()], &[file("a", "a1"), file("b", "b1"), file("c", "c1")], false)
+            .unwrap();
+        db.update_branch_head("feature", "f2").unwrap();
+
+        let commit = match service.merge_branch("feature", ConflictResolution::Abort, None).unwrap() {
+            MergeOutcome::Merged { commit, resolved } => {
+                assert!(resolved.is_empty());
+                commit
+            }
+            other => panic!("Expected merge commit, got {:?}", other),
+        };
+
+        let merge_commit = db.get_commit(&commit).unwrap().unwrap();
+        assert!(merge_commit.is_merge);
+        assert_eq!(merge_commit.parents.len(), 2);
+        assert_eq!(
+            db.get_commit_files(&commit).unwrap(),
+            vec![file("a", "a2"), file("b", "b1"), file("c", "c1")]
+        );
+        assert_eq!(db.get_branch_head("main").unwrap(), Some(commit.clone()));
+        assert_eq!(work_file("a").as_deref(), Some("a2"));
+        assert_eq!(work_file("c").as_deref(), Some("c1"));
+
+        // Local edits of tracked files block merging
+        std::fs::write(temp_dir.path().join("a"), "local edit").unwrap();
+        let error = service.merge_branch("feature", ConflictResolution::Abort, None).unwrap_err();
+        assert!(error.to_string().contains("uncommitted changes to a"), "{}", error);
+        assert_eq!(db.get_branch_head("main").unwrap(), Some(commit));
+    }
+
+    #[test]
+    fn test_cherry_pick_applies_single_commit() {
+        let (service, temp_dir) = create_test_merge_service();
+        let db = &service.database;
+        let file = |path: &str, content: &str| stored(&service, path, content);
+
+        db.create_commit("root", "Root", &[], &[file("a", "a1"), file("b", "b1")], false).unwrap();
+        db.update_branch_head("main", "root").unwrap();
+        db.create_branch("experiment", "root").unwrap();
+
+        // Two experiment commits; only the second one should move over
+        db.create_commit("e1", "Tweak a", &["root".to_string()], &[file("a", "a2"), file("b", "b1")], false)
+            .unwrap();
+        db.create_commit("e2", "Drop b, add c", &["e1".to_string()], &[file("a", "a2"), file("c", "c1")], false)
+            .unwrap();
+        db.update_branch_head("experiment", "e2").unwrap();
+
+        let commit = match service.cherry_pick("e2", ConflictResolution::Abort).unwrap() {
+            MergeOutcome::Merged { commit, resolved } => {
+                assert!(resolved.is_empty());
+                commit
+            }
+            other => panic!("Expected cherry-picked commit, got {:?}", other),
+        };
+
+        let picked = db.get_commit(&commit).unwrap().unwrap();
+        assert!(!picked.is_merge);
+        assert_eq!(picked.parents, vec!["root".to_string()]);
+        assert!(picked.message.starts_with("Drop b, add c"));
+        assert_eq!(db.get_commit_files(&commit).unwrap(), vec![file("a", "a1"), file("c", "c1")]);
+        assert_eq!(std::fs::read_to_string(temp_dir.path().join("c")).unwrap(), "c1");
+        assert_eq!(service.cherry_pick("e2", ConflictResolution::Abort).unwrap(), MergeOutcome::UpToDate);
+
+        // e1 modifies "a", which main has since modified differently
+        db.create_commit("m2", "Main a", std::slice::from_ref(&commit), &[file("a", "a3"), file("c", "c1")], false)
+            .unwrap();
+        db.update_branch_head("main", "m2").unwrap();
+        std::fs::write(temp_dir.path().join("a"), "a3").unwrap();
+        match service.cherry_pick("e1", ConflictResolution::Abort).unwrap() {
+            MergeOutcome::Conflicts(conflicts) => assert_eq!(conflicts[0].describe(), "both modified"),
+            other => panic!("Expected conflicts, got {:?}", other),
+        }
+    }
+}
@@ -2,17 +2,30 @@
 //!
 //! This module provides various services that handle different aspects of the application:
 //! - Branch management
+//! - Branch merging
 //! - CLI operations
+//! - Garbage collection
+//! - History rewriting (reset and revert)
 //! - Security and authentication
+//! - Stashing uncommitted changes
 //! - Web interface
 
 pub mod branch_service;
 pub mod cli_service;
+pub mod gc_service;
+pub mod history_service;
+pub mod merge_service;
 pub mod security_service;
+pub mod stash_service;
 pub mod web_service;
+mod worktree;
 
 // Re-export commonly used items
-pub use branch_service::{BranchService, BranchInfo};
+pub use branch_service::{BranchService, BranchInfo, BranchDivergence, TrackingStatus};
 pub use cli_service::CliService;
+pub use gc_service::{GcService, GcSummary};
+pub use history_service::{HistoryService, ResetMode, ResetSummary};
+pub use merge_service::{ConflictResolution, MergeConflict, MergeOutcome, MergeService};
 pub use security_service::{SecurityService, SecurityConfig, UserConfig, UserKeyPair};
+pub use stash_service::StashService;
 pub use web_service::{WebService, WebServiceConfig};
\ No newline at end of file
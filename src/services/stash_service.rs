@@ -0,0 +1,266 @@
+//! Stash service for FAI Protocol
+//!
+//! Saves staged changes and modified tracked files so the working tree can
+//! be switched to another branch, and restores them later:
+//! - `push` stores the changes as a stash entry and resets to the branch head
+//! - `pop` restores a stash entry and drops it
+//! - `list` / `drop` manage saved entries
+//!
+//! File contents are kept in object storage; a stash entry only references
+//! them by hash.
+
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+use crate::database::{DatabaseManager, StashEntry, StashFile, EMPTY_COMMIT_HASH};
+use crate::storage::StorageManager;
+
+use super::merge_service::FileEntry;
+use super::worktree;
+
+/// Stash service for saving and restoring uncommitted changes
+pub struct StashService {
+    pub database: DatabaseManager,
+    storage: StorageManager,
+    work_dir: PathBuf,
+}
+
+impl StashService {
+    /// Create a new stash service instance
+    ///
+    /// # Arguments
+    /// * `database` - Repository database
+    /// * `storage` - Object storage holding stashed file contents
+    /// * `work_dir` - Working tree that file paths are relative to
+    pub fn new(database: DatabaseManager, storage: StorageManager, work_dir: PathBuf) -> Self {
+        Self {
+            database,
+            storage,
+            work_dir,
+        }
+    }
+
+    /// Initialize the stash service from repository path (the `.fai` directory)
+    pub fn from_repo_path(repo_path: &Path) -> Result<Self> {
+        let database = DatabaseManager::new(&repo_path.join("db.sqlite"))?;
+        let storage = StorageManager::new(repo_path.to_path_buf())?;
+        let work_dir = repo_path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from("."));
+        Ok(Self::new(database, storage, work_dir))
+    }
+
+    /// Save staged changes and modified tracked files, then reset them
+    ///
+    /// Staging is cleared and modified files are restored to their committed
+    /// version.
+    ///
+    /// # Arguments
+    /// * `message` - Description (defaults to "WIP on <branch>: <head>")
+    ///
+    /// # Returns
+    /// The new stash entry
+    pub fn push(&self, message: Option<&str>) -> Result<StashEntry> {
+        let branch = self.database.get_current_branch()?;
+        let head = self.branch_head(&branch)?;
+        let tracked = self.tracked_files(head.as_deref())?;
+
+        let mut files: Vec<StashFile> = self
+            .database
+            .get_staged_files()?
+            .into_iter()
+            .map(|(path, hash, size)| StashFile { path, hash, size, staged: true })
+            .collect();
+
+        let modified = worktree::modified_files(&self.storage, &self.work_dir, &tracked)?;
+        for path in &modified {
+            let content = std::fs::read(self.work_dir.join(path))?;
+            let hash = self.storage.store(&content)?;
+            files.push(StashFile {
+                path: path.clone(),
+                hash,
+                size: content.len() as u64,
+                staged: false,
+            });
+        }
+
+        if files.is_empty() {
+            return Err(anyhow::anyhow!("No local changes to save"));
+        }
+
+        let message = match message {
+            Some(message) => message.to_string(),
+            None => format!(
+                "WIP on {}: {}",
+                branch,
+                head.as_deref().map(|h| &h[..8.min(h.len())]).unwrap_or("(no commits)")
+            ),
+        };
+        let entry = self
+            .database
+            .create_stash(&branch, head.as_deref(), &message, &files)?;
+
+        // Back to a clean state: nothing staged, tracked files as committed
+        self.database.clear_staging()?;
+        for (path, hash, _) in tracked.iter().filter(|(path, _, _)| modified.contains(path)) {
+            std::fs::write(self.work_dir.join(path), self.storage.retrieve(hash)?)?;
+        }
+
+        Ok(entry)
+    }
+
+    /// Restore a stash entry and drop it
+    ///
+    /// # Arguments
+    /// * `index` - Stash index (0 is the most recent)
+    ///
+    /// # Returns
+    /// The restored entry and its files
+    pub fn pop(&self, index: usize) -> Result<(StashEntry, Vec<StashFile>)> {
+        let entry = self.get(index)?;
+        let files = self.database.get_stash_files(entry.id)?;
+
+        if !self.database.get_staged_files()?.is_empty() {
+            return Err(anyhow::anyhow!(
+                "You have staged changes. Commit or stash them before popping."
+            ));
+        }
+
+        // Never overwrite local edits of the files we are about to restore
+        let branch = self.database.get_current_branch()?;
+        let tracked = self.tracked_files(self.branch_head(&branch)?.as_deref())?;
+        let modified = worktree::modified_files(&self.storage, &self.work_dir, &tracked)?;
+        let blocked: Vec<&str> = files
+            .iter()
+            .filter(|file| !file.staged && modified.contains(&file.path))
+            .map(|file| file.path.as_str())
+            .collect();
+        if !blocked.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Local changes would be overwritten by the stash: {}",
+                blocked.join(", ")
+            ));
+        }
+
+        // Load everything first so a missing object leaves nothing half-applied
+        let mut contents = Vec::new();
+        for file in files.iter().filter(|file| !file.staged) {
+            contents.push((file.path.clone(), self.storage.retrieve(&file.hash)?));
+        }
+
+        for file in files.iter().filter(|file| file.staged) {
+            self.database.add_to_staging(&file.path, &file.hash, file.size)?;
+        }
+        for (path, data) in contents {
+            let full_path = self.work_dir.join(&path);
+            if let Some(parent) = full_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(full_path, data)?;
+        }
+
+        self.database.delete_stash(entry.id)?;
+        Ok((entry, files))
+    }
+
+    /// List stash entries, newest first
+    pub fn list(&self) -> Result<Vec<StashEntry>> {
+        self.database.list_stashes()
+    }
+
+    /// Drop a stash entry without restoring it
+    ///
+    /// # Arguments
+    /// * `index` - Stash index (0 is the most recent)
+    pub fn drop(&self, index: usize) -> Result<StashEntry> {
+        let entry = self.get(index)?;
+        self.database.delete_stash(entry.id)?;
+        Ok(entry)
+    }
+
+    /// Look up `stash@{index}`
+    fn get(&self, index: usize) -> Result<StashEntry> {
+        self.database
+            .list_stashes()?
+            .into_iter()
+            .nth(index)
+            .ok_or_else(|| anyhow::anyhow!("stash@{{{}}} does not exist", index))
+    }
+
+    /// Head commit of a branch, None if it has no commits
+    fn branch_head(&self, branch: &str) -> Result<Option<String>> {
+        Ok(self
+            .database
+            .get_branch_head(branch)?
+            .filter(|hash| hash != EMPTY_COMMIT_HASH))
+    }
+
+    /// Files of the head snapshot
+    fn tracked_files(&self, head: Option<&str>) -> Result<Vec<FileEntry>> {
+        match head {
+            Some(head) => self.database.get_commit_files(head),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn create_test_stash_service() -> (StashService, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let fai_path = temp_dir.path().join(".fai");
+        std::fs::create_dir_all(&fai_path).unwrap();
+        let service = StashService::from_repo_path(&fai_path).unwrap();
+        (service, temp_dir)
+    }
+
+    #[test]
+    fn test_stash_push_and_pop() {
+        let (service, temp_dir) = create_test_stash_service();
+        let db = &service.database;
+        let model_path = temp_dir.path().join("model.bin");
+
+        let committed = service.storage.store(b"committed").unwrap();
+        db.create_commit("c1", "First", &[], &[("model.bin".to_string(), committed, 9)], false)
+            .unwrap();
+        db.update_branch_head("main", "c1").unwrap();
+
+        // One staged checkpoint and one local edit of a tracked file
+        let staged = service.storage.store(b"checkpoint").unwrap();
+        db.add_to_staging("checkpoint.bin", &staged, 10).unwrap();
+        std::fs::write(&model_path, b"edited weights").unwrap();
+
+        let entry = service.push(Some("experiment")).unwrap();
+        assert_eq!(entry.message, "experiment");
+        assert!(db.get_staged_files().unwrap().is_empty());
+        assert_eq!(std::fs::read(&model_path).unwrap(), b"committed");
+        assert_eq!(service.list().unwrap().len(), 1);
+
+        let (_, files) = service.pop(0).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(db.get_staged_files().unwrap(), vec![("checkpoint.bin".to_string(), staged, 10)]);
+        assert_eq!(std::fs::read(&model_path).unwrap(), b"edited weights");
+        assert!(service.list().unwrap().is_empty());
+
+        assert!(service.pop(0).is_err());
+    }
+
+    #[test]
+    fn test_stash_drop_and_empty_push() {
+        let (service, _temp_dir) = create_test_stash_service();
+        assert!(service.push(None).is_err());
+
+        let hash = service.storage.store(b"a").unwrap();
+        service.database.add_to_staging("a.bin", &hash, 1).unwrap();
+        let entry = service.push(None).unwrap();
+        assert_eq!(entry.message, "WIP on main: (no commits)");
+
+        service.drop(0).unwrap();
+        assert!(service.list().unwrap().is_empty());
+        assert!(service.drop(0).is_err());
+    }
+}
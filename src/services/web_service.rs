@@ -12,10 +12,8 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 
 // Import required HTTP/axum types
-use axum::response::Html;
 use axum::Json;
 use tower_http::services::ServeDir;
-use axum::serve;
 use axum::http::StatusCode;
 
 /// Web service configuration
@@ -94,6 +92,7 @@ impl WebService {
             // API routes
             .route("/api/status", axum::routing::get(status_handler))
             .route("/api/branches", axum::routing::get(branches_handler))
+            .route("/api/tags", axum::routing::get(tags_handler))
             .route("/api/commits", axum::routing::get(commits_handler))
             .route("/api/files", axum::routing::get(files_handler))
             .route("/api/log", axum::routing::get(log_handler))
@@ -108,6 +107,7 @@ impl WebService {
             // Web interface
             .route("/", axum::routing::get(index_handler))
             .route("/branches", axum::routing::get(branches_page_handler))
+            .route("/tags", axum::routing::get(tags_page_handler))
             .route("/commits", axum::routing::get(commits_page_handler))
             .route("/files", axum::routing::get(files_page_handler))
 
@@ -183,6 +183,9 @@ async fn branches_handler(
             "is_current": branch.is_current,
             "is_empty": branch.is_empty,
             "short_hash": branch.short_hash(),
+            "ahead": branch.divergence.as_ref().map(|d| d.ahead),
+            "behind": branch.divergence.as_ref().map(|d| d.behind),
+            "merge_base": branch.divergence.as_ref().and_then(|d| d.merge_base.clone()),
         })
     }).collect();
 
@@ -192,6 +195,33 @@ async fn branches_handler(
     })))
 }
 
+async fn tags_handler(
+    axum::extract::State(state): axum::extract::State<Arc<RwLock<WebState>>>,
+) -> Result<axum::Json<serde_json::Value>, StatusCode> {
+    let state = state.read().await;
+
+    let database = crate::database::DatabaseManager::new(&state.repo_path().join(".fai/db.sqlite"))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let tags = database.list_tags().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let tags_json: Vec<_> = tags.into_iter().map(|tag| {
+        serde_json::json!({
+            "name": tag.name,
+            "target": tag.target,
+            "short_hash": &tag.target[..8.min(tag.target.len())],
+            "annotated": tag.is_annotated(),
+            "message": tag.message,
+            "created_at": tag.created_at,
+        })
+    }).collect();
+
+    Ok(axum::Json(serde_json::json!({
+        "status": "ok",
+        "tags": tags_json
+    })))
+}
+
 async fn commits_handler(
     axum::extract::State(state): axum::extract::State<Arc<RwLock<WebState>>>,
 ) -> Result<axum::Json<serde_json::Value>, StatusCode> {
@@ -277,6 +307,7 @@ async fn index_handler() -> axum::response::Html<String> {
     <div class="nav">
         <a href="/">Status</a>
         <a href="/branches">Branches</a>
+        <a href="/tags">Tags</a>
         <a href="/commits">Commits</a>
         <a href="/files">Files</a>
     </div>
@@ -325,6 +356,39 @@ async fn branches_page_handler() -> axum::response::Html<String> {
     axum::response::Html(html.to_string())
 }
 
+async fn tags_page_handler() -> axum::response::Html<String> {
+    let html = r#"
+<!DOCTYPE html>
+<html><head><title>Tags</title><meta charset="utf-8"></head>
+<body>
+    <h1>Tags</h1>
+    <ul id="tags"><li>Loading tags...</li></ul>
+    <script>
+        fetch('/api/tags')
+            .then(response => response.json())
+            .then(data => {
+                const list = document.getElementById('tags');
+                if (data.tags.length === 0) {
+                    list.innerHTML = '<li>No tags</li>';
+                    return;
+                }
+                // Tags can come from peers, so never parse them as HTML
+                list.replaceChildren(...data.tags.map(tag => {
+                    const item = document.createElement('li');
+                    const name = document.createElement('strong');
+                    name.textContent = tag.name;
+                    const hash = document.createElement('code');
+                    hash.textContent = tag.short_hash;
+                    item.append(name, ' ', hash, ' ', tag.message || '');
+                    return item;
+                }));
+            });
+    </script>
+</body></html>
+    "#;
+    axum::response::Html(html.to_string())
+}
+
 async fn commits_page_handler() -> axum::response::Html<String> {
     let html = r#"
 <!DOCTYPE html>
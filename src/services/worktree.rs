@@ -0,0 +1,182 @@
+//! Working tree updates shared by the services that move branch heads
+//!
+//! `reset --hard`, reverts, merges and cherry-picks all end by making the working tree
+//! match a commit snapshot; they do it through [`check_out`].
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::database::{check_file_path, DatabaseManager};
+use crate::storage::StorageManager;
+
+use super::merge_service::FileEntry;
+
+/// Make the working tree match the snapshot `to`
+///
+/// Files of `to` that are new, changed or missing from disk are copied out
+/// of storage one at a time, and files of `from` that `to` no longer has
+/// are removed. Paths, untracked files in the way and the presence of every
+/// object are checked before anything is written, so a failed check leaves
+/// the working tree unchanged.
+///
+/// # Arguments
+/// * `from` - Files currently tracked in the working tree
+/// * `to` - Snapshot to check out
+///
+/// # Returns
+/// Number of files written and number of files removed
+pub(crate) fn check_out(
+    storage: &StorageManager,
+    work_dir: &Path,
+    from: &[FileEntry],
+    to: &[FileEntry],
+) -> Result<(usize, usize)> {
+    for (path, _, _) in to.iter().chain(from) {
+        check_file_path(path)?;
+    }
+    let tracked: HashMap<&str, &str> = from
+        .iter()
+        .map(|(path, hash, _)| (path.as_str(), hash.as_str()))
+        .collect();
+    let changed: Vec<&FileEntry> = to
+        .iter()
+        .filter(|(path, hash, _)| {
+            tracked.get(path.as_str()) != Some(&hash.as_str()) || !work_dir.join(path).exists()
+        })
+        .collect();
+
+    let untracked: Vec<&str> = changed
+        .iter()
+        .filter(|(path, _, _)| !tracked.contains_key(path.as_str()) && work_dir.join(path).exists())
+        .map(|(path, _, _)| path.as_str())
+        .collect();
+    if !untracked.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Untracked files would be overwritten: {}. Move or remove them first.",
+            untracked.join(", ")
+        ));
+    }
+    for (_, hash, _) in &changed {
+        if !storage.exists(hash) || !storage.missing_chunks(hash)?.is_empty() {
+            return Err(anyhow::anyhow!("Object not found: {}", hash));
+        }
+    }
+
+    for (path, hash, _) in &changed {
+        let full_path = work_dir.join(path);
+        if let Some(parent) = full_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = BufWriter::new(File::create(&full_path)?);
+        storage.retrieve_to(hash, &mut file)?;
+        file.flush()?;
+    }
+
+    let mut removed = 0;
+    for (path, _, _) in from {
+        let still_tracked = to.iter().any(|(p, _, _)| p == path);
+        let full_path = work_dir.join(path);
+        if !still_tracked && full_path.exists() {
+            std::fs::remove_file(&full_path)?;
+            removed += 1;
+        }
+    }
+
+    Ok((changed.len(), removed))
+}
+
+/// Refuse to continue while staging or tracked files hold uncommitted changes
+///
+/// # Arguments
+/// * `head_files` - Files of the current branch head
+/// * `action` - What is being refused, for the error message
+pub(crate) fn check_clean(
+    database: &DatabaseManager,
+    storage: &StorageManager,
+    work_dir: &Path,
+    head_files: &[FileEntry],
+    action: &str,
+) -> Result<()> {
+    if !database.get_staged_files()?.is_empty() {
+        return Err(anyhow::anyhow!(
+            "You have staged changes. Commit them before {}.",
+            action
+        ));
+    }
+    let modified = modified_files(storage, work_dir, head_files)?;
+    if !modified.is_empty() {
+        return Err(anyhow::anyhow!(
+            "You have uncommitted changes to {}. Commit or stash them before {}.",
+            modified.join(", "),
+            action
+        ));
+    }
+    Ok(())
+}
+
+/// Tracked files whose working tree content differs from the snapshot
+///
+/// Files missing from the working tree are not considered modified.
+pub(crate) fn modified_files(storage: &StorageManager, work_dir: &Path, files: &[FileEntry]) -> Result<Vec<String>> {
+    let mut modified = Vec::new();
+    for (path, hash, size) in files {
+        check_file_path(path)?;
+        let full_path = work_dir.join(path);
+        let metadata = match std::fs::metadata(&full_path) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        if metadata.len() != *size || std::fs::read(&full_path)? != storage.retrieve(hash)? {
+            modified.push(path.clone());
+        }
+    }
+    Ok(modified)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_check_out_writes_only_changed_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = StorageManager::new(temp_dir.path().join(".fai")).unwrap();
+        let work_dir = temp_dir.path();
+        let file = |path: &str, content: &str| -> FileEntry {
+            let hash = storage.store(content.as_bytes()).unwrap();
+            (path.to_string(), hash, content.len() as u64)
+        };
+        let work_file = |path: &str| std::fs::read_to_string(work_dir.join(path)).ok();
+
+        let from = vec![file("a", "a1"), file("b", "b1")];
+        let to = vec![file("a", "a1"), file("b", "b2"), file("c", "c1")];
+        // Unchanged files are left alone, so this marker survives the checkout
+        std::fs::write(work_dir.join("a"), "kept").unwrap();
+        std::fs::write(work_dir.join("b"), "b1").unwrap();
+
+        // An untracked file in the way blocks the checkout
+        std::fs::write(work_dir.join("c"), "local").unwrap();
+        let err = check_out(&storage, work_dir, &from, &to).unwrap_err().to_string();
+        assert!(err.contains("Untracked files would be overwritten: c"), "{}", err);
+        assert_eq!(work_file("b").as_deref(), Some("b1"));
+        std::fs::remove_file(work_dir.join("c")).unwrap();
+
+        // So does a missing object, before anything is written
+        let mut missing = to.clone();
+        missing.push(("d".to_string(), "0".repeat(64), 1));
+        assert!(check_out(&storage, work_dir, &from, &missing).is_err());
+        assert_eq!(work_file("b").as_deref(), Some("b1"));
+
+        assert_eq!(check_out(&storage, work_dir, &from, &to).unwrap(), (2, 0));
+        assert_eq!(work_file("a").as_deref(), Some("kept"));
+        assert_eq!(work_file("b").as_deref(), Some("b2"));
+        assert_eq!(work_file("c").as_deref(), Some("c1"));
+
+        assert_eq!(check_out(&storage, work_dir, &to, &from).unwrap(), (1, 1));
+        assert_eq!(work_file("c"), None);
+    }
+}
@@ -9,6 +9,7 @@ use chrono::DateTime;
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::io::Write;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
@@ -139,7 +140,7 @@ impl StorageManager {
 
         // Check if file needs to be chunked
         if data.len() > CHUNK_SIZE {
-            let total_chunks = (data.len() + CHUNK_SIZE - 1) / CHUNK_SIZE;
+            let total_chunks = data.len().div_ceil(CHUNK_SIZE);
             println!(
                 "SPLITTING: Large file detected ({} bytes > {} bytes)",
                 data.len(),
@@ -203,6 +204,26 @@ impl StorageManager {
         }
     }
 
+    /// Compute the hash `data` would be stored under, without storing it
+    ///
+    /// Matches [`Self::store`]: the BLAKE3 hash of small files and the hash
+    /// of the manifest for files that are split into chunks.
+    pub fn content_hash(data: &[u8]) -> Result<String> {
+        if data.len() <= CHUNK_SIZE {
+            return Ok(blake3::hash(data).to_hex().to_string());
+        }
+        let manifest = FileManifest {
+            total_size: data.len() as u64,
+            chunks: data
+                .chunks(CHUNK_SIZE)
+                .map(|chunk| blake3::hash(chunk).to_hex().to_string())
+                .collect(),
+            filename: None,
+        };
+        let manifest_json = serde_json::to_string_pretty(&manifest)?;
+        Ok(blake3::hash(manifest_json.as_bytes()).to_hex().to_string())
+    }
+
     /// Retrieve data by its content hash
     ///
     /// # Arguments
@@ -251,6 +272,37 @@ impl StorageManager {
         }
     }
 
+    /// Write the data stored under `hash` to `writer`
+    ///
+    /// Unlike [`Self::retrieve`], multi-chunk files are copied one chunk at
+    /// a time instead of being reassembled in memory.
+    ///
+    /// # Arguments
+    /// * `hash` - The BLAKE3 hash of the data to write
+    /// * `writer` - Destination of the data
+    ///
+    /// # Returns
+    /// Number of bytes written
+    pub fn retrieve_to(&self, hash: &str, writer: &mut impl Write) -> Result<u64> {
+        let data = self.read_object(hash)?;
+        let manifest = match std::str::from_utf8(&data) {
+            Ok(text) if text.trim_start().starts_with('{') => Some(serde_json::from_str::<FileManifest>(text)?),
+            _ => None,
+        };
+
+        let Some(manifest) = manifest else {
+            writer.write_all(&data)?;
+            return Ok(data.len() as u64);
+        };
+        let mut written = 0;
+        for chunk_hash in &manifest.chunks {
+            let chunk = self.retrieve_single_chunk(chunk_hash)?;
+            writer.write_all(&chunk)?;
+            written += chunk.len() as u64;
+        }
+        Ok(written)
+    }
+
     /// Reconstruct file data from manifest
     ///
     /// # Arguments
@@ -357,6 +409,113 @@ impl StorageManager {
         object_path.exists()
     }
 
+    /// Read the manifest stored under `hash`, if that object is one
+    ///
+    /// # Arguments
+    /// * `hash` - Object hash (typically a committed file hash)
+    ///
+    /// # Returns
+    /// The manifest for multi-chunk files, None for single-object files
+    pub fn read_manifest(&self, hash: &str) -> Result<Option<FileManifest>> {
+        if hash.len() < 2 {
+            return Err(anyhow!("Invalid hash length"));
+        }
+
+        let object_path = self.root_path.join("objects").join(&hash[..2]).join(&hash[2..]);
+        let data = fs::read(&object_path).map_err(|e| anyhow!("Object not found: {} - {}", hash, e))?;
+
+        Ok(std::str::from_utf8(&data)
+            .ok()
+            .filter(|text| text.trim_start().starts_with('{'))
+            .and_then(|text| serde_json::from_str(text).ok()))
+    }
+
+    /// Read a stored object as-is, without reassembling manifests
+    ///
+    /// # Arguments
+    /// * `hash` - Object hash (a single-object file, manifest or chunk)
+    pub fn read_object(&self, hash: &str) -> Result<Vec<u8>> {
+        self.retrieve_single_chunk(hash)
+    }
+
+    /// Store an object received from elsewhere under its expected hash
+    ///
+    /// # Arguments
+    /// * `hash` - Hash the object is expected to have
+    /// * `data` - Raw object data (a single-object file, manifest or chunk)
+    ///
+    /// # Returns
+    /// An error if the data does not hash to `hash`
+    pub fn store_object(&self, hash: &str, data: &[u8]) -> Result<()> {
+        let actual = blake3::hash(data).to_hex().to_string();
+        if actual != hash {
+            return Err(anyhow!("Object {} failed verification (got {})", hash, actual));
+        }
+        self.store_single_object(data)?;
+        Ok(())
+    }
+
+    /// Chunks of a multi-chunk file that are not stored locally
+    ///
+    /// # Returns
+    /// Missing chunk hashes in file order; empty for single-object files
+    /// and for files whose manifest is not stored
+    pub fn missing_chunks(&self, hash: &str) -> Result<Vec<String>> {
+        if !self.exists(hash) {
+            return Ok(Vec::new());
+        }
+        Ok(self
+            .read_manifest(hash)?
+            .map(|manifest| manifest.chunks.into_iter().filter(|chunk| !self.exists(chunk)).collect())
+            .unwrap_or_default())
+    }
+
+    /// List the hashes of every object in `.fai/objects`
+    pub fn list_objects(&self) -> Result<Vec<String>> {
+        let objects_dir = self.root_path.join("objects");
+        let mut hashes = Vec::new();
+        if !objects_dir.exists() {
+            return Ok(hashes);
+        }
+
+        for prefix_entry in fs::read_dir(&objects_dir)? {
+            let prefix_entry = prefix_entry?;
+            if !prefix_entry.file_type()?.is_dir() {
+                continue;
+            }
+            let prefix = prefix_entry.file_name().to_string_lossy().to_string();
+            for object_entry in fs::read_dir(prefix_entry.path())? {
+                let object_entry = object_entry?;
+                hashes.push(format!("{}{}", prefix, object_entry.file_name().to_string_lossy()));
+            }
+        }
+
+        hashes.sort();
+        Ok(hashes)
+    }
+
+    /// Delete an object from storage
+    ///
+    /// # Returns
+    /// Number of bytes freed
+    pub fn remove_object(&self, hash: &str) -> Result<u64> {
+        if hash.len() < 2 {
+            return Err(anyhow!("Invalid hash length"));
+        }
+
+        let object_dir = self.root_path.join("objects").join(&hash[..2]);
+        let object_path = object_dir.join(&hash[2..]);
+        let size = fs::metadata(&object_path)?.len();
+        fs::remove_file(&object_path)?;
+
+        // Drop the fan-out directory once it is empty
+        if fs::read_dir(&object_dir)?.next().is_none() {
+            fs::remove_dir(&object_dir)?;
+        }
+
+        Ok(size)
+    }
+
     /// Chunk file data into smaller pieces
     ///
     /// # Arguments
@@ -550,7 +709,7 @@ impl StorageManager {
                 "SELECT parent_hash FROM commit_parents WHERE commit_hash = ?1 ORDER BY parent_hash"
             )?;
             let parent_rows = parent_stmt.query_map([&commit_hash], |parent_row| {
-                Ok(parent_row.get::<_, String>(0)?)
+                parent_row.get::<_, String>(0)
             })?;
 
             let mut parents = Vec::new();
@@ -601,7 +760,7 @@ impl StorageManager {
                 "SELECT parent_hash FROM commit_parents WHERE commit_hash = ?1 ORDER BY parent_hash"
             )?;
             let parent_rows = parent_stmt.query_map([&commit_hash], |parent_row| {
-                Ok(parent_row.get::<_, String>(0)?)
+                parent_row.get::<_, String>(0)
             })?;
 
             let mut parents = Vec::new();
@@ -693,6 +852,33 @@ mod tests {
         assert_eq!(hash1, hash2);
     }
 
+    #[test]
+    fn test_content_hash_matches_store() {
+        let (storage, _temp_dir) = create_temp_storage();
+        let small = b"Small model".to_vec();
+        let large: Vec<u8> = (0..CHUNK_SIZE * 2 + 10).map(|i| (i % 251) as u8).collect();
+
+        for data in [small, large] {
+            let expected = storage.store(&data).unwrap();
+            assert_eq!(StorageManager::content_hash(&data).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_retrieve_to_streams_chunks() {
+        let (storage, _temp_dir) = create_temp_storage();
+        let small = b"Small model".to_vec();
+        let large: Vec<u8> = (0..CHUNK_SIZE * 2 + 10).map(|i| (i % 251) as u8).collect();
+
+        for data in [small, large] {
+            let hash = storage.store(&data).unwrap();
+            let mut copied = Vec::new();
+            assert_eq!(storage.retrieve_to(&hash, &mut copied).unwrap(), data.len() as u64);
+            assert_eq!(copied, data);
+        }
+        assert!(storage.retrieve_to("nonexistenthash123456789", &mut Vec::new()).is_err());
+    }
+
     #[test]
     fn test_exists() {
         let (storage, _temp_dir) = create_temp_storage();
@@ -725,4 +911,24 @@ mod tests {
         assert!(!storage.exists(""));
         assert!(!storage.exists("a"));
     }
+
+    #[test]
+    fn test_copy_objects_between_stores() {
+        let (source, _source_dir) = create_temp_storage();
+        let (target, _target_dir) = create_temp_storage();
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 10).map(|i| (i % 251) as u8).collect();
+        let hash = source.store(&data).unwrap();
+
+        // Copy the manifest first: all three chunks are then missing
+        target.store_object(&hash, &source.read_object(&hash).unwrap()).unwrap();
+        let missing = target.missing_chunks(&hash).unwrap();
+        assert_eq!(missing.len(), 3);
+
+        assert!(target.store_object(&missing[0], b"tampered").is_err());
+        for chunk in &missing {
+            target.store_object(chunk, &source.read_object(chunk).unwrap()).unwrap();
+        }
+        assert!(target.missing_chunks(&hash).unwrap().is_empty());
+        assert_eq!(target.retrieve(&hash).unwrap(), data);
+    }
 }
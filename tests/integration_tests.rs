@@ -3,10 +3,34 @@
 //! This file contains comprehensive end-to-end tests that verify all major functionality
 //! including repository operations, P2P networking, and data consistency.
 
-use std::process::Command;
+use std::path::Path;
+use std::process::{Command, Output};
 use std::fs;
 use tempfile::TempDir;
 
+/// Build a `fai` command running in `dir`
+fn fai_command(dir: &Path) -> Command {
+    let fai_binary = std::env::current_dir().unwrap().join("target/debug/fai");
+    if !fai_binary.exists() {
+        panic!("FAI binary not found. Run `cargo build` first.");
+    }
+    let mut command = Command::new(fai_binary);
+    command.current_dir(dir);
+    command
+}
+
+/// Run `fai` with `args` in `dir`
+fn fai(dir: &Path, args: &[&str]) -> Output {
+    fai_command(dir).args(args).output().expect("Failed to execute fai command")
+}
+
+/// Run `fai` with `args` in `dir`, assert it succeeded and return its stdout
+fn fai_ok(dir: &Path, args: &[&str]) -> String {
+    let output = fai(dir, args);
+    assert!(output.status.success(), "fai {:?} should succeed: {}", args, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
 /// Test basic repository operations
 #[test]
 fn test_basic_repository_workflow() {
@@ -24,7 +48,7 @@ fn test_basic_repository_workflow() {
 
     // Test 1: Initialize repository
     let init_output = Command::new(&fai_binary)
-        .args(&["init"])
+        .args(["init"])
         .current_dir(repo_path)
         .output()
         .expect("Failed to execute init command");
@@ -38,7 +62,7 @@ fn test_basic_repository_workflow() {
 
     // Test 3: Add the file
     let add_output = Command::new(&fai_binary)
-        .args(&["add", "test.txt"])
+        .args(["add", "test.txt"])
         .current_dir(repo_path)
         .output()
         .expect("Failed to execute add command");
@@ -47,7 +71,7 @@ fn test_basic_repository_workflow() {
 
     // Test 4: Commit the file
     let commit_output = Command::new(&fai_binary)
-        .args(&["commit", "--message", "Integration test commit"])
+        .args(["commit", "--message", "Integration test commit"])
         .current_dir(repo_path)
         .output()
         .expect("Failed to execute commit command");
@@ -56,7 +80,7 @@ fn test_basic_repository_workflow() {
 
     // Test 5: Check status
     let status_output = Command::new(&fai_binary)
-        .args(&["status"])
+        .args(["status"])
         .current_dir(repo_path)
         .output()
         .expect("Failed to execute status command");
@@ -65,7 +89,7 @@ fn test_basic_repository_workflow() {
 
     // Test 6: Check log
     let log_output = Command::new(&fai_binary)
-        .args(&["log"])
+        .args(["log"])
         .current_dir(repo_path)
         .output()
         .expect("Failed to execute log command");
@@ -94,7 +118,7 @@ fn test_data_integrity() {
 
     // Initialize repository
     let init_output = Command::new(&fai_binary)
-        .args(&["init"])
+        .args(["init"])
         .current_dir(repo_path)
         .output()
         .expect("Failed to execute init command");
@@ -108,7 +132,7 @@ fn test_data_integrity() {
 
     // Add the file
     let add_output = Command::new(&fai_binary)
-        .args(&["add", "integrity_test.txt"])
+        .args(["add", "integrity_test.txt"])
         .current_dir(repo_path)
         .output()
         .expect("Failed to execute add command");
@@ -117,7 +141,7 @@ fn test_data_integrity() {
 
     // Commit the file
     let commit_output = Command::new(&fai_binary)
-        .args(&["commit", "--message", "Integrity test commit"])
+        .args(["commit", "--message", "Integrity test commit"])
         .current_dir(repo_path)
         .output()
         .expect("Failed to execute commit command");
@@ -150,7 +174,7 @@ fn test_multiple_file_operations() {
 
     // Initialize repository
     Command::new(&fai_binary)
-        .args(&["init"])
+        .args(["init"])
         .current_dir(repo_path)
         .output()
         .expect("Failed to execute init command");
@@ -168,7 +192,7 @@ fn test_multiple_file_operations() {
 
         // Add each file
         let add_output = Command::new(&fai_binary)
-            .args(&["add", filename])
+            .args(["add", filename])
             .current_dir(repo_path)
             .output()
             .expect("Failed to execute add command");
@@ -178,7 +202,7 @@ fn test_multiple_file_operations() {
 
     // Commit all files
     let commit_output = Command::new(&fai_binary)
-        .args(&["commit", "--message", "Multiple files commit"])
+        .args(["commit", "--message", "Multiple files commit"])
         .current_dir(repo_path)
         .output()
         .expect("Failed to execute commit command");
@@ -187,7 +211,7 @@ fn test_multiple_file_operations() {
 
     // Verify all files are tracked
     let status_output = Command::new(&fai_binary)
-        .args(&["status"])
+        .args(["status"])
         .current_dir(repo_path)
         .output()
         .expect("Failed to execute status command");
@@ -212,7 +236,7 @@ fn test_error_handling() {
 
     // Try to add a file that doesn't exist (should fail gracefully)
     let add_output = Command::new(&fai_binary)
-        .args(&["add", "nonexistent.txt"])
+        .args(["add", "nonexistent.txt"])
         .current_dir(repo_path)
         .output()
         .expect("Failed to execute add command");
@@ -224,7 +248,7 @@ fn test_error_handling() {
 
     // Try to commit without any files (should fail gracefully)
     let commit_output = Command::new("cargo")
-        .args(&["run", "--", "commit", "--message", "Empty commit"])
+        .args(["run", "--", "commit", "--message", "Empty commit"])
         .output()
         .expect("Failed to execute commit command");
 
@@ -251,7 +275,7 @@ fn test_branch_operations() {
 
     // Initialize repository
     Command::new(&fai_binary)
-        .args(&["init"])
+        .args(["init"])
         .current_dir(repo_path)
         .output()
         .expect("Failed to execute init command");
@@ -261,18 +285,18 @@ fn test_branch_operations() {
     fs::write(&test_file, "main branch content").expect("Failed to write test file");
 
     Command::new("cargo")
-        .args(&["run", "--", "add", "main.txt"])
+        .args(["run", "--", "add", "main.txt"])
         .output()
         .expect("Failed to execute add command");
 
     Command::new("cargo")
-        .args(&["run", "--", "commit", "--message", "Initial commit"])
+        .args(["run", "--", "commit", "--message", "Initial commit"])
         .output()
         .expect("Failed to execute commit command");
 
     // Test branch creation (if branch command exists)
     let branch_output = Command::new("cargo")
-        .args(&["run", "--", "branch", "create", "test-branch"])
+        .args(["run", "--", "branch", "create", "test-branch"])
         .output();
 
     // If branch command is not implemented yet, this test will be skipped
@@ -283,4 +307,480 @@ fn test_branch_operations() {
             println!("Branch command not fully implemented yet - skipping detailed branch tests");
         }
     }
-}
\ No newline at end of file
+}
+/// Test that each commit records the full repository snapshot
+#[test]
+fn test_commit_snapshot_inherits_parent() {
+    // Create a temporary directory for testing
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path();
+
+    let commit_hash = |stdout: &str| {
+        stdout
+            .lines()
+            .find_map(|line| line.strip_prefix("Created commit "))
+            .expect("Commit output should contain the new hash")
+            .trim()
+            .to_string()
+    };
+
+    fai_ok(repo_path, &["init"]);
+
+    // First commit contains only the model
+    fs::write(repo_path.join("model.bin"), "model v1").unwrap();
+    fai_ok(repo_path, &["add", "model.bin"]);
+    let first = commit_hash(&fai_ok(repo_path, &["commit", "--message", "Add model"]));
+
+    // Second commit only stages the config
+    fs::write(repo_path.join("config.json"), "{}").unwrap();
+    fai_ok(repo_path, &["add", "config.json"]);
+    let second = commit_hash(&fai_ok(repo_path, &["commit", "--message", "Add config"]));
+
+    // The model must be carried over rather than reported as removed
+    let diff = fai_ok(repo_path, &["diff", &first, &second]);
+    assert!(diff.contains("Added:     1 files"), "Unexpected diff output: {}", diff);
+    assert!(diff.contains("Removed:   0 files"), "Unexpected diff output: {}", diff);
+    assert!(diff.contains("Unchanged: 1 files"), "Unexpected diff output: {}", diff);
+    assert!(diff.contains("+ config.json"), "Unexpected diff output: {}", diff);
+}
+
+/// Test merging branches with a conflicting model file
+#[test]
+fn test_merge_conflict_resolution() {
+    // Create a temporary directory for testing
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path();
+
+    fai_ok(repo_path, &["init"]);
+    fs::write(repo_path.join("model.bin"), "base weights").unwrap();
+    fai_ok(repo_path, &["add", "model.bin"]);
+    fai_ok(repo_path, &["commit", "--message", "Base model"]);
+
+    // Retrain on an experiment branch
+    fai_ok(repo_path, &["branch", "experiment"]);
+    fai_ok(repo_path, &["checkout", "experiment"]);
+    fs::write(repo_path.join("model.bin"), "experiment weights").unwrap();
+    fai_ok(repo_path, &["add", "model.bin"]);
+    fai_ok(repo_path, &["commit", "--message", "Experiment model"]);
+
+    // Retrain differently on main
+    fai_ok(repo_path, &["checkout", "main"]);
+    fs::write(repo_path.join("model.bin"), "main weights").unwrap();
+    fai_ok(repo_path, &["add", "model.bin"]);
+    fai_ok(repo_path, &["commit", "--message", "Main model"]);
+
+    // Both sides changed the same model: merge must stop and report it
+    let conflict = fai(repo_path, &["merge", "experiment"]);
+    assert!(!conflict.status.success(), "Conflicting merge should fail");
+    let stdout = String::from_utf8_lossy(&conflict.stdout);
+    assert!(stdout.contains("CONFLICT (both modified): model.bin"), "Unexpected merge output: {}", stdout);
+
+    // Taking their version records a merge commit
+    let merged = fai_ok(repo_path, &["merge", "experiment", "--theirs"]);
+    assert!(merged.contains("Merged branch 'experiment' into main"), "Unexpected merge output: {}", merged);
+
+    let log = fai_ok(repo_path, &["log"]);
+    assert!(log.contains("Merge branch 'experiment' into main"), "Unexpected log output: {}", log);
+}
+
+/// Test undoing commits with revert and hard reset
+#[test]
+fn test_reset_and_revert() {
+    // Create a temporary directory for testing
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path();
+
+    fai_ok(repo_path, &["init"]);
+    fs::write(repo_path.join("model.bin"), "good weights").unwrap();
+    fai_ok(repo_path, &["add", "model.bin"]);
+    fai_ok(repo_path, &["commit", "--message", "Good model"]);
+
+    fs::write(repo_path.join("model.bin"), "bad weights").unwrap();
+    fs::write(repo_path.join("notes.txt"), "bad run").unwrap();
+    fai_ok(repo_path, &["add", "model.bin"]);
+    fai_ok(repo_path, &["add", "notes.txt"]);
+    fai_ok(repo_path, &["commit", "--message", "Bad model"]);
+
+    // Revert records a new commit restoring the previous model
+    let reverted = fai_ok(repo_path, &["revert", "HEAD"]);
+    assert!(reverted.contains("Created revert commit"), "Unexpected revert output: {}", reverted);
+    assert_eq!(fs::read_to_string(repo_path.join("model.bin")).unwrap(), "good weights");
+    assert!(!repo_path.join("notes.txt").exists());
+    let log = fai_ok(repo_path, &["log"]);
+    assert!(log.contains("Revert \"Bad model\""), "Unexpected log output: {}", log);
+
+    // Hard reset drops both commits and restores the working tree
+    let reset = fai_ok(repo_path, &["reset", "--hard", "HEAD~2"]);
+    assert!(reset.contains("HEAD is now at"), "Unexpected reset output: {}", reset);
+    assert_eq!(fs::read_to_string(repo_path.join("model.bin")).unwrap(), "good weights");
+    let log = fai_ok(repo_path, &["log"]);
+    assert!(log.contains("Good model"));
+}
+
+/// Test creating, listing, resolving and deleting tags
+#[test]
+fn test_tag_operations() {
+    // Create a temporary directory for testing
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path();
+
+    fai_ok(repo_path, &["init"]);
+    fs::write(repo_path.join("model.bin"), "v1 weights").unwrap();
+    fai_ok(repo_path, &["add", "model.bin"]);
+    fai_ok(repo_path, &["commit", "--message", "First release"]);
+    fs::write(repo_path.join("model.bin"), "v2 weights").unwrap();
+    fai_ok(repo_path, &["add", "model.bin"]);
+    fai_ok(repo_path, &["commit", "--message", "Second release"]);
+
+    let created = fai_ok(repo_path, &["tag", "v1.0", "HEAD~1"]);
+    assert!(created.contains("Created lightweight tag 'v1.0'"), "Unexpected tag output: {}", created);
+    let created = fai_ok(repo_path, &["tag", "v2.0", "-m", "Production model"]);
+    assert!(created.contains("Created annotated tag 'v2.0'"), "Unexpected tag output: {}", created);
+
+    let listed = fai_ok(repo_path, &["tag", "-l"]);
+    assert!(listed.contains("v1.0"));
+    assert!(listed.contains("Production model"), "Unexpected tag list: {}", listed);
+
+    // Tags work as revisions
+    fai_ok(repo_path, &["reset", "--hard", "v1.0"]);
+    assert_eq!(fs::read_to_string(repo_path.join("model.bin")).unwrap(), "v1 weights");
+
+    fai_ok(repo_path, &["tag", "-d", "v1.0"]);
+    assert!(!fai_ok(repo_path, &["tag", "-l"]).contains("v1.0"));
+}
+
+/// Test recovering an amended commit through the reflog
+#[test]
+fn test_reflog_recovers_amended_commit() {
+    // Create a temporary directory for testing
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path();
+
+    fai_ok(repo_path, &["init"]);
+    fs::write(repo_path.join("model.bin"), "weights").unwrap();
+    fai_ok(repo_path, &["add", "model.bin"]);
+    fai_ok(repo_path, &["commit", "--message", "Original message"]);
+    fai_ok(repo_path, &["commit-amend", "--message", "Amended message"]);
+
+    let reflog = fai_ok(repo_path, &["reflog"]);
+    assert!(reflog.contains("HEAD@{0}: commit (amend): Amended message"), "Unexpected reflog: {}", reflog);
+    assert!(reflog.contains("HEAD@{1}: commit: Original message"), "Unexpected reflog: {}", reflog);
+
+    // The pre-amend commit is still reachable through the reflog
+    let original = reflog.lines().nth(1).unwrap()[..8].to_string();
+    let reset = fai_ok(repo_path, &["reset", "HEAD@{1}"]);
+    assert!(reset.contains(&format!("HEAD is now at {}", original)), "Unexpected reset output: {}", reset);
+
+    // GC keeps everything the reflog still refers to
+    let gc = fai_ok(repo_path, &["gc"]);
+    assert!(gc.contains("Removed 0 unreachable commit(s)"), "Unexpected gc output: {}", gc);
+}
+
+/// Test branch deletion safety checks and renaming
+#[test]
+fn test_branch_delete_safety_and_rename() {
+    // Create a temporary directory for testing
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path();
+
+    fai_ok(repo_path, &["init"]);
+    fs::write(repo_path.join("model.bin"), "base").unwrap();
+    fai_ok(repo_path, &["add", "model.bin"]);
+    fai_ok(repo_path, &["commit", "--message", "Base"]);
+
+    // Names that can't be used in a ref are refused
+    for name in ["a b", "HEAD", "../x"] {
+        assert!(!fai(repo_path, &["branch", name]).status.success(), "{}", name);
+    }
+
+    fai_ok(repo_path, &["branch", "experiment"]);
+    fai_ok(repo_path, &["checkout", "experiment"]);
+    fs::write(repo_path.join("model.bin"), "experiment").unwrap();
+    fai_ok(repo_path, &["add", "model.bin"]);
+    fai_ok(repo_path, &["commit", "--message", "Experiment"]);
+
+    // The current branch and unmerged branches are refused
+    assert!(!fai(repo_path, &["branch", "--delete", "experiment"]).status.success());
+    fai_ok(repo_path, &["checkout", "main"]);
+    let refused = fai(repo_path, &["branch", "--delete", "experiment"]);
+    assert!(!refused.status.success());
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("not fully merged"));
+
+    // Renaming keeps the commits and is reflected in the listing
+    fai_ok(repo_path, &["branch", "-m", "experiment", "archived"]);
+    let branches = fai_ok(repo_path, &["branch", "--list"]);
+    assert!(branches.contains("archived") && !branches.contains("experiment"), "Unexpected branches: {}", branches);
+    fai_ok(repo_path, &["branch", "--delete", "--force", "archived"]);
+
+    // Protected branches survive even forced deletion
+    fs::write(repo_path.join(".fai/config.toml"), "[branches]\nprotected = [\"release/*\"]\n").unwrap();
+    fai_ok(repo_path, &["branch", "release/1.0"]);
+    assert!(!fai(repo_path, &["branch", "--delete", "--force", "release/1.0"]).status.success());
+
+    // Renaming the current branch moves HEAD with it
+    fai_ok(repo_path, &["branch", "-m", "main", "trunk"]);
+    assert_eq!(fs::read_to_string(repo_path.join(".fai/HEAD")).unwrap(), "ref: refs/heads/trunk");
+    fs::write(repo_path.join("model.bin"), "next").unwrap();
+    fai_ok(repo_path, &["add", "model.bin"]);
+    fai_ok(repo_path, &["commit", "--message", "On trunk"]);
+    let branches = fai_ok(repo_path, &["branch", "-v"]);
+    assert!(branches.contains("* trunk") && !branches.contains("main"), "Unexpected branches: {}", branches);
+}
+
+/// Test stashing staged and modified files across a branch switch
+#[test]
+fn test_stash_push_pop() {
+    // Create a temporary directory for testing
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path();
+
+    fai_ok(repo_path, &["init"]);
+    fs::write(repo_path.join("model.bin"), "committed").unwrap();
+    fai_ok(repo_path, &["add", "model.bin"]);
+    fai_ok(repo_path, &["commit", "--message", "Base"]);
+
+    // Stage a checkpoint and edit the tracked model without staging it
+    fs::write(repo_path.join("checkpoint.bin"), "checkpoint").unwrap();
+    fai_ok(repo_path, &["add", "checkpoint.bin"]);
+    fs::write(repo_path.join("model.bin"), "work in progress").unwrap();
+
+    fai_ok(repo_path, &["stash", "push", "-m", "half-trained"]);
+    assert_eq!(fs::read_to_string(repo_path.join("model.bin")).unwrap(), "committed");
+    assert!(fai_ok(repo_path, &["status"]).contains("No changes staged for commit"));
+    assert!(fai_ok(repo_path, &["stash", "list"]).contains("stash@{0}: On main: half-trained"));
+
+    let popped = fai_ok(repo_path, &["stash", "pop"]);
+    assert!(popped.contains("staged: checkpoint.bin"), "Unexpected pop output: {}", popped);
+    assert_eq!(fs::read_to_string(repo_path.join("model.bin")).unwrap(), "work in progress");
+    assert!(fai_ok(repo_path, &["status"]).contains("checkpoint.bin"));
+    assert!(!fai_ok(repo_path, &["stash", "list"]).contains("stash@{0}"));
+}
+
+/// Test upstream tracking against a remote-tracking ref
+#[test]
+fn test_upstream_tracking() {
+    // Create a temporary directory for testing
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path();
+
+    fai_ok(repo_path, &["init"]);
+    fs::write(repo_path.join("model.bin"), "v1").unwrap();
+    fai_ok(repo_path, &["add", "model.bin"]);
+    fai_ok(repo_path, &["commit", "--message", "First"]);
+
+    // Nothing has been pulled yet, so there is nothing to track
+    assert!(fai_ok(repo_path, &["branch", "-r"]).contains("No remote-tracking branches"));
+    assert!(!fai(repo_path, &["branch", "-u", "origin/main"]).status.success());
+
+    // Record origin/main as a pull would, then commit on top of it locally
+    let db = fai_protocol::database::DatabaseManager::new(&repo_path.join(".fai/db.sqlite")).unwrap();
+    let first = db.get_branch_head("main").unwrap().unwrap();
+    db.update_remote_ref("origin", "main", &first, "pull: from origin").unwrap();
+    fs::write(repo_path.join("model.bin"), "v2").unwrap();
+    fai_ok(repo_path, &["add", "model.bin"]);
+    fai_ok(repo_path, &["commit", "--message", "Second"]);
+
+    assert!(fai_ok(repo_path, &["branch", "-r"]).contains(&format!("origin/main {}", &first[..8])));
+    assert!(fai_ok(repo_path, &["branch", "-u", "origin/main"]).contains("set up to track 'origin/main'"));
+    let status = fai_ok(repo_path, &["status"]);
+    assert!(status.contains("Your branch is ahead 1 relative to 'origin/main'"), "Unexpected status: {}", status);
+    assert!(fai_ok(repo_path, &["branch", "-v"]).contains("[origin/main: ahead 1]"));
+
+    // The upstream follows a rename and can be removed
+    fai_ok(repo_path, &["branch", "-m", "main", "trunk"]);
+    assert!(fai_ok(repo_path, &["status"]).contains("relative to 'origin/main'"));
+    fai_ok(repo_path, &["branch", "--unset-upstream"]);
+    assert!(!fai_ok(repo_path, &["status"]).contains("origin/main"));
+}
+
+/// Test that the node identity survives restarts and can be encrypted
+#[test]
+fn test_persistent_identity() {
+    // Create a temporary directory for testing
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path();
+
+    let fai = |args: &[&str], passphrase: Option<&str>| {
+        let mut command = fai_command(repo_path);
+        command.args(args).env_remove("FAI_IDENTITY_PASSPHRASE");
+        if let Some(passphrase) = passphrase {
+            command.env("FAI_IDENTITY_PASSPHRASE", passphrase);
+        }
+        command.output().expect("Failed to execute fai command")
+    };
+    let run = |args: &[&str], passphrase: Option<&str>| {
+        let output = fai(args, passphrase);
+        assert!(output.status.success(), "fai {:?} should succeed: {}", args, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+
+    run(&["init"], None);
+    let peer_id = run(&["id"], None);
+    assert!(peer_id.starts_with("12D3KooW"), "Unexpected peer ID: {}", peer_id);
+    assert_eq!(run(&["id"], None), peer_id);
+
+    // Encrypted identities need the passphrase but keep the same peer ID
+    assert!(!fai(&["id", "--encrypt"], None).status.success());
+    run(&["id", "--encrypt"], Some("secret"));
+    assert!(!fai(&["id"], None).status.success());
+    assert!(!fai(&["id"], Some("wrong")).status.success());
+    assert_eq!(run(&["id"], Some("secret")), peer_id);
+
+    run(&["id", "--decrypt"], Some("secret"));
+    assert_eq!(run(&["id"], None), peer_id);
+}
+
+/// Test adding, listing and removing named remotes
+#[test]
+fn test_named_remotes() {
+    // Create a temporary directory for testing
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path();
+
+    fai_ok(repo_path, &["init"]);
+    fs::write(repo_path.join("model.bin"), "v1").unwrap();
+    fai_ok(repo_path, &["add", "model.bin"]);
+    fai_ok(repo_path, &["commit", "--message", "First"]);
+
+    let peer_id = libp2p::PeerId::random().to_string();
+    fai_ok(repo_path, &["remote", "add", "origin", &peer_id, "--addr", "/ip4/10.0.0.2/tcp/4001", "--addr", "/ip4/192.168.1.5/tcp/4001"]);
+    assert!(!fai(repo_path, &["remote", "add", "origin", &peer_id]).status.success());
+    assert!(!fai(repo_path, &["remote", "add", "backup", "not-a-peer-id"]).status.success());
+    assert!(!fai(repo_path, &["remote", "add", "backup", &peer_id, "--addr", "10.0.0.2:4001"]).status.success());
+
+    assert_eq!(fai_ok(repo_path, &["remote"]).trim(), "origin");
+    let verbose = fai_ok(repo_path, &["remote", "list", "-v"]);
+    assert!(verbose.contains(&format!("origin\t{}", peer_id)));
+    assert!(verbose.contains("/ip4/192.168.1.5/tcp/4001"));
+
+    // Removing a remote drops its remote-tracking branches and upstreams
+    let db = fai_protocol::database::DatabaseManager::new(&repo_path.join(".fai/db.sqlite")).unwrap();
+    let head = db.get_branch_head("main").unwrap().unwrap();
+    db.update_remote_ref("origin", "main", &head, "pull: from origin").unwrap();
+    fai_ok(repo_path, &["branch", "-u", "origin/main"]);
+
+    assert!(fai_ok(repo_path, &["remote", "remove", "origin"]).contains("1 remote-tracking branches deleted"));
+    assert!(fai_ok(repo_path, &["remote"]).trim().is_empty());
+    assert!(fai_ok(repo_path, &["branch", "-r"]).contains("No remote-tracking branches"));
+    assert!(!fai_ok(repo_path, &["status"]).contains("origin/main"));
+    assert!(!fai(repo_path, &["remote", "remove", "origin"]).status.success());
+}
+
+/// Test discarding an interrupted pull with `fai pull --abort`
+#[test]
+fn test_pull_abort() {
+    // Create a temporary directory for testing
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path();
+
+    fai_ok(repo_path, &["init"]);
+    fs::write(repo_path.join("model.bin"), "v1").unwrap();
+    fai_ok(repo_path, &["add", "model.bin"]);
+    fai_ok(repo_path, &["commit", "--message", "First"]);
+    assert!(!fai(repo_path, &["pull", "--abort"]).status.success());
+    assert!(!fai(repo_path, &["pull"]).status.success());
+
+    // Simulate a pull that died after receiving a commit and one of its objects
+    let db = fai_protocol::database::DatabaseManager::new(&repo_path.join(".fai/db.sqlite")).unwrap();
+    let storage = fai_protocol::storage::StorageManager::new(repo_path.join(".fai")).unwrap();
+    let head = db.get_branch_head("main").unwrap().unwrap();
+    db.begin_transfer("origin", &libp2p::PeerId::random().to_string()).unwrap();
+    let object = storage.store(b"v2 weights").unwrap();
+    db.create_commit("remote-commit", "Second", &[head], &[("model.bin".to_string(), object.clone(), 10)], false)
+        .unwrap();
+    db.update_remote_ref("origin", "main", "remote-commit", "pull: from origin").unwrap();
+    db.add_to_transfer(Some("remote-commit"), &["remote-commit".to_string()], std::slice::from_ref(&object))
+        .unwrap();
+    db.record_transfer_object(&object).unwrap();
+
+    // An object the pull received that the staging area also refers to
+    fs::write(repo_path.join("notes.txt"), "staged notes").unwrap();
+    fai_ok(repo_path, &["add", "notes.txt"]);
+    let staged = storage.store(b"staged notes").unwrap();
+    db.record_transfer_object(&staged).unwrap();
+
+    let output = fai_ok(repo_path, &["pull", "--abort"]);
+    assert!(output.contains("discarded 1 commit(s) and 1 object(s)"), "{}", output);
+    assert!(!storage.exists(&object));
+    assert!(storage.exists(&staged));
+    assert!(fai_ok(repo_path, &["branch", "-r"]).contains("No remote-tracking branches"));
+    assert!(fai_ok(repo_path, &["log"]).contains("First"));
+    assert!(!fai(repo_path, &["pull", "--abort"]).status.success());
+}
+
+/// Test that a clone checks out the peer's branch without leaving a stray `main`
+#[test]
+fn test_clone_default_branch() {
+    use std::io::BufRead;
+
+    // Create a temporary directory for testing
+    let temp_dir = TempDir::new().unwrap();
+    let origin_path = temp_dir.path().join("origin");
+    let clone_path = temp_dir.path().join("clone");
+    fs::create_dir_all(&origin_path).unwrap();
+
+    fai_ok(&origin_path, &["init"]);
+    fs::write(origin_path.join("model.bin"), "weights").unwrap();
+    fai_ok(&origin_path, &["add", "model.bin"]);
+    fai_ok(&origin_path, &["commit", "--message", "First"]);
+    fai_ok(&origin_path, &["branch", "-m", "main", "trunk"]);
+    let peer_id = fai_ok(&origin_path, &["id"]).trim().to_string();
+
+    let mut server = fai_command(&origin_path)
+        .args(["serve", "--listen", "/ip4/127.0.0.1/tcp/0"])
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to start the server");
+    let mut lines = std::io::BufReader::new(server.stdout.take().unwrap()).lines();
+    let listen_addr = lines
+        .by_ref()
+        .map_while(Result::ok)
+        .find_map(|line| line.strip_prefix("Listening on ").map(str::to_string))
+        .expect("Server should print its listen address");
+    // Keep draining the server output so it never blocks on a full pipe
+    std::thread::spawn(move || lines.for_each(drop));
+
+    let output = fai(temp_dir.path(), &["clone", &format!("{}/p2p/{}", listen_addr, peer_id), "clone"]);
+    server.kill().unwrap();
+    server.wait().unwrap();
+    assert!(output.status.success(), "Clone should succeed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let branches = fai_ok(&clone_path, &["branch", "--list"]);
+    assert!(branches.contains("* trunk") && !branches.contains("main"), "Unexpected branches: {}", branches);
+    assert!(fai_ok(&clone_path, &["log"]).contains("First"));
+}
+
+/// Test starting, querying and stopping `fai daemon`
+#[cfg(unix)]
+#[test]
+fn test_daemon_control() {
+    // Create a temporary directory for testing
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path();
+
+    fai_ok(repo_path, &["init"]);
+    assert!(fai_ok(repo_path, &["daemon", "status"]).contains("No daemon is running"));
+
+    let mut daemon = fai_command(repo_path)
+        .args(["daemon", "--listen", "/ip4/127.0.0.1/tcp/0"])
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .expect("Failed to start the daemon");
+    let socket = repo_path.join(".fai/daemon.sock");
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    while !socket.exists() && std::time::Instant::now() < deadline {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    assert!(socket.exists(), "Daemon should create its socket");
+
+    let status = fai_ok(repo_path, &["daemon", "status"]);
+    assert!(status.contains("Daemon running as"), "Unexpected status: {}", status);
+    assert!(status.contains("/ip4/127.0.0.1/tcp/"));
+    assert!(status.contains("Idle"));
+    assert!(fai_ok(repo_path, &["peers"]).contains("Asking the daemon for peers"));
+
+    assert!(fai_ok(repo_path, &["daemon", "stop"]).contains("Stopped the daemon"));
+    assert!(daemon.wait().unwrap().success());
+    assert!(!socket.exists(), "Daemon should remove its socket");
+    assert!(fai_ok(repo_path, &["daemon", "stop"]).contains("No daemon is running"));
+}